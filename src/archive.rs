@@ -0,0 +1,146 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use body::{BodySource, OpenBody};
+use pieces::{Piece, Pieces};
+
+const BLOCK: u64 = 512;
+
+struct Entry {
+    // Relative to the archived directory, with `/` between components.
+    name: String,
+    path: PathBuf,
+    dir: bool,
+    size: u64,
+    modified: u64,
+}
+
+fn padding(size: u64) -> u64 {
+    (BLOCK - size % BLOCK) % BLOCK
+}
+
+// Everything below dir, parents before their children and in name order, so the
+// same directory always makes the same archive.
+fn walk(dir: &Path, prefix: &str, entries: &mut Vec<Entry>) -> io::Result<()> {
+    let mut children = vec![];
+    for child in try!(fs::read_dir(dir)) {
+        children.push(try!(child).path());
+    }
+    children.sort();
+    for path in children {
+        let metadata = try!(fs::metadata(&path));
+        let file_name = path.file_name().map(|n| n.to_string_lossy().into_owned());
+        let name = format!("{}{}", prefix, file_name.unwrap_or_else(String::new));
+        let modified = metadata.modified()
+                               .ok()
+                               .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+                               .map(|d| d.as_secs())
+                               .unwrap_or(0);
+        if metadata.is_dir() {
+            entries.push(Entry {
+                name: format!("{}/", name),
+                path: path.clone(),
+                dir: true,
+                size: 0,
+                modified: modified,
+            });
+            try!(walk(&path, &format!("{}/", name), entries));
+        } else if metadata.is_file() {
+            entries.push(Entry {
+                name: name,
+                path: path,
+                dir: false,
+                size: metadata.len(),
+                modified: modified,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+}
+
+// A ustar header. Names too long for it are split between its prefix and name
+// fields at a `/`, as long as that's enough.
+fn header(entry: &Entry) -> io::Result<Vec<u8>> {
+    let mut header = vec![0u8; BLOCK as usize];
+    let name = entry.name.as_bytes();
+    let (prefix, name) = if name.len() <= 100 {
+        (&name[..0], name)
+    } else {
+        let split = name.iter()
+                        .enumerate()
+                        .filter(|&(i, &c)| c == b'/' && i <= 155 && name.len() - i - 1 <= 100)
+                        .map(|(i, _)| i)
+                        .next();
+        match split {
+            Some(i) => (&name[..i], &name[i + 1..]),
+            None => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          format!("{} is too long for a tar archive",
+                                                  entry.name)))
+            }
+        }
+    };
+    header[..name.len()].copy_from_slice(name);
+    octal(&mut header[100..108], if entry.dir { 0o755 } else { 0o644 });
+    octal(&mut header[108..116], 0);
+    octal(&mut header[116..124], 0);
+    octal(&mut header[124..136], entry.size);
+    octal(&mut header[136..148], entry.modified);
+    header[156] = if entry.dir { b'5' } else { b'0' };
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix);
+    // The checksum is summed with its own field taken as spaces.
+    for byte in &mut header[148..156] {
+        *byte = b' ';
+    }
+    let checksum = header.iter().map(|&b| b as u64).sum::<u64>();
+    octal(&mut header[148..155], checksum);
+    Ok(header)
+}
+
+/// A directory as a tar archive, made on the fly while it's sent so there's no
+/// temporary file, for deploy endpoints and such that take archives. Only
+/// directories and regular files go in (symlinks are followed), named relative to
+/// the directory. See Request::tar_directory.
+#[derive(Clone, Debug)]
+pub struct TarDirectory {
+    dir: PathBuf,
+}
+
+impl TarDirectory {
+    pub fn new(dir: &Path) -> TarDirectory {
+        TarDirectory { dir: dir.to_path_buf() }
+    }
+}
+
+impl BodySource for TarDirectory {
+    fn open(&self) -> io::Result<OpenBody> {
+        let mut entries = vec![];
+        try!(walk(&self.dir, "", &mut entries));
+        let mut pieces = VecDeque::new();
+        for entry in entries {
+            pieces.push_back(Piece::bytes(try!(header(&entry))));
+            if !entry.dir {
+                pieces.push_back(Piece::File(entry.path, None, entry.size));
+                pieces.push_back(Piece::Zeros(padding(entry.size)));
+            }
+        }
+        // Two empty blocks end the archive.
+        pieces.push_back(Piece::Zeros(2 * BLOCK));
+        let reader = Pieces(pieces);
+        let length = reader.len();
+        Ok(OpenBody {
+            reader: Box::new(reader),
+            length: Some(length),
+        })
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use hyper::header::ContentLength;
+use serde_json::{self, Value};
+use url::Url;
+
+use super::{CursResult, PreparedRequest, Response};
+
+/// What an AuditSink hears about each request a Client sends.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditRecord {
+    /// When it started, as time since the unix epoch on the Client's clock.
+    pub started_at: Duration,
+    pub method: String,
+    pub host: String,
+    /// Without the query, which may carry secrets.
+    pub path: String,
+    /// None when there was no response at all, see error.
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    /// Until the response headers arrived. Reading the body is up to the caller.
+    pub duration: Duration,
+    pub request_bytes: u64,
+    /// As the Content-Length header says, if it does.
+    pub response_bytes: Option<u64>,
+    /// Whatever the request was tagged with, see Request::audit_tag.
+    pub tags: Vec<(String, String)>,
+}
+
+impl AuditRecord {
+    /// A record of a request that hasn't been answered yet.
+    pub fn new(prepared: &PreparedRequest, started_at: Duration) -> AuditRecord {
+        let url = Url::parse(&prepared.url).ok();
+        AuditRecord {
+            started_at: started_at,
+            method: prepared.method.to_string(),
+            host: url.as_ref().and_then(|u| u.serialize_host()).unwrap_or_else(String::new),
+            path: url.as_ref().and_then(|u| u.serialize_path()).unwrap_or_else(String::new),
+            status: None,
+            error: None,
+            duration: Duration::new(0, 0),
+            request_bytes: prepared.body_len() as u64,
+            response_bytes: None,
+            tags: prepared.audit_tags.clone(),
+        }
+    }
+
+    /// Fills in how it went.
+    pub fn finish(&mut self, result: &CursResult<Response>, duration: Duration) {
+        self.duration = duration;
+        match *result {
+            Ok(ref response) => {
+                self.status = Some(response.status.to_u16());
+                self.response_bytes = response.headers.get::<ContentLength>().map(|l| l.0);
+            }
+            Err(ref error) => self.error = Some(format!("{:?}", error)),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let millis = |d: Duration| d.as_secs() * 1000 + (d.subsec_nanos() / 1_000_000) as u64;
+        let optional = |value: Option<Value>| value.unwrap_or(Value::Null);
+        let tags = self.tags
+                       .iter()
+                       .map(|&(ref name, ref value)| (name.clone(), Value::String(value.clone())))
+                       .collect();
+        let mut map = BTreeMap::new();
+        map.insert("started_at_ms".to_string(), Value::U64(millis(self.started_at)));
+        map.insert("method".to_string(), Value::String(self.method.clone()));
+        map.insert("host".to_string(), Value::String(self.host.clone()));
+        map.insert("path".to_string(), Value::String(self.path.clone()));
+        map.insert("status".to_string(),
+                   optional(self.status.map(|s| Value::U64(s as u64))));
+        map.insert("error".to_string(), optional(self.error.clone().map(Value::String)));
+        map.insert("duration_ms".to_string(), Value::U64(millis(self.duration)));
+        map.insert("request_bytes".to_string(), Value::U64(self.request_bytes));
+        map.insert("response_bytes".to_string(),
+                   optional(self.response_bytes.map(Value::U64)));
+        map.insert("tags".to_string(), Value::Object(tags));
+        Value::Object(map)
+    }
+}
+
+/// Where a Client's audit records go, see Client::audit.
+pub trait AuditSink {
+    fn record(&self, record: &AuditRecord);
+}
+
+impl<F> AuditSink for F
+    where F: Fn(&AuditRecord)
+{
+    fn record(&self, record: &AuditRecord) {
+        self(record)
+    }
+}
+
+/// Writes each record as a line of JSON, to a file or anything else.
+pub struct JsonLinesAuditSink<W: Write> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write> JsonLinesAuditSink<W> {
+    pub fn new(writer: W) -> JsonLinesAuditSink<W> {
+        JsonLinesAuditSink { writer: Mutex::new(writer) }
+    }
+}
+
+impl<W: Write> AuditSink for JsonLinesAuditSink<W> {
+    /// Failing to write a record doesn't fail the request.
+    fn record(&self, record: &AuditRecord) {
+        if let Ok(line) = serde_json::to_string(&record.to_json()) {
+            let mut writer = self.writer.lock().unwrap();
+            let _ = writer.write_all(line.as_bytes()).and_then(|_| writer.write_all(b"\n"));
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::env;
+
+use rustc_serialize::base64::{STANDARD, ToBase64};
+
+use secret::Secret;
+
+/// What the server (or proxy) said when it asked for credentials.
+#[derive(Clone, Debug)]
+pub struct Challenge<'a> {
+    pub url: &'a str,
+    /// True for 407 Proxy Authentication Required, false for 401 Unauthorized.
+    pub proxy: bool,
+    /// The WWW-Authenticate (or Proxy-Authenticate) header, if there was one.
+    pub authenticate: Option<String>,
+}
+
+/// A username and password to retry a request with, using basic auth.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: Option<Secret>,
+}
+
+impl Credentials {
+    /// The value of an Authorization or Proxy-Authorization header sending them.
+    pub fn basic(&self) -> Secret {
+        let password = self.password.as_ref().map(|p| &**p).unwrap_or("");
+        let pair = Secret::new(format!("{}:{}", self.username, password));
+        Secret::new(format!("Basic {}", pair.as_bytes().to_base64(STANDARD)))
+    }
+}
+
+/// Asked for credentials when a request gets a 401, or a proxy answers a CONNECT
+/// with a 407. The request, or the CONNECT, is retried once with whatever it
+/// returns. Return None to give up and get the original response.
+/// Implement it to prompt the user, or to call a secrets manager.
+pub trait CredentialProvider {
+    fn credentials(&self, challenge: &Challenge) -> Option<Credentials>;
+}
+
+/// Reads credentials from a pair of environment variables.
+#[derive(Clone, Debug)]
+pub struct EnvCredentials {
+    pub username_var: String,
+    pub password_var: String,
+}
+
+impl EnvCredentials {
+    pub fn new(username_var: &str, password_var: &str) -> EnvCredentials {
+        EnvCredentials {
+            username_var: username_var.to_string(),
+            password_var: password_var.to_string(),
+        }
+    }
+}
+
+impl CredentialProvider for EnvCredentials {
+    fn credentials(&self, _: &Challenge) -> Option<Credentials> {
+        env::var(&self.username_var).ok().map(|username| {
+            Credentials {
+                username: username,
+                password: env::var(&self.password_var).ok().map(Secret::from),
+            }
+        })
+    }
+}
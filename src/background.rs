@@ -0,0 +1,102 @@
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{Receiver, SyncSender, sync_channel};
+use std::thread::{self, JoinHandle};
+
+use super::{CursResult, Response};
+use client::Client;
+
+type Job = Box<FnMut(&Client) + Send>;
+
+/// Sends requests from a few worker threads so callers don't wait for them,
+/// like telemetry or analytics going out. Dropping the queue sends whatever is
+/// still queued and waits for the workers to finish.
+///
+/// ```no_run
+/// # use curs::{BackgroundQueue, Client};
+/// let queue = BackgroundQueue::new(Client::new(), 2, 100);
+/// queue.enqueue(|client| client.post("http://example.com/events").send(),
+///               |result| if result.is_err() { println!("Event lost") });
+/// ```
+pub struct BackgroundQueue {
+    client: Arc<Client>,
+    sender: Option<SyncSender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl BackgroundQueue {
+    /// Starts `workers` threads sharing the client. At most `capacity` requests
+    /// can wait to be sent.
+    pub fn new(client: Client, workers: usize, capacity: usize) -> BackgroundQueue {
+        let client = Arc::new(client);
+        let (sender, receiver) = sync_channel(capacity);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..workers)
+                          .map(|_| {
+                              let client = client.clone();
+                              let receiver = receiver.clone();
+                              thread::spawn(move || work(&client, &receiver))
+                          })
+                          .collect();
+        BackgroundQueue {
+            client: client,
+            sender: Some(sender),
+            workers: workers,
+        }
+    }
+
+    /// The client requests go out with.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Queue a request: `send` builds and sends it with the shared client,
+    /// `callback` gets the result on the worker thread.
+    /// Returns false without queueing anything when the queue is full.
+    pub fn enqueue<S, C>(&self, send: S, callback: C) -> bool
+        where S: FnOnce(&Client) -> CursResult<Response> + Send + 'static,
+              C: FnOnce(CursResult<Response>) + Send + 'static
+    {
+        let mut pending = Some((send, callback));
+        let job: Job = Box::new(move |client| {
+            if let Some((send, callback)) = pending.take() {
+                callback(send(client));
+            }
+        });
+        match self.sender {
+            Some(ref sender) => sender.try_send(job).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Stop taking requests, send the queued ones and wait for the workers.
+    pub fn shutdown(mut self) {
+        self.finish();
+    }
+
+    fn finish(&mut self) {
+        // Workers stop once the channel is closed and empty.
+        self.sender.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for BackgroundQueue {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+fn work(client: &Client, receiver: &Mutex<Receiver<Job>>) {
+    loop {
+        let job = match receiver.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(_) => return,
+        };
+        match job {
+            Ok(mut job) => job(client),
+            Err(_) => return,
+        }
+    }
+}
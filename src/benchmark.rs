@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::Duration;
+
+use super::{CursResult, PreparedRequest, Request, Response};
+use client::Client;
+use clock::{self, Clock, SystemClock};
+
+/// How a request did when sent over and over, see Request::benchmark.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Benchmark {
+    /// How many were sent, failed ones included.
+    pub requests: usize,
+    /// The ones that failed, like those that couldn't connect.
+    pub errors: usize,
+    /// Latencies of the ones that got a response, until the whole body was read.
+    pub min: Duration,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+    /// From the first request going out to the last response coming in.
+    pub elapsed: Duration,
+    /// How many responses came with each status code.
+    pub statuses: BTreeMap<u16, usize>,
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9
+}
+
+fn from_seconds(seconds: f64) -> Duration {
+    Duration::new(seconds as u64, ((seconds - seconds.trunc()) * 1e9) as u32)
+}
+
+// The latency this fraction of them took at most, going by the nearest rank.
+fn percentile(sorted: &[Duration], fraction: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::new(0, 0);
+    }
+    let rank = (fraction * sorted.len() as f64).ceil() as usize;
+    sorted[rank.max(1).min(sorted.len()) - 1]
+}
+
+impl Benchmark {
+    pub fn new(latencies: &[Duration],
+               errors: usize,
+               statuses: BTreeMap<u16, usize>,
+               elapsed: Duration)
+               -> Benchmark {
+        let mut sorted = latencies.to_vec();
+        sorted.sort();
+        let total = sorted.iter().map(|latency| seconds(*latency)).sum::<f64>();
+        let mean = if sorted.is_empty() {
+            Duration::new(0, 0)
+        } else {
+            from_seconds(total / sorted.len() as f64)
+        };
+        Benchmark {
+            requests: sorted.len() + errors,
+            errors: errors,
+            min: sorted.first().cloned().unwrap_or(Duration::new(0, 0)),
+            mean: mean,
+            p50: percentile(&sorted, 0.5),
+            p95: percentile(&sorted, 0.95),
+            p99: percentile(&sorted, 0.99),
+            max: sorted.last().cloned().unwrap_or(Duration::new(0, 0)),
+            elapsed: elapsed,
+            statuses: statuses,
+        }
+    }
+
+    /// Requests sent per second, failed ones included.
+    pub fn throughput(&self) -> f64 {
+        let elapsed = seconds(self.elapsed);
+        if elapsed == 0.0 {
+            0.0
+        } else {
+            self.requests as f64 / elapsed
+        }
+    }
+}
+
+// Sends and reads the whole body, so the latency counts it and the connection can
+// be used again.
+pub fn timed(client: &Client, request: &PreparedRequest) -> (Duration, CursResult<Response>) {
+    let started = SystemClock.now();
+    let result = client.send_prepared(request.reborrow()).and_then(|mut response| {
+        try!(io::copy(&mut response, &mut io::sink()));
+        Ok(response)
+    });
+    (clock::since(&SystemClock, started), result)
+}
+
+/// See Request::benchmark.
+pub fn run(request: &Request, count: usize, concurrency: usize) -> CursResult<Benchmark> {
+    let prepared = Arc::new(try!(request.prepare()).into_owned());
+    let clients = try!(worker_clients(request, concurrency.max(1).min(count)));
+    let left = Arc::new(Mutex::new(count));
+    let (sender, receiver) = mpsc::channel();
+    let started = SystemClock.now();
+    let workers: Vec<_> = clients.into_iter()
+                                 .map(|client| {
+                                     let (prepared, left) = (prepared.clone(), left.clone());
+                                     let sender = sender.clone();
+                                     thread::spawn(move || {
+                                         while take_one(&left) {
+                                             let (latency, result) = timed(&client, &prepared);
+                                             let status = result.ok().map(|r| r.status.to_u16());
+                                             let _ = sender.send((latency, status));
+                                         }
+                                     })
+                                 })
+                                 .collect();
+    drop(sender);
+
+    let (mut latencies, mut errors, mut statuses) = (vec![], 0, BTreeMap::new());
+    for (latency, status) in receiver {
+        match status {
+            Some(status) => {
+                latencies.push(latency);
+                *statuses.entry(status).or_insert(0) += 1;
+            }
+            None => errors += 1,
+        }
+    }
+    for worker in workers {
+        let _ = worker.join();
+    }
+    let elapsed = clock::since(&SystemClock, started);
+    Ok(Benchmark::new(&latencies, errors, statuses, elapsed))
+}
+
+/// Clients to send this request from other threads: clones of its Client with
+/// everything it's set up with (see Client::try_clone), or new ones like
+/// Request::new would use.
+pub fn worker_clients(request: &Request, count: usize) -> CursResult<Vec<Client>> {
+    (0..count)
+        .map(|_| {
+            match request.client {
+                Some(client) => client.try_clone(),
+                None => Ok(Client::new()),
+            }
+        })
+        .collect()
+}
+
+fn take_one(left: &Mutex<usize>) -> bool {
+    let mut left = left.lock().unwrap();
+    if *left == 0 {
+        return false;
+    }
+    *left -= 1;
+    true
+}
@@ -0,0 +1,155 @@
+//! A curl-like command line for curs. Build it with `--features cli`.
+//!
+//!     curs [-X METHOD] [-H "Name: value"]... [-d name=value]... [-F name=@path]...
+//!          [--json '{"some":"json"}'] URL
+//!
+//! Params go in the query string for GET and HEAD, and in the body otherwise.
+//! JSON responses are pretty printed, anything else goes to stdout byte for byte.
+
+extern crate curs;
+
+use std::env;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process;
+
+use curs::{Client, FileUpload, Method};
+use curs::hyper::header::{ContentType, Headers};
+use curs::hyper::mime::SubLevel;
+use curs::serde_json::{self, Value};
+
+struct Options {
+    method: Option<Method>,
+    url: Option<String>,
+    headers: Vec<(String, String)>,
+    params: Vec<(String, String)>,
+    files: Vec<(String, PathBuf)>,
+    json: Option<String>,
+}
+
+fn usage() -> ! {
+    let _ = writeln!(io::stderr(),
+                     "usage: curs [-X METHOD] [-H 'Name: value'] [-d name=value] \
+                      [-F name=@path] [--json JSON] URL");
+    process::exit(2)
+}
+
+fn fail(message: String) -> ! {
+    let _ = writeln!(io::stderr(), "curs: {}", message);
+    process::exit(1)
+}
+
+fn split_pair(pair: &str, separator: char) -> (String, String) {
+    let mut parts = pair.splitn(2, separator);
+    let name = parts.next().unwrap_or("").trim().to_string();
+    match parts.next() {
+        Some(value) => (name, value.trim_left().to_string()),
+        None => usage(),
+    }
+}
+
+fn is_json(headers: &Headers) -> bool {
+    match headers.get::<ContentType>() {
+        Some(&ContentType(ref mime)) => {
+            match mime.1 {
+                SubLevel::Json => true,
+                SubLevel::Ext(ref ext) => ext.ends_with("+json"),
+                _ => false,
+            }
+        }
+        None => false,
+    }
+}
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        method: None,
+        url: None,
+        headers: vec![],
+        params: vec![],
+        files: vec![],
+        json: None,
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage());
+        match &*arg {
+            "-X" | "--request" => {
+                let method = value();
+                options.method = Some(method.parse().unwrap_or_else(|_| usage()));
+            }
+            "-H" | "--header" => options.headers.push(split_pair(&value(), ':')),
+            "-d" | "--data" => options.params.push(split_pair(&value(), '=')),
+            "-F" | "--form" => {
+                let (name, field) = split_pair(&value(), '=');
+                if field.starts_with('@') {
+                    options.files.push((name, PathBuf::from(&field[1..])));
+                } else {
+                    options.params.push((name, field));
+                }
+            }
+            "--json" => options.json = Some(value()),
+            "-h" | "--help" => usage(),
+            _ if options.url.is_none() && !arg.starts_with('-') => options.url = Some(arg),
+            _ => usage(),
+        }
+    }
+    options
+}
+
+fn main() {
+    let options = parse_options();
+    let url = options.url.clone().unwrap_or_else(|| usage());
+
+    // Like curl, sending a body implies POST unless told otherwise.
+    let has_body = options.json.is_some() || !options.files.is_empty() ||
+                   !options.params.is_empty();
+    let method = options.method.clone().unwrap_or(if has_body {
+        Method::Post
+    } else {
+        Method::Get
+    });
+
+    // Like curl, it reads file: urls, whoever typed them is on this machine already.
+    let mut client = Client::new();
+    client.allow_file_urls(true);
+    let mut request = client.request(method, &url);
+    request.params(options.params.iter().map(|&(ref n, ref v)| (&**n, &**v)));
+    request.files(options.files.iter().map(|&(ref name, ref path)| {
+        FileUpload {
+            name: name.clone(),
+            mime: None,
+            path: path,
+        }
+    }));
+    if let Some(ref json) = options.json {
+        let value: Value = serde_json::from_str(json)
+                               .unwrap_or_else(|e| fail(format!("invalid --json: {}", e)));
+        request.json(value);
+    }
+    for &(ref name, ref value) in &options.headers {
+        request.raw_header(name, value);
+    }
+
+    let mut response = request.send().unwrap_or_else(|e| fail(format!("{:?}", e)));
+    let mut body = vec![];
+    response.read_to_end(&mut body).unwrap_or_else(|e| fail(e.to_string()));
+
+    let json = if is_json(&response.headers) {
+        serde_json::from_slice::<Value>(&body).ok()
+    } else {
+        None
+    };
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let written = match json {
+        Some(value) => writeln!(stdout, "{}", serde_json::to_string_pretty(&value).unwrap()),
+        None => stdout.write_all(&body),
+    };
+    written.and_then(|_| stdout.flush()).unwrap_or_else(|e| fail(e.to_string()));
+
+    if !response.status.is_success() {
+        let _ = writeln!(io::stderr(), "curs: {}", response.status);
+        process::exit(22);
+    }
+}
@@ -0,0 +1,111 @@
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+
+/// A body made while it's being sent instead of all at once beforehand, so big
+/// ones never have to fit in memory. See Request::body_source.
+pub trait BodySource {
+    /// A reader over the whole body, from the start. Retries open it again.
+    fn open(&self) -> io::Result<OpenBody>;
+}
+
+/// What a BodySource opens to.
+pub struct OpenBody {
+    pub reader: Box<Read + Send>,
+    /// When it's known up front it's sent as the Content-Length, otherwise the
+    /// body goes chunked.
+    pub length: Option<u64>,
+}
+
+/// Makes a body a chunk at a time, each one only once the connection has taken
+/// the one before. Writes block while the socket is full, so a producer that
+/// makes data on the fly, like an encoder or a database cursor, never gets more
+/// than a chunk ahead of the network. See Request::body_producer.
+pub trait BodyProducer {
+    /// The next chunk, or None once the body is over. Empty chunks are skipped.
+    fn next_chunk(&mut self) -> io::Result<Option<Vec<u8>>>;
+}
+
+/// A BodySource that starts a new producer with this function every time it's
+/// opened, so retries start over. The body goes chunked.
+pub struct Produced<F>(pub F);
+
+impl<F, P> BodySource for Produced<F>
+    where F: Fn() -> P,
+          P: BodyProducer + Send + 'static
+{
+    fn open(&self) -> io::Result<OpenBody> {
+        let reader = ProducerReader {
+            producer: (self.0)(),
+            chunk: Cursor::new(vec![]),
+            done: false,
+        };
+        Ok(OpenBody {
+            reader: Box::new(reader),
+            length: None,
+        })
+    }
+}
+
+// Asks for a chunk when the last one has been read through, not before.
+struct ProducerReader<P> {
+    producer: P,
+    chunk: Cursor<Vec<u8>>,
+    done: bool,
+}
+
+impl<P: BodyProducer> Read for ProducerReader<P> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = try!(self.chunk.read(buf));
+            if read > 0 || buf.is_empty() || self.done {
+                return Ok(read);
+            }
+            match try!(self.producer.next_chunk()) {
+                Some(chunk) => self.chunk = Cursor::new(chunk),
+                None => self.done = true,
+            }
+        }
+    }
+}
+
+/// A BodySource over a reader that can seek, like a File or a SpooledBody, sent
+/// from where the reader was when it was given to its end. Every open seeks back
+/// there, so a retry sends the whole body again instead of what was left of it.
+/// See Request::seekable_body.
+pub struct Rewindable<R> {
+    reader: Arc<Mutex<R>>,
+    start: u64,
+}
+
+impl<R: Read + Seek> Rewindable<R> {
+    pub fn new(mut reader: R) -> io::Result<Rewindable<R>> {
+        let start = try!(reader.seek(SeekFrom::Current(0)));
+        Ok(Rewindable {
+            reader: Arc::new(Mutex::new(reader)),
+            start: start,
+        })
+    }
+}
+
+impl<R: Read + Seek + Send + 'static> BodySource for Rewindable<R> {
+    fn open(&self) -> io::Result<OpenBody> {
+        let length = {
+            let mut reader = self.reader.lock().unwrap();
+            let end = try!(reader.seek(SeekFrom::End(0)));
+            try!(reader.seek(SeekFrom::Start(self.start)));
+            end.saturating_sub(self.start)
+        };
+        Ok(OpenBody {
+            reader: Box::new(SharedReader(self.reader.clone())),
+            length: Some(length),
+        })
+    }
+}
+
+struct SharedReader<R>(Arc<Mutex<R>>);
+
+impl<R: Read> Read for SharedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().read(buf)
+    }
+}
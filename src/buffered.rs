@@ -0,0 +1,102 @@
+use std::io::{self, Cursor, Read};
+
+use hyper::header::Headers;
+use serde::Deserialize;
+use url::Url;
+
+use super::{CursResult, Response, StatusCode, first_raw_header};
+use charset;
+use decode::{Decoder, JsonDecoder};
+
+/// A response whose status and headers can be looked at before deciding what to
+/// do with its body: read it whole (as many times as needed), stream it, or throw
+/// it away. Decoding reads the whole body first, so when it fails the body is
+/// still there to look at. See Request::send_buffered.
+pub struct BufferedResponse {
+    response: Response,
+    body: Option<Vec<u8>>,
+}
+
+impl BufferedResponse {
+    pub fn new(response: Response) -> BufferedResponse {
+        BufferedResponse {
+            response: response,
+            body: None,
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.response.status
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.response.headers
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.response.url
+    }
+
+    /// Its ETag exactly as it came, to update the resource with Request::if_match.
+    pub fn etag(&self) -> Option<String> {
+        first_raw_header(&self.response.headers, "ETag")
+    }
+
+    /// The whole body. It's read the first time, later calls get the same bytes.
+    pub fn body(&mut self) -> CursResult<&[u8]> {
+        if self.body.is_none() {
+            let mut body = vec![];
+            try!(self.response.read_to_end(&mut body));
+            self.body = Some(body);
+        }
+        Ok(self.body.as_ref().map(|body| &body[..]).unwrap_or(&[]))
+    }
+
+    /// The whole body as text, with anything that isn't UTF-8 replaced.
+    pub fn text(&mut self) -> CursResult<String> {
+        Ok(String::from_utf8_lossy(try!(self.body())).into_owned())
+    }
+
+    /// The whole body as text in its charset, for pages that aren't in UTF-8: the
+    /// one in its Content-Type, or for HTML without one there, the one its byte
+    /// order mark or `<meta charset>` says. See charset::text.
+    pub fn decoded_text(&mut self) -> CursResult<String> {
+        try!(self.body());
+        let body = self.body.as_ref().map(|body| &body[..]).unwrap_or(&[]);
+        Ok(charset::text(&self.response.headers, body))
+    }
+
+    /// The body as JSON, whatever the status. Check it first.
+    pub fn decode<T: Deserialize>(&mut self) -> CursResult<T> {
+        self.decode_with(&JsonDecoder)
+    }
+
+    pub fn decode_with<T: Deserialize, D: Decoder>(&mut self, decoder: &D) -> CursResult<T> {
+        try!(self.body());
+        let body = self.body.as_ref().map(|body| &body[..]).unwrap_or(&[]);
+        decoder.decode(body, &self.response.headers)
+    }
+
+    /// The body as a stream, for bodies too big to keep in memory. If it was
+    /// already read, the stream goes over what was read.
+    pub fn into_reader(self) -> Box<Read + Send> {
+        match self.body {
+            Some(body) => Box::new(Cursor::new(body)),
+            None => Box::new(self.response),
+        }
+    }
+
+    /// Reads the rest of the body without keeping it, so the connection can go
+    /// back to the Client's pool for the next request.
+    pub fn discard(mut self) -> CursResult<()> {
+        if self.body.is_none() {
+            try!(io::copy(&mut self.response, &mut io::sink()));
+        }
+        Ok(())
+    }
+
+    /// The response as hyper gave it, with whatever is left of its body.
+    pub fn into_inner(self) -> Response {
+        self.response
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use openssl::crypto::hash::{Type, hash};
+use rustc_serialize::hex::ToHex;
+use serde_json::{self, Value};
+
+use super::{replace_file, CursResult};
+use clock::{Clock, SystemClock};
+
+/// How a Client with a cache decides between its store and the network, for GETs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CachePolicy {
+    /// Serve fresh responses (see Freshness) from the store. Stale ones still within
+    /// their stale-while-revalidate window are served too, while they get revalidated
+    /// on a thread of their own. Anything else is revalidated before answering.
+    Normal,
+    /// Always revalidate with the server first, like Cache-Control: no-cache.
+    Revalidate,
+    /// Like Normal, but when the server can't be reached the stored response is
+    /// served no matter how stale it is.
+    StaleIfError,
+    /// Never touch the network, for working offline. Requests with nothing stored
+    /// get a 504 Gateway Timeout, like Cache-Control: only-if-cached.
+    OnlyIfCached,
+}
+
+impl Default for CachePolicy {
+    fn default() -> CachePolicy {
+        CachePolicy::Normal
+    }
+}
+
+/// Whether a stored response can be used without asking the server, going by the
+/// max-age and stale-while-revalidate (RFC 5861) directives it was stored with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Freshness {
+    /// Younger than its max-age.
+    Fresh,
+    /// Past its max-age, but not by more than its stale-while-revalidate.
+    StaleWhileRevalidate,
+    /// Has to be revalidated.
+    Stale,
+}
+
+/// The seconds in a Cache-Control directive like `max-age=60`, if it's there.
+pub fn directive_seconds(cache_control: &str, name: &str) -> Option<i64> {
+    cache_control.split(',')
+                 .filter_map(|directive| {
+                     let mut parts = directive.splitn(2, '=');
+                     match (parts.next(), parts.next()) {
+                         (Some(n), Some(seconds)) if n.trim().to_lowercase() == name => {
+                             seconds.trim().trim_matches('"').parse().ok()
+                         }
+                         _ => None,
+                     }
+                 })
+                 .next()
+}
+
+/// The policy a request's own Cache-Control header asks for, if any: no-cache
+/// means Revalidate and only-if-cached OnlyIfCached.
+pub fn requested_policy(cache_control: &str) -> Option<CachePolicy> {
+    let cache_control = cache_control.to_lowercase();
+    let has = |name: &str| cache_control.split(',').any(|directive| directive.trim() == name);
+    if has("only-if-cached") {
+        Some(CachePolicy::OnlyIfCached)
+    } else if has("no-cache") {
+        Some(CachePolicy::Revalidate)
+    } else {
+        None
+    }
+}
+
+/// A response kept to revalidate with its ETag or Last-Modified later.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CachedResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// Unix timestamp, in seconds.
+    pub stored_at: i64,
+}
+
+impl CachedResponse {
+    /// The first value of this header, names compared case insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_lowercase();
+        self.headers.iter().find(|&&(ref n, _)| n.to_lowercase() == name).map(|&(_, ref v)| &**v)
+    }
+
+    /// Seconds since it was stored or last revalidated.
+    pub fn age(&self) -> i64 {
+        SystemClock.unix_time() - self.stored_at
+    }
+
+    pub fn freshness(&self) -> Freshness {
+        self.freshness_at(SystemClock.unix_time())
+    }
+
+    /// The freshness it has at this unix timestamp.
+    pub fn freshness_at(&self, now: i64) -> Freshness {
+        let cache_control = self.header("Cache-Control").unwrap_or("").to_lowercase();
+        if cache_control.contains("no-cache") {
+            return Freshness::Stale;
+        }
+        let max_age = match directive_seconds(&cache_control, "max-age") {
+            Some(max_age) => max_age,
+            None => return Freshness::Stale,
+        };
+        let window = directive_seconds(&cache_control, "stale-while-revalidate").unwrap_or(0);
+        let age = now - self.stored_at;
+        if age < max_age {
+            Freshness::Fresh
+        } else if age < max_age + window {
+            Freshness::StaleWhileRevalidate
+        } else {
+            Freshness::Stale
+        }
+    }
+
+    // Everything but the body, which the disk store keeps apart.
+    fn meta_to_value(&self) -> Value {
+        let headers = self.headers
+                          .iter()
+                          .map(|&(ref name, ref value)| {
+                              Value::Array(vec![Value::String(name.clone()),
+                                                Value::String(value.clone())])
+                          })
+                          .collect();
+        let mut map = BTreeMap::new();
+        map.insert("status".to_string(), Value::U64(self.status as u64));
+        map.insert("headers".to_string(), Value::Array(headers));
+        map.insert("stored_at".to_string(), Value::I64(self.stored_at));
+        Value::Object(map)
+    }
+
+    fn from_meta_value(value: &Value, body: Vec<u8>) -> Option<CachedResponse> {
+        let headers = value.find("headers").and_then(|h| h.as_array()).map(|headers| {
+            headers.iter()
+                   .filter_map(|pair| {
+                       let pair = pair.as_array().map(|pair| (pair.get(0), pair.get(1)));
+                       match pair {
+                           Some((Some(&Value::String(ref n)), Some(&Value::String(ref v)))) => {
+                               Some((n.clone(), v.clone()))
+                           }
+                           _ => None,
+                       }
+                   })
+                   .collect()
+        });
+        match (value.find("status").and_then(|s| s.as_u64()), headers) {
+            (Some(status), Some(headers)) => {
+                Some(CachedResponse {
+                    status: status as u16,
+                    headers: headers,
+                    body: body,
+                    stored_at: value.find("stored_at").and_then(|s| s.as_i64()).unwrap_or(0),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Where a Client's HTTP cache keeps responses. Keys are request urls, followed
+/// by a line for each request header in the response's Vary, if it has one.
+/// Those that vary are kept under their url alone as well.
+pub trait CacheStore {
+    fn get(&self, key: &str) -> CursResult<Option<CachedResponse>>;
+    fn put(&self, key: &str, response: &CachedResponse) -> CursResult<()>;
+    fn remove(&self, key: &str) -> CursResult<()>;
+}
+
+/// Keeps responses for as long as the process lives.
+#[derive(Debug, Default)]
+pub struct MemoryCacheStore {
+    responses: Mutex<BTreeMap<String, CachedResponse>>,
+}
+
+impl MemoryCacheStore {
+    pub fn new() -> MemoryCacheStore {
+        MemoryCacheStore::default()
+    }
+}
+
+impl CacheStore for MemoryCacheStore {
+    fn get(&self, key: &str) -> CursResult<Option<CachedResponse>> {
+        Ok(self.responses.lock().unwrap().get(key).cloned())
+    }
+
+    fn put(&self, key: &str, response: &CachedResponse) -> CursResult<()> {
+        self.responses.lock().unwrap().insert(key.to_string(), response.clone());
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> CursResult<()> {
+        self.responses.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+/// Keeps responses in a directory so they survive restarts. Bodies go in files
+/// named after their SHA256, so identical bodies are stored once, and an
+/// index.json maps urls to statuses, headers and body files.
+#[derive(Debug)]
+pub struct DiskCacheStore {
+    dir: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl DiskCacheStore {
+    /// The directory is created when the first response is stored.
+    pub fn new(dir: &Path) -> DiskCacheStore {
+        DiskCacheStore {
+            dir: dir.to_path_buf(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.dir.join("index.json")
+    }
+
+    // An index that can't be read is as good as an empty one, it's only a cache.
+    fn read_index(&self) -> BTreeMap<String, Value> {
+        let mut contents = String::new();
+        let read = File::open(self.index_path())
+                       .and_then(|mut file| file.read_to_string(&mut contents));
+        match read.ok().and_then(|_| serde_json::from_str::<Value>(&contents).ok()) {
+            Some(Value::Object(index)) => index,
+            _ => BTreeMap::new(),
+        }
+    }
+
+    fn write_index(&self, index: &BTreeMap<String, Value>) -> CursResult<()> {
+        let contents = try!(serde_json::to_string(&Value::Object(index.clone())));
+        try!(replace_file(&self.index_path(), contents.as_bytes(), OpenOptions::new()));
+        Ok(())
+    }
+
+    // Drops a body file once no entry points to it anymore.
+    fn collect_body(&self, index: &BTreeMap<String, Value>, digest: &str) -> CursResult<()> {
+        let used = index.values().any(|entry| body_digest(entry).map_or(false, |d| d == digest));
+        let path = self.dir.join(digest);
+        if !used && path.exists() {
+            try!(fs::remove_file(path));
+        }
+        Ok(())
+    }
+}
+
+fn body_digest(entry: &Value) -> Option<String> {
+    entry.find("body").and_then(|b| b.as_str()).map(|b| b.to_string())
+}
+
+impl CacheStore for DiskCacheStore {
+    fn get(&self, key: &str) -> CursResult<Option<CachedResponse>> {
+        let _guard = self.lock.lock().unwrap();
+        let index = self.read_index();
+        let entry = match index.get(key) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let mut body = vec![];
+        if let Some(digest) = body_digest(entry) {
+            // Someone cleaned up the directory, or it can't be read, treat it as a miss.
+            let read = File::open(self.dir.join(digest))
+                           .and_then(|mut file| file.read_to_end(&mut body));
+            if read.is_err() {
+                return Ok(None);
+            }
+        }
+        Ok(CachedResponse::from_meta_value(entry, body))
+    }
+
+    fn put(&self, key: &str, response: &CachedResponse) -> CursResult<()> {
+        let _guard = self.lock.lock().unwrap();
+        try!(fs::create_dir_all(&self.dir));
+        let digest = hash(Type::SHA256, &response.body).to_hex();
+        let body_path = self.dir.join(&digest);
+        if !body_path.exists() {
+            try!(replace_file(&body_path, &response.body, OpenOptions::new()));
+        }
+        let mut entry = response.meta_to_value();
+        if let Value::Object(ref mut map) = entry {
+            map.insert("body".to_string(), Value::String(digest));
+        }
+        let mut index = self.read_index();
+        let previous = index.insert(key.to_string(), entry).as_ref().and_then(body_digest);
+        try!(self.write_index(&index));
+        match previous {
+            Some(previous) => self.collect_body(&index, &previous),
+            None => Ok(()),
+        }
+    }
+
+    fn remove(&self, key: &str) -> CursResult<()> {
+        let _guard = self.lock.lock().unwrap();
+        let mut index = self.read_index();
+        let previous = index.remove(key).as_ref().and_then(body_digest);
+        try!(self.write_index(&index));
+        match previous {
+            Some(previous) => self.collect_body(&index, &previous),
+            None => Ok(()),
+        }
+    }
+}
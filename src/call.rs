@@ -0,0 +1,119 @@
+use super::{CursError, CursResult, Request, Response};
+use client::Client;
+use retry::is_idempotent;
+
+/// Something that sends requests: a Client, a layer wrapping another Call, or a
+/// mock for tests. Code that takes a Call instead of a Client can have layers
+/// added or swapped without changing.
+///
+/// ```no_run
+/// # use curs::{Client, Method, Request};
+/// # use curs::call::{Call, MapRequest, Retry};
+/// let client = Client::new();
+/// let tagged = MapRequest::new(&client, |request: &mut Request| {
+///     request.raw_header("X-Source", "sync-job");
+/// });
+/// let layered = Retry::new(tagged, 3);
+/// layered.call(Request::new(Method::Get, "http://example.com/jobs")).unwrap();
+/// ```
+pub trait Call {
+    fn call(&self, request: Request) -> CursResult<Response>;
+}
+
+/// Sends it with this Client, whatever Client it was made with.
+impl Call for Client {
+    fn call(&self, request: Request) -> CursResult<Response> {
+        let mut request: Request = request;
+        request.client = Some(self);
+        request.send()
+    }
+}
+
+impl<'c, C: Call + ?Sized> Call for &'c C {
+    fn call(&self, request: Request) -> CursResult<Response> {
+        (**self).call(request)
+    }
+}
+
+impl<C: Call + ?Sized> Call for Box<C> {
+    fn call(&self, request: Request) -> CursResult<Response> {
+        (**self).call(request)
+    }
+}
+
+/// A function answering requests, like a mock for tests. See call_fn.
+pub struct CallFn<F>(pub F);
+
+/// Makes a Call out of a function.
+pub fn call_fn<F>(function: F) -> CallFn<F>
+    where F: Fn(Request) -> CursResult<Response>
+{
+    CallFn(function)
+}
+
+impl<F> Call for CallFn<F>
+    where F: Fn(Request) -> CursResult<Response>
+{
+    fn call(&self, request: Request) -> CursResult<Response> {
+        (self.0)(request)
+    }
+}
+
+/// Changes every request before passing it on, like adding headers.
+pub struct MapRequest<C, F> {
+    inner: C,
+    map: F,
+}
+
+impl<C: Call, F: Fn(&mut Request)> MapRequest<C, F> {
+    pub fn new(inner: C, map: F) -> MapRequest<C, F> {
+        MapRequest {
+            inner: inner,
+            map: map,
+        }
+    }
+}
+
+impl<C: Call, F: Fn(&mut Request)> Call for MapRequest<C, F> {
+    fn call(&self, mut request: Request) -> CursResult<Response> {
+        (self.map)(&mut request);
+        self.inner.call(request)
+    }
+}
+
+/// Passes requests on again when they fail with a network error, up to this
+/// many attempts in all. Like Client::retry_policy, only idempotent methods and
+/// requests with retry_non_idempotent or an Idempotency-Key get another attempt.
+/// For backoff and retrying on statuses, see Client::retry_policy.
+pub struct Retry<C> {
+    inner: C,
+    attempts: u32,
+}
+
+impl<C: Call> Retry<C> {
+    pub fn new(inner: C, attempts: u32) -> Retry<C> {
+        Retry {
+            inner: inner,
+            attempts: attempts,
+        }
+    }
+}
+
+impl<C: Call> Call for Retry<C> {
+    fn call(&self, request: Request) -> CursResult<Response> {
+        // The server may have acted on a POST before the connection dropped.
+        let attempts = if is_idempotent(&request.method) || request.retry_non_idempotent ||
+                          request.headers.get_raw("Idempotency-Key").is_some() {
+            self.attempts
+        } else {
+            1
+        };
+        let mut attempt = 1;
+        loop {
+            match self.inner.call(request.clone()) {
+                Err(CursError::Network(_)) if attempt < attempts => attempt += 1,
+                result => return result,
+            }
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use hyper::header::Headers;
+
+use super::first_raw_header;
+
+// Browsers only look this far into a page for its <meta charset>.
+const META_PREFIX: usize = 1024;
+
+// What windows-1252 has at 0x80 to 0x9F, where ISO-8859-1 has control characters.
+// The five it leaves undefined stay as they are.
+const WINDOWS_1252: [u16; 32] = [0x20AC, 0x0081, 0x201A, 0x0192, 0x201E, 0x2026, 0x2020, 0x2021,
+                                 0x02C6, 0x2030, 0x0160, 0x2039, 0x0152, 0x008D, 0x017D, 0x008F,
+                                 0x0090, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013, 0x2014,
+                                 0x02DC, 0x2122, 0x0161, 0x203A, 0x0153, 0x009D, 0x017E, 0x0178];
+
+/// The charset a body is in, lowercase: the one its byte order mark says, then the
+/// one in its Content-Type, then for HTML pages the one a `<meta charset>` or
+/// `<meta http-equiv="Content-Type">` in their first 1024 bytes says. None when
+/// nothing says.
+pub fn sniff(headers: &Headers, body: &[u8]) -> Option<String> {
+    if let Some((charset, _)) = bom(body) {
+        return Some(charset.to_string());
+    }
+    let content_type = first_raw_header(headers, "Content-Type").map(|value| value.to_lowercase());
+    if let Some(charset) = content_type.as_ref().and_then(|value| charset_param(value)) {
+        return Some(charset);
+    }
+    let html = match content_type {
+        Some(ref value) => {
+            value.starts_with("text/html") || value.starts_with("application/xhtml+xml")
+        }
+        None => {
+            let start = String::from_utf8_lossy(&body[..body.len().min(64)]).to_lowercase();
+            let start = start.trim_left();
+            start.starts_with("<!doctype html") || start.starts_with("<html")
+        }
+    };
+    if !html {
+        return None;
+    }
+    // A page can't really be in UTF-16 if its <meta> could be read as ASCII.
+    meta_charset(&body[..body.len().min(META_PREFIX)]).map(|charset| {
+        if charset.starts_with("utf-16") {
+            "utf-8".to_string()
+        } else {
+            charset
+        }
+    })
+}
+
+/// The body decoded from this charset, with anything that doesn't fit replaced.
+/// Knows UTF-8, UTF-16 and windows-1252, which is what browsers read ISO-8859-1
+/// and ASCII pages as. None for any other charset.
+pub fn decode(charset: &str, body: &[u8]) -> Option<String> {
+    match &*charset.trim().to_lowercase() {
+        "utf-8" | "utf8" | "unicode-1-1-utf-8" => Some(String::from_utf8_lossy(body).into_owned()),
+        "utf-16" | "utf-16le" => Some(utf16(body, false)),
+        "utf-16be" => Some(utf16(body, true)),
+        "windows-1252" | "cp1252" | "x-cp1252" | "iso-8859-1" | "iso8859-1" | "iso_8859-1" |
+        "latin1" | "l1" | "us-ascii" | "ascii" => Some(windows_1252(body)),
+        _ => None,
+    }
+}
+
+/// The body as text in the charset sniff finds, without its byte order mark.
+/// Bodies in no charset or one decode doesn't know are read as UTF-8.
+pub fn text(headers: &Headers, body: &[u8]) -> String {
+    let charset = sniff(headers, body);
+    let body = match bom(body) {
+        Some((_, length)) => &body[length..],
+        None => body,
+    };
+    charset.and_then(|charset| decode(&charset, body))
+           .unwrap_or_else(|| String::from_utf8_lossy(body).into_owned())
+}
+
+fn bom(body: &[u8]) -> Option<(&'static str, usize)> {
+    if body.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        Some(("utf-8", 3))
+    } else if body.starts_with(&[0xFF, 0xFE]) {
+        Some(("utf-16le", 2))
+    } else if body.starts_with(&[0xFE, 0xFF]) {
+        Some(("utf-16be", 2))
+    } else {
+        None
+    }
+}
+
+// The charset after `charset=` in something already lowercase, like a
+// Content-Type or a <meta> tag.
+fn charset_param(value: &str) -> Option<String> {
+    value.find("charset=").and_then(|start| {
+        let charset: String = value[start + "charset=".len()..]
+                                  .trim_left_matches(|c| c == '"' || c == '\'' || c == ' ')
+                                  .chars()
+                                  .take_while(|&c| {
+                                      !(c == '"' || c == '\'' || c == ';' || c == '/' ||
+                                        c == '>' || c.is_whitespace())
+                                  })
+                                  .collect();
+        if charset.is_empty() {
+            None
+        } else {
+            Some(charset)
+        }
+    })
+}
+
+fn meta_charset(prefix: &[u8]) -> Option<String> {
+    let page = String::from_utf8_lossy(prefix).to_lowercase();
+    page.split("<meta")
+        .skip(1)
+        .filter_map(|tag| charset_param(tag.split('>').next().unwrap_or("")))
+        .next()
+}
+
+fn utf16(body: &[u8], big_endian: bool) -> String {
+    let units: Vec<u16> = body.chunks(2)
+                              .map(|pair| {
+                                  match (pair.len(), big_endian) {
+                                      (1, _) => 0xFFFD,
+                                      (_, true) => (pair[0] as u16) << 8 | pair[1] as u16,
+                                      (_, false) => (pair[1] as u16) << 8 | pair[0] as u16,
+                                  }
+                              })
+                              .collect();
+    String::from_utf16_lossy(&units)
+}
+
+fn windows_1252(body: &[u8]) -> String {
+    body.iter()
+        .map(|&byte| {
+            match byte {
+                0x80...0x9F => {
+                    ::std::char::from_u32(WINDOWS_1252[(byte - 0x80) as usize] as u32)
+                        .unwrap_or('\u{FFFD}')
+                }
+                _ => byte as char,
+            }
+        })
+        .collect()
+}
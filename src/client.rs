@@ -0,0 +1,1258 @@
+use std::borrow::Cow;
+use std::io::{self, Read};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use hyper::client::{Body, Client as HyperClient, RedirectPolicy};
+use hyper::client::pool::Pool;
+use hyper::error::Error as HyperError;
+use hyper::header::{Authorization, Basic, Bearer, ContentType, Header, HeaderFormat, Headers,
+                    Location, UserAgent};
+use hyper::method::Method;
+use hyper::net::{NetworkConnector, Openssl};
+use hyper::status::{StatusClass, StatusCode};
+use openssl::ssl::SslContext;
+use url::{ParseError as UrlError, Url};
+
+use super::{CursError, CursResult, DecodableResult, PreparedRequest, Request, Response,
+            first_raw_header, is_bad_header};
+use audit::{AuditRecord, AuditSink};
+use auth::{Challenge, CredentialProvider};
+use body::OpenBody;
+use cache::{self, CachePolicy, CacheStore, CachedResponse, Freshness};
+use clock::{self, Clock, SystemClock};
+use dns::{self, PublicOnly, Resolver, SystemResolver};
+use endpoint::{self, Endpoint};
+use local;
+use memo::{self, Memo};
+use netrc::Netrc;
+use proxy::{AbsoluteForm, NoProxy, ProxyConnector, ProxySettings, parse_proxy_url};
+use queue::{Gate, Priority, QueueDepth};
+use ratelimit::RateLimiter;
+use retry::{Attempt, Outcome, RetryDecision, RetryPolicy, is_connection_reset, is_idempotent,
+            retry_after};
+use tls::{CursSsl, Tls, TlsSettings};
+use tokens::TokenStore;
+use transform::{BodyTransformer, Unwrap};
+use validate::ResponseValidator;
+
+fn is_storable(response: &Response) -> bool {
+    let validated = response.headers.get_raw("ETag").is_some() ||
+                    response.headers.get_raw("Last-Modified").is_some();
+    let no_store = response.headers
+                           .get_raw("Cache-Control")
+                           .map(|values| {
+                               values.iter().any(|v| {
+                                   String::from_utf8_lossy(v).to_lowercase().contains("no-store")
+                               })
+                           })
+                           .unwrap_or(false);
+    let max_age = response.headers
+                          .get_raw("Cache-Control")
+                          .map(|values| {
+                              values.iter().any(|v| {
+                                  String::from_utf8_lossy(v).to_lowercase().contains("max-age")
+                              })
+                          })
+                          .unwrap_or(false);
+    // A response that varies on everything can't be picked out again.
+    let varies_on_all = first_raw_header(&response.headers, "Vary")
+                            .map(|vary| vary.split(',').any(|name| name.trim() == "*"))
+                            .unwrap_or(false);
+    (validated || max_age) && !no_store && !varies_on_all
+}
+
+// Whether a response to a request with credentials can be shared with others,
+// which takes it saying so (RFC 7234, 3.2).
+fn is_public(response: &Response) -> bool {
+    first_raw_header(&response.headers, "Cache-Control")
+        .map(|value| value.to_lowercase().split(',').any(|directive| directive.trim() == "public"))
+        .unwrap_or(false)
+}
+
+// What a response is stored under: its url, and the request headers it says it
+// varies on, if any (RFC 7234, 4.1).
+fn cache_key(url: &str, headers: &Headers, vary: Option<&str>) -> String {
+    let mut key = url.to_string();
+    for name in vary.unwrap_or("").split(',').map(|name| name.trim().to_lowercase()) {
+        if !name.is_empty() {
+            let value = first_raw_header(headers, &name).unwrap_or_else(String::new);
+            key.push_str(&format!("\n{}: {}", name, value));
+        }
+    }
+    key
+}
+
+// Catches what signers and default headers add, Request::raw_header checks its own.
+fn check_headers(headers: &Headers) -> CursResult<()> {
+    for header in headers.iter() {
+        let name = header.name();
+        if is_bad_header(name, &header.value_string()) {
+            return Err(CursError::InvalidInput(format!("Header {:?} has control characters",
+                                                       name)));
+        }
+    }
+    Ok(())
+}
+
+// Reads the whole response to keep it for later.
+fn read_stored(response: &mut Response, now: i64) -> CursResult<CachedResponse> {
+    let mut body = vec![];
+    try!(response.read_to_end(&mut body));
+    Ok(CachedResponse {
+        status: response.status.to_u16(),
+        headers: response.headers
+                         .iter()
+                         .map(|h| (h.name().to_string(), h.value_string()))
+                         .collect(),
+        body: body,
+        stored_at: now,
+    })
+}
+
+fn from_cache(url: &str, cached: &CachedResponse) -> CursResult<Response> {
+    from_stored(url, &Method::Get, cached)
+}
+
+fn from_stored(url: &str, method: &Method, cached: &CachedResponse) -> CursResult<Response> {
+    local::response_with_headers(try!(Url::parse(url)),
+                                 method,
+                                 StatusCode::from_u16(cached.status),
+                                 &cached.headers,
+                                 &cached.body)
+}
+
+// What only-if-cached requests get when there's nothing stored (RFC 7234, 5.2.1.7).
+fn not_cached(url: &str) -> CursResult<Response> {
+    local::response(try!(Url::parse(url)),
+                    &Method::Get,
+                    StatusCode::GatewayTimeout,
+                    "text/plain",
+                    b"Not cached")
+}
+
+// Fails reads once more than `left` bytes went through, see Client::max_request_bytes.
+struct Capped<R> {
+    inner: R,
+    left: u64,
+}
+
+impl<R: Read> Read for Capped<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = try!(self.inner.read(buf));
+        if read as u64 > self.left {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "The body got bigger than the limit"));
+        }
+        self.left -= read as u64;
+        Ok(read)
+    }
+}
+
+// Past this many redirects in a row the last one is returned as it is.
+const MAX_REDIRECTS: usize = 20;
+
+// Scheme, host and port, as redirects are told apart (RFC 6454).
+fn origin(url: &str) -> Option<(String, String, Option<u16>)> {
+    Url::parse(url).ok().map(|url| {
+        let host = url.serialize_host().unwrap_or_else(String::new);
+        (url.scheme.clone(), host, url.port_or_default())
+    })
+}
+
+type Connections = Arc<Pool<ProxyConnector<CursSsl>>>;
+
+/// Hands hyper the same pool the Client keeps, so it can be warmed up with preconnect.
+/// Requests for http proxies get rewritten to absolute form on their way out.
+struct SharedPool(Connections, ProxySettings);
+
+impl NetworkConnector for SharedPool {
+    type Stream = AbsoluteForm<<Pool<ProxyConnector<CursSsl>> as NetworkConnector>::Stream>;
+
+    fn connect(&self, host: &str, port: u16, scheme: &str) -> ::hyper::Result<Self::Stream> {
+        let stream = try!(self.0.connect(host, port, scheme));
+        Ok(AbsoluteForm::new(stream, &self.1, host, port, scheme))
+    }
+}
+
+const TOR_BROWSER_USER_AGENT: &'static str = "Mozilla/5.0 (Windows NT 6.1; rv:45.0) \
+                                               Gecko/20100101 Firefox/45.0";
+
+/// A Client lets several requests share the same underlying hyper client
+/// (and its connection pool). Requests made with Request::new get a fresh one each time.
+pub struct Client {
+    /// Replaced whenever proxies or TLS settings change, and stood in for by
+    /// another for requests with their own, so set timeouts with
+    /// Client::read_timeout and write_timeout instead, which all of them get.
+    /// Redirects are followed by the Client itself and not by hyper, see
+    /// Client::redirect_policy.
+    pub hyper: HyperClient,
+    connections: Connections,
+    proxies: ProxySettings,
+    tls: TlsSettings,
+    resolver: Arc<Resolver + Send + Sync>,
+    clock: Arc<Clock + Send + Sync>,
+    base: Option<Url>,
+    headers: Headers,
+    signer: Option<Arc<Fn(&mut PreparedRequest) + Send + Sync>>,
+    netrc: Option<Netrc>,
+    credentials: Option<Arc<CredentialProvider + Send + Sync>>,
+    tokens: Option<(Arc<TokenStore + Send + Sync>, String)>,
+    gate: Option<Arc<Gate>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    retry: Option<Arc<RetryPolicy + Send + Sync>>,
+    retry_budget: Option<Duration>,
+    resend_after_reset: bool,
+    cache: Option<Arc<CacheStore + Send + Sync>>,
+    cache_policy: CachePolicy,
+    memo: Option<Arc<Memo>>,
+    validators: Vec<Arc<ResponseValidator + Send + Sync>>,
+    transformers: Vec<Arc<BodyTransformer + Send + Sync>>,
+    audit: Option<Arc<AuditSink + Send + Sync>>,
+    max_request_bytes: Option<u64>,
+    stream_threshold: Option<u64>,
+    file_urls: bool,
+    redirects: RedirectPolicy,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+}
+
+impl Client {
+    pub fn new() -> Client {
+        let resolver: Arc<Resolver + Send + Sync> = Arc::new(SystemResolver);
+        let (hyper, connections) = Client::hyper_client(ProxySettings::default(),
+                                                        &TlsSettings::default(),
+                                                        resolver.clone(),
+                                                        None)
+                                       .unwrap();
+        Client {
+            hyper: hyper,
+            connections: connections,
+            proxies: ProxySettings::default(),
+            tls: TlsSettings::default(),
+            resolver: resolver,
+            clock: Arc::new(SystemClock),
+            base: None,
+            headers: Headers::new(),
+            signer: None,
+            netrc: None,
+            credentials: None,
+            tokens: None,
+            gate: None,
+            rate_limiter: None,
+            retry: None,
+            retry_budget: None,
+            resend_after_reset: false,
+            cache: None,
+            cache_policy: CachePolicy::default(),
+            memo: None,
+            validators: vec![],
+            transformers: vec![],
+            audit: None,
+            max_request_bytes: None,
+            stream_threshold: None,
+            file_urls: false,
+            redirects: RedirectPolicy::FollowAll,
+            read_timeout: None,
+            write_timeout: None,
+        }
+    }
+
+    /// Start a request that will be sent using this Client.
+    /// Relative urls need a base_url, sending them fails with CursError::Url otherwise.
+    pub fn request<'a>(&'a self, method: Method, url: &'a str) -> Request<'a> {
+        let mut request = Request::new(method, url);
+        request.client = Some(self);
+        request
+    }
+
+    pub fn get<'a>(&'a self, url: &'a str) -> Request<'a> {
+        self.request(Method::Get, url)
+    }
+
+    pub fn head<'a>(&'a self, url: &'a str) -> Request<'a> {
+        self.request(Method::Head, url)
+    }
+
+    pub fn post<'a>(&'a self, url: &'a str) -> Request<'a> {
+        self.request(Method::Post, url)
+    }
+
+    pub fn put<'a>(&'a self, url: &'a str) -> Request<'a> {
+        self.request(Method::Put, url)
+    }
+
+    pub fn patch<'a>(&'a self, url: &'a str) -> Request<'a> {
+        self.request(Method::Patch, url)
+    }
+
+    pub fn delete<'a>(&'a self, url: &'a str) -> Request<'a> {
+        self.request(Method::Delete, url)
+    }
+
+    /// Send the request this endpoint makes for this input, and decode its JSON
+    /// response. See endpoint::Endpoint.
+    pub fn execute<E: Endpoint>(&self, input: E::Input) -> CursResult<E::Output> {
+        let url = endpoint::path_and_query::<E>(&input);
+        let mut request = self.request(E::method(), &url);
+        if let Some(body) = E::body(&input) {
+            request.json(body);
+        }
+        request.send().decode_success()
+    }
+
+    /// Request urls are resolved against this one, so they can be relative to it,
+    /// `..` segments included. End it with a `/` if relative paths should go below it.
+    pub fn base_url(&mut self, base: Url) -> &mut Client {
+        self.base = Some(base);
+        self
+    }
+
+    /// The url requests are resolved against, if any.
+    pub fn base(&self) -> Option<&Url> {
+        self.base.as_ref()
+    }
+
+    /// Add a header sent with every request. Headers set on the request itself win.
+    pub fn header<H>(&mut self, default: H) -> &mut Client
+        where H: Header + HeaderFormat
+    {
+        self.headers.set(default);
+        self
+    }
+
+    /// Add several headers sent with every request.
+    pub fn headers(&mut self, defaults: &Headers) -> &mut Client {
+        for default in defaults.iter() {
+            self.headers.set_raw(default.name().to_string(),
+                                 vec![default.value_string().into_bytes()]);
+        }
+        self
+    }
+
+    /// Set a function that signs every request once its url, headers and body are final.
+    /// It can add headers (HMAC signatures, timestamps, nonces) right before sending.
+    /// Redirects to another origin are followed unsigned.
+    pub fn signer<F>(&mut self, signer: F) -> &mut Client
+        where F: Fn(&mut PreparedRequest) + Send + Sync + 'static
+    {
+        self.signer = Some(Arc::new(signer));
+        self
+    }
+
+    /// Go through the proxies set in http_proxy, https_proxy and all_proxy,
+    /// except for hosts matching no_proxy. This replaces the hyper client, so
+    /// set any timeouts on it afterwards.
+    pub fn proxy_from_env(&mut self) -> &mut Client {
+        self.proxy(ProxySettings::from_env())
+    }
+
+    /// Go through the given proxies. Replaces the hyper client too.
+    pub fn proxy(&mut self, settings: ProxySettings) -> &mut Client {
+        // The TLS settings were already accepted once, so building them again can't fail.
+        let tls = self.tls.clone();
+        self.rebuild(settings, tls).unwrap();
+        self
+    }
+
+    /// Negotiate TLS following these settings. Replaces the hyper client too.
+    /// Fails if openssl rejects them, like when it knows none of the ciphers.
+    pub fn tls(&mut self, settings: TlsSettings) -> CursResult<&mut Client> {
+        let proxies = self.proxies.clone();
+        try!(self.rebuild(proxies, settings));
+        Ok(self)
+    }
+
+    /// Refuse to talk to servers that can't do at least this TLS version.
+    pub fn min_tls_version(&mut self, version: Tls) -> CursResult<&mut Client> {
+        let mut settings = self.tls.clone();
+        settings.min_version = Some(version);
+        self.tls(settings)
+    }
+
+    /// Negotiate TLS with an openssl context you configured yourself, for custom
+    /// verifiers, session tickets or anything else curs doesn't have a setting for.
+    pub fn ssl_context(&mut self, context: SslContext) -> &mut Client {
+        let mut settings = self.tls.clone();
+        settings.context = Some(Openssl { context: Arc::new(context) });
+        // Nothing gets built from a ready made context, so this can't fail.
+        let proxies = self.proxies.clone();
+        self.rebuild(proxies, settings).unwrap();
+        self
+    }
+
+    /// Offer these ALPN protocols during the TLS handshake, most preferred first.
+    #[cfg(feature = "alpn")]
+    pub fn alpn_protocols(&mut self, protocols: &[&str]) -> CursResult<&mut Client> {
+        let mut settings = self.tls.clone();
+        settings.alpn_protocols = protocols.iter().map(|p| p.to_string()).collect();
+        self.tls(settings)
+    }
+
+    /// Only offer the ciphers in this openssl cipher list.
+    pub fn ciphers(&mut self, list: &str) -> CursResult<&mut Client> {
+        let mut settings = self.tls.clone();
+        settings.ciphers = Some(list.to_string());
+        self.tls(settings)
+    }
+
+    /// Route everything through Tor's SOCKS port on localhost, letting Tor resolve names.
+    /// It also stops using .netrc credentials, and sends the Tor Browser User-Agent
+    /// unless you set one yourself.
+    pub fn over_tor(&mut self) -> &mut Client {
+        let tor = parse_proxy_url("socks5h://127.0.0.1:9050").unwrap();
+        self.proxy(ProxySettings {
+            http: Some(tor.clone()),
+            https: Some(tor),
+            no_proxy: NoProxy::default(),
+        });
+        self.netrc = None;
+        if !self.headers.has::<UserAgent>() {
+            self.header(UserAgent(TOR_BROWSER_USER_AGENT.to_string()));
+        }
+        self
+    }
+
+    /// Look up the hosts requests go to with this resolver, like DohResolver, instead
+    /// of asking the system. It's also used for SOCKS5 proxies, which get addresses
+    /// rather than names; other proxies resolve names themselves.
+    /// Replaces the hyper client too.
+    pub fn resolver<R>(&mut self, resolver: R) -> &mut Client
+        where R: Resolver + Send + Sync + 'static
+    {
+        self.resolver = Arc::new(resolver);
+        let (proxies, tls) = (self.proxies.clone(), self.tls.clone());
+        // Same settings as before, they can't fail now.
+        self.rebuild(proxies, tls).unwrap();
+        self
+    }
+
+    /// Refuse to connect to private, loopback and link-local addresses, after any
+    /// redirect too, for services that fetch urls users give them. Requests going
+    /// there fail with a PermissionDenied network error, and so do those to urls
+    /// that aren't http or https, like `file:` and `data:`. Keeps the resolver set
+    /// before, set another one afterwards with dns::PublicOnly. See dns::PublicOnly.
+    pub fn block_private_addresses(&mut self) -> &mut Client {
+        let guarded = PublicOnly::wrapping(self.resolver.clone());
+        self.resolver(guarded)
+    }
+
+    /// Which redirects to follow, all of them by default. Whenever one goes to
+    /// another scheme, host or port, the Authorization, Proxy-Authorization and
+    /// Cookie headers are left behind, whoever set them. 303s, and 301s and 302s to
+    /// POSTs, are followed with a GET without the body, like browsers do, the rest
+    /// with the same method and body.
+    pub fn redirect_policy(&mut self, policy: RedirectPolicy) -> &mut Client {
+        self.redirects = policy;
+        self
+    }
+
+    /// Give up on responses that stall for longer than this, for every hyper client
+    /// the Client builds.
+    pub fn read_timeout(&mut self, timeout: Option<Duration>) -> &mut Client {
+        self.read_timeout = timeout;
+        self.hyper.set_read_timeout(timeout);
+        self
+    }
+
+    /// Give up on sending requests that stall for longer than this, for every hyper
+    /// client the Client builds.
+    pub fn write_timeout(&mut self, timeout: Option<Duration>) -> &mut Client {
+        self.write_timeout = timeout;
+        self.hyper.set_write_timeout(timeout);
+        self
+    }
+
+    /// Let requests read `file:` urls from disk, which they can't by default so
+    /// urls from users don't reach local files. Never while private addresses are
+    /// blocked. See block_private_addresses.
+    pub fn allow_file_urls(&mut self, allow: bool) -> &mut Client {
+        self.file_urls = allow;
+        self
+    }
+
+    /// See allow_file_urls.
+    pub fn allows_file_urls(&self) -> bool {
+        self.file_urls && !self.refuses_private_addresses()
+    }
+
+    /// Whether its resolver refuses private addresses, like after
+    /// block_private_addresses or with a dns::PublicOnly resolver.
+    pub fn refuses_private_addresses(&self) -> bool {
+        !self.resolver.allows(&IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)))
+    }
+
+    // Hyper's own connector can't reach IPv6 literals, so we always bring ours.
+    fn hyper_client(proxies: ProxySettings,
+                    tls: &TlsSettings,
+                    resolver: Arc<Resolver + Send + Sync>,
+                    credentials: Option<Arc<CredentialProvider + Send + Sync>>)
+                    -> CursResult<(HyperClient, Connections)> {
+        let ssl = try!(tls.ssl().map_err(HyperError::from));
+        let mut connector = ProxyConnector::with_resolver(proxies.clone(), ssl, resolver);
+        if let Some(credentials) = credentials {
+            connector.credential_provider(credentials);
+        }
+        let connections = Arc::new(Pool::with_connector(Default::default(), connector));
+        let mut hyper = HyperClient::with_connector(SharedPool(connections.clone(), proxies));
+        // It would send every header on to wherever a redirect points, credentials too.
+        hyper.set_redirect_policy(RedirectPolicy::FollowNone);
+        Ok((hyper, connections))
+    }
+
+    fn rebuild(&mut self, proxies: ProxySettings, tls: TlsSettings) -> CursResult<()> {
+        let (resolver, credentials) = (self.resolver.clone(), self.credentials.clone());
+        let (mut hyper, connections) = try!(Client::hyper_client(proxies.clone(),
+                                                                 &tls,
+                                                                 resolver,
+                                                                 credentials));
+        self.set_timeouts(&mut hyper);
+        self.hyper = hyper;
+        self.connections = connections;
+        self.proxies = proxies;
+        self.tls = tls;
+        Ok(())
+    }
+
+    // Hyper keeps its timeouts to itself, so they're set again on every hyper client.
+    fn set_timeouts(&self, hyper: &mut HyperClient) {
+        hyper.set_read_timeout(self.read_timeout);
+        hyper.set_write_timeout(self.write_timeout);
+    }
+
+    /// Open a connection to this url's host ahead of time, TLS handshake included,
+    /// and leave it in the pool for the next request going there.
+    /// Relative urls are resolved against the base url.
+    pub fn preconnect(&self, url: &str) -> CursResult<()> {
+        let url = match self.base {
+            Some(ref base) => try!(base.join(url)),
+            None => try!(Url::parse(url)),
+        };
+        let host = try!(url.serialize_host().ok_or(UrlError::EmptyHost));
+        let port = try!(url.port_or_default().ok_or(UrlError::InvalidPort));
+        // Dropping the stream is what puts it back in the pool.
+        try!(self.connections.connect(&host, port, &url.scheme));
+        Ok(())
+    }
+
+    /// Use basic auth credentials from ~/.netrc for matching hosts, like curl --netrc.
+    /// Requests that already have an Authorization header are left alone.
+    pub fn netrc(&mut self, enabled: bool) -> &mut Client {
+        self.netrc = if enabled {
+            Some(Netrc::from_home())
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Same as netrc(true) but reading credentials from some other file.
+    pub fn netrc_file(&mut self, path: &Path) -> &mut Client {
+        self.netrc = Some(Netrc::from_file(path).unwrap_or_else(|_| Netrc::default()));
+        self
+    }
+
+    /// Ask this provider for credentials when a request gets a 401, or a proxy
+    /// wants them to open a tunnel. Replaces the hyper client too, proxies are
+    /// answered while connecting.
+    pub fn credential_provider<P>(&mut self, provider: P) -> &mut Client
+        where P: CredentialProvider + Send + Sync + 'static
+    {
+        self.credentials = Some(Arc::new(provider));
+        let (proxies, tls) = (self.proxies.clone(), self.tls.clone());
+        // Same settings as before, they can't fail now.
+        self.rebuild(proxies, tls).unwrap();
+        self
+    }
+
+    /// Send the token stored under this key as a Bearer Authorization header.
+    /// Requests that already have an Authorization header are left alone.
+    pub fn bearer_tokens<S>(&mut self, store: S, key: &str) -> &mut Client
+        where S: TokenStore + Send + Sync + 'static
+    {
+        self.tokens = Some((Arc::new(store), key.to_string()));
+        self
+    }
+
+    /// The token store set with bearer_tokens, so you can save renewed tokens to it.
+    pub fn token_store(&self) -> Option<&(TokenStore + Send + Sync)> {
+        self.tokens.as_ref().map(|&(ref store, _)| &**store)
+    }
+
+    /// Send at most this many requests at once, from any number of threads.
+    /// The rest wait their turn by priority, see Request::priority.
+    /// A request's turn lasts until its response headers arrive.
+    pub fn max_concurrent(&mut self, limit: usize) -> &mut Client {
+        self.gate = Some(Arc::new(Gate::new(limit)));
+        self
+    }
+
+    /// Send at most `requests_per_sec` requests a second on average, letting up to
+    /// `burst` go at once after a quiet spell. Requests over the limit wait.
+    /// Retries count too. Fails with CursError::Config unless the rate is above zero.
+    pub fn rate_limit(&mut self, requests_per_sec: f64, burst: u32) -> CursResult<&mut Client> {
+        let limiter = try!(RateLimiter::new(requests_per_sec, burst));
+        self.rate_limiter = Some(Arc::new(limiter.with_clock(self.clock.clone())));
+        Ok(self)
+    }
+
+    /// Same as rate_limit, but counting requests to each host separately.
+    pub fn rate_limit_per_host(&mut self,
+                               requests_per_sec: f64,
+                               burst: u32)
+                               -> CursResult<&mut Client> {
+        let limiter = try!(RateLimiter::per_host(requests_per_sec, burst));
+        self.rate_limiter = Some(Arc::new(limiter.with_clock(self.clock.clone())));
+        Ok(self)
+    }
+
+    /// How many requests are being sent and how many wait, by priority.
+    /// All zeros when there's no concurrency limit.
+    pub fn queue_depth(&self) -> QueueDepth {
+        self.gate.as_ref().map(|g| g.depth()).unwrap_or_else(QueueDepth::default)
+    }
+
+    /// Retry failed requests as this policy says. Without one nothing is retried.
+    pub fn retry_policy<P>(&mut self, policy: P) -> &mut Client
+        where P: RetryPolicy + Send + Sync + 'static
+    {
+        self.retry = Some(Arc::new(policy));
+        self
+    }
+
+    /// Send requests once more on a new connection when theirs is reset or closed
+    /// before the response comes, which is mostly kept-alive connections the server
+    /// already dropped. Bodies go again whole: those in memory as they were, those
+    /// from a body::BodySource opened again from the start. Only requests that can
+    /// be retried are resent, see retry::is_idempotent.
+    pub fn resend_after_reset(&mut self) -> &mut Client {
+        self.resend_after_reset = true;
+        self
+    }
+
+    /// Tell time and wait with this clock, for retries, caching, memoizing and rate
+    /// limiting. Handy to test them without waiting, see clock::ManualClock.
+    /// A rate limiter already shared with a try_clone keeps the clock it had.
+    pub fn clock(&mut self, clock: Arc<Clock + Send + Sync>) -> &mut Client {
+        self.rate_limiter = self.rate_limiter.take().map(|limiter| {
+            match Arc::try_unwrap(limiter) {
+                Ok(limiter) => Arc::new(limiter.with_clock(clock.clone())),
+                Err(shared) => shared,
+            }
+        });
+        self.clock = clock;
+        self
+    }
+
+    /// Stop retrying once waiting for the next attempt would take longer than this,
+    /// counting from the first one. The request then fails with
+    /// CursError::RetriesExhausted, wrapping how the last attempt went.
+    pub fn max_retry_duration(&mut self, budget: Duration) -> &mut Client {
+        self.retry_budget = Some(budget);
+        self
+    }
+
+    /// Keep GET responses that have an ETag, Last-Modified or Cache-Control max-age
+    /// in this store. Fresh ones are served straight from it and the rest get
+    /// revalidated, a 304 gets the stored response back. See CachePolicy.
+    /// Responses marked Cache-Control: no-store are never kept.
+    pub fn cache<S>(&mut self, store: S) -> &mut Client
+        where S: CacheStore + Send + Sync + 'static
+    {
+        self.cache = Some(Arc::new(store));
+        self
+    }
+
+    /// How requests use the cache unless they say otherwise. CachePolicy::Normal by default.
+    pub fn cache_policy(&mut self, policy: CachePolicy) -> &mut Client {
+        self.cache_policy = policy;
+        self
+    }
+
+    /// Remember successful responses for this long, whatever the server says about
+    /// caching them, and answer identical requests (same method, url, params, body
+    /// and Accept, Authorization and Cookie headers) with them meanwhile. Handy to
+    /// deduplicate lookups in a batch job. Only the most recently used memo::CAPACITY
+    /// responses are kept, and only GETs and HEADs are memoized, sending anything else
+    /// twice is meant to do it twice.
+    pub fn memoize(&mut self, ttl: Duration) -> &mut Client {
+        self.memo = Some(Arc::new(Memo::new(ttl, memo::CAPACITY)));
+        self
+    }
+
+    /// Check every response with this validator before handing it over.
+    /// Validators run in the order they were added, the first complaint wins.
+    pub fn validator<V>(&mut self, validator: V) -> &mut Client
+        where V: ResponseValidator + Send + Sync + 'static
+    {
+        self.validators.push(Arc::new(validator));
+        self
+    }
+
+    /// Rewrite request bodies with this transformer before sending them, and
+    /// response bodies before handing them over. Outgoing bodies go through the
+    /// transformers in the order they were added, incoming ones the other way
+    /// around. See transform::BodyTransformer.
+    pub fn body_transformer<T>(&mut self, transformer: T) -> &mut Client
+        where T: BodyTransformer + Send + Sync + 'static
+    {
+        self.transformers.push(Arc::new(transformer));
+        self
+    }
+
+    /// Decode JSON responses wrapped in an envelope, like `{"data": ..., "meta": ...}`,
+    /// as whatever is in this field. See transform::Unwrap.
+    pub fn unwrap_field(&mut self, field: &str) -> &mut Client {
+        self.body_transformer(Unwrap(field.to_string()))
+    }
+
+    /// Refuse to send bodies bigger than this, failing with CursError::InvalidInput
+    /// before anything goes out. Bodies made while they're sent without a length
+    /// known up front are cut off with an error once they get past it.
+    pub fn max_request_bytes(&mut self, limit: u64) -> &mut Client {
+        self.max_request_bytes = Some(limit);
+        self
+    }
+
+    /// Stream multipart bodies whose files and uploads add up to more than this
+    /// many bytes, like Request::stream_multipart, instead of reading them into
+    /// memory first.
+    pub fn stream_bodies_above(&mut self, threshold: u64) -> &mut Client {
+        self.stream_threshold = Some(threshold);
+        self
+    }
+
+    /// See stream_bodies_above.
+    pub fn stream_threshold(&self) -> Option<u64> {
+        self.stream_threshold
+    }
+
+    /// Tell this sink about every request sent, how it went and how long it took,
+    /// for an audit trail. See audit::AuditRecord for what gets recorded, and
+    /// Request::audit_tag to add your own fields, like who asked for it.
+    pub fn audit<S>(&mut self, sink: S) -> &mut Client
+        where S: AuditSink + Send + Sync + 'static
+    {
+        self.audit = Some(Arc::new(sink));
+        self
+    }
+
+    /// Sends a request that's already been prepared, running the signer first.
+    /// Hosts are looked up once for all of it when the resolver pins addresses, see
+    /// dns::pinning.
+    pub fn send_prepared(&self, prepared: PreparedRequest) -> CursResult<Response> {
+        dns::pinning(|| self.send_audited(prepared))
+    }
+
+    fn send_audited(&self, prepared: PreparedRequest) -> CursResult<Response> {
+        let sink = match self.audit {
+            Some(ref sink) => sink,
+            None => return self.send_validated(prepared),
+        };
+        let started = self.clock.now();
+        let mut record = AuditRecord::new(&prepared, started);
+        let result = self.send_validated(prepared);
+        record.finish(&result, clock::since(&*self.clock, started));
+        sink.record(&record);
+        result
+    }
+
+    fn send_validated(&self, mut prepared: PreparedRequest) -> CursResult<Response> {
+        if self.transformers.is_empty() {
+            return self.check_response(try!(self.send_memoized(prepared)));
+        }
+        if let Some(body) = prepared.body.take() {
+            let mut body = body.into_owned();
+            for transformer in &self.transformers {
+                body = try!(transformer.outgoing(body, &mut prepared.headers));
+            }
+            prepared.body = Some(Cow::Owned(body));
+        }
+        // The receiver checks the signature against the body that reaches it.
+        if let Some(ref signer) = prepared.webhook_signer {
+            let signature = signer.sign(prepared.body.as_ref().map(|b| &**b).unwrap_or(&[]));
+            prepared.headers.set_raw(signer.header().to_string(), vec![signature.into_bytes()]);
+        }
+        let (url, method) = (prepared.url.clone(), prepared.method.clone());
+        let mut response = try!(self.send_memoized(prepared));
+        if !self.transformers.iter().any(|t| t.rewrites_incoming(&response.headers)) {
+            return self.check_response(response);
+        }
+        let mut stored = try!(read_stored(&mut response, self.clock.unix_time()));
+        for transformer in self.transformers.iter().rev() {
+            stored.body = try!(transformer.incoming(stored.body, &response.headers));
+        }
+        self.check_response(try!(from_stored(&url, &method, &stored)))
+    }
+
+    fn check_response(&self, response: Response) -> CursResult<Response> {
+        for validator in &self.validators {
+            if let Err(reason) = validator.validate(&response) {
+                return Err(CursError::InvalidResponse(response, reason));
+            }
+        }
+        Ok(response)
+    }
+
+    fn send_memoized(&self, prepared: PreparedRequest) -> CursResult<Response> {
+        // Bodies made on the fly can't be told apart by their fingerprint.
+        let safe = prepared.method == Method::Get || prepared.method == Method::Head;
+        let memo = match self.memo {
+            Some(ref memo) if safe && prepared.body_source.is_none() => memo,
+            _ => return self.send_cached(prepared),
+        };
+        let key = memo::fingerprint(&prepared);
+        let (url, method) = (prepared.url.clone(), prepared.method.clone());
+        if let Some(memoized) = memo.get(&key, self.clock.now()) {
+            return from_stored(&url, &method, &memoized);
+        }
+        let mut response = try!(self.send_cached(prepared));
+        if response.status.class() != StatusClass::Success {
+            return Ok(response);
+        }
+        let memoized = try!(read_stored(&mut response, self.clock.unix_time()));
+        let rebuilt = from_stored(&url, &method, &memoized);
+        memo.put(key, memoized, self.clock.now());
+        rebuilt
+    }
+
+    fn send_cached(&self, mut prepared: PreparedRequest) -> CursResult<Response> {
+        let cache_control = first_raw_header(&prepared.headers, "Cache-Control")
+                                .map(|value| value.to_lowercase())
+                                .unwrap_or_else(String::new);
+        let policy = prepared.cache_policy
+                             .or_else(|| cache::requested_policy(&cache_control))
+                             .unwrap_or(self.cache_policy);
+        // The request's own max-age makes older responses stale, whatever they said.
+        let max_age = cache::directive_seconds(&cache_control, "max-age");
+        let offline = policy == CachePolicy::OnlyIfCached;
+        let store = match self.cache {
+            Some(ref store) if prepared.method == Method::Get => store,
+            None if prepared.method == Method::Get && offline => return not_cached(&prepared.url),
+            _ => return self.send_uncached(prepared),
+        };
+        let url = prepared.url.clone();
+        let request_headers = prepared.headers.clone();
+        // Responses that vary are also kept under their url alone, to know what
+        // they vary on before looking for the one that fits.
+        let by_url = try!(store.get(&url));
+        let key = cache_key(&url, &request_headers, by_url.as_ref().and_then(|c| c.header("Vary")));
+        let cached = if key == url {
+            by_url
+        } else {
+            try!(store.get(&key))
+        };
+        let credentials = self.sends_credentials(&prepared);
+        let caller_validates = prepared.headers.get_raw("If-None-Match").is_some() ||
+                               prepared.headers.get_raw("If-Modified-Since").is_some();
+        let now = self.clock.unix_time();
+        let freshness = cached.as_ref().map(|c| {
+            match max_age {
+                Some(max_age) if now - c.stored_at >= max_age => Freshness::Stale,
+                _ => c.freshness_at(now),
+            }
+        });
+        match (policy, freshness) {
+            (CachePolicy::OnlyIfCached, None) => return not_cached(&url),
+            (CachePolicy::OnlyIfCached, Some(_)) => return from_cache(&url, &cached.unwrap()),
+            (CachePolicy::Normal, Some(Freshness::Fresh)) |
+            (CachePolicy::StaleIfError, Some(Freshness::Fresh)) if !caller_validates => {
+                return from_cache(&url, &cached.unwrap());
+            }
+            (CachePolicy::Normal, Some(Freshness::StaleWhileRevalidate)) |
+            (CachePolicy::StaleIfError, Some(Freshness::StaleWhileRevalidate))
+                if !caller_validates => {
+                self.revalidate_in_background(&prepared);
+                return from_cache(&url, &cached.unwrap());
+            }
+            _ => {}
+        }
+        if let (Some(cached), false) = (cached.as_ref(), caller_validates) {
+            if let Some(etag) = cached.header("ETag") {
+                prepared.headers.set_raw("If-None-Match", vec![etag.as_bytes().to_vec()]);
+            }
+            if let Some(date) = cached.header("Last-Modified") {
+                prepared.headers.set_raw("If-Modified-Since", vec![date.as_bytes().to_vec()]);
+            }
+        }
+
+        let mut response = match self.send_uncached(prepared) {
+            Ok(response) => response,
+            Err(CursError::Network(_)) if policy == CachePolicy::StaleIfError &&
+                                          cached.is_some() => {
+                return from_cache(&url, &cached.unwrap());
+            }
+            Err(error) => return Err(error),
+        };
+        let cached = match (response.status, cached) {
+            (StatusCode::NotModified, Some(mut cached)) if !caller_validates => {
+                // Revalidated, so it's fresh again for another max-age.
+                cached.stored_at = self.clock.unix_time();
+                try!(store.put(&key, &cached));
+                cached
+            }
+            (StatusCode::Ok, _) if is_storable(&response) &&
+                                   (!credentials || is_public(&response)) => {
+                let fresh = try!(read_stored(&mut response, self.clock.unix_time()));
+                let key = cache_key(&url, &request_headers, fresh.header("Vary"));
+                if key != url {
+                    try!(store.put(&url, &fresh));
+                }
+                try!(store.put(&key, &fresh));
+                fresh
+            }
+            _ => return Ok(response),
+        };
+        from_cache(&url, &cached)
+    }
+
+    // Whether the request goes out with an Authorization header, its own or one
+    // the Client adds. Signers are taken to add one.
+    fn sends_credentials(&self, prepared: &PreparedRequest) -> bool {
+        let netrc = self.netrc.as_ref().map_or(false, |netrc| {
+            let host = Url::parse(&prepared.url).ok().and_then(|u| u.serialize_host());
+            host.map_or(false, |host| netrc.lookup(&host).is_some())
+        });
+        prepared.headers.get_raw("Authorization").is_some() ||
+        self.headers.get_raw("Authorization").is_some() || self.signer.is_some() ||
+        self.tokens.is_some() || netrc
+    }
+
+    /// A new Client with this one's proxies, TLS settings, timeouts, redirect
+    /// policy, resolver, clock, default headers and audit sink, and a connection
+    /// pool of its own, for sending from other threads. Signers, credentials,
+    /// retries and caching don't come along, see try_clone for a Client that has
+    /// them too.
+    pub fn sibling(&self) -> CursResult<Client> {
+        let mut client = Client::new();
+        client.resolver = self.resolver.clone();
+        client.clock = self.clock.clone();
+        client.read_timeout = self.read_timeout;
+        client.write_timeout = self.write_timeout;
+        client.redirects = self.redirects;
+        try!(client.rebuild(self.proxies.clone(), self.tls.clone()));
+        client.base = self.base.clone();
+        client.headers = self.headers.clone();
+        client.audit = self.audit.clone();
+        client.max_request_bytes = self.max_request_bytes;
+        client.stream_threshold = self.stream_threshold;
+        Ok(client)
+    }
+
+    /// A new Client set up like this one in every way, with a connection pool of
+    /// its own, for sending from other threads. Its signer, credentials, token
+    /// store, retry policy, validators, body transformers, cache, memo, rate limiter
+    /// and max_concurrent limit are shared with this one.
+    pub fn try_clone(&self) -> CursResult<Client> {
+        let mut client = try!(self.sibling());
+        client.signer = self.signer.clone();
+        client.netrc = self.netrc.clone();
+        if self.credentials.is_some() {
+            // Proxies ask the provider too, so the hyper client needs it.
+            client.credentials = self.credentials.clone();
+            try!(client.rebuild(self.proxies.clone(), self.tls.clone()));
+        }
+        client.tokens = self.tokens.clone();
+        client.gate = self.gate.clone();
+        client.rate_limiter = self.rate_limiter.clone();
+        client.retry = self.retry.clone();
+        client.retry_budget = self.retry_budget;
+        client.resend_after_reset = self.resend_after_reset;
+        client.cache = self.cache.clone();
+        client.cache_policy = self.cache_policy;
+        client.memo = self.memo.clone();
+        client.validators = self.validators.clone();
+        client.transformers = self.transformers.clone();
+        client.file_urls = self.file_urls;
+        Ok(client)
+    }
+
+    // Revalidates on a thread of its own, with a sibling Client sharing the store.
+    // Anything signers and credentials added to the request comes along.
+    fn revalidate_in_background(&self, prepared: &PreparedRequest) {
+        let store = match self.cache {
+            Some(ref store) => store.clone(),
+            None => return,
+        };
+        let mut client = match self.sibling() {
+            Ok(client) => client,
+            Err(_) => return,
+        };
+        client.cache = Some(store);
+        let revalidation = PreparedRequest {
+            method: Method::Get,
+            url: prepared.url.clone(),
+            headers: prepared.headers.clone(),
+            body: None,
+            body_source: None,
+            proxy: prepared.proxy.clone(),
+            sni_hostname: prepared.sni_hostname.clone(),
+            verify_hostname: prepared.verify_hostname.clone(),
+            priority: Priority::Background,
+            retry_non_idempotent: false,
+            cache_policy: Some(CachePolicy::Revalidate),
+            audit_tags: prepared.audit_tags.clone(),
+            webhook_signer: None,
+        };
+        thread::spawn(move || {
+            let _ = client.send_prepared(revalidation);
+        });
+    }
+
+    fn send_uncached(&self, mut prepared: PreparedRequest) -> CursResult<Response> {
+        for default in self.headers.iter() {
+            if prepared.headers.get_raw(default.name()).is_none() {
+                let value = default.value_string().into_bytes();
+                prepared.headers.set_raw(default.name().to_string(), vec![value]);
+            }
+        }
+        if let Some(ref netrc) = self.netrc {
+            let host = Url::parse(&prepared.url).ok().and_then(|u| u.serialize_host());
+            let entry = host.as_ref().and_then(|h| netrc.lookup(h));
+            if let Some(entry) = entry {
+                if prepared.headers.get_raw("Authorization").is_none() {
+                    prepared.headers.set(Authorization(Basic {
+                        username: entry.login.clone(),
+                        password: entry.password.as_ref().map(|p| p.to_string()),
+                    }));
+                }
+            }
+        }
+        if let Some((ref store, ref key)) = self.tokens {
+            if prepared.headers.get_raw("Authorization").is_none() {
+                if let Some(token) = try!(store.load(key)) {
+                    let bearer = Bearer { token: token.access_token.to_string() };
+                    prepared.headers.set(Authorization(bearer));
+                }
+            }
+        }
+
+        let response = try!(self.send_redirected(&prepared));
+        // Only plain http requests sent to a proxy in absolute form get its 407s,
+        // the ones for tunnels are answered while opening them, see
+        // proxy::ProxyConnector.
+        let proxy = response.status == StatusCode::ProxyAuthenticationRequired;
+        if response.status != StatusCode::Unauthorized && !proxy {
+            return Ok(response);
+        }
+        let credentials = self.credentials.as_ref().and_then(|provider| {
+            let authenticate = if proxy { "Proxy-Authenticate" } else { "WWW-Authenticate" };
+            provider.credentials(&Challenge {
+                url: &prepared.url,
+                proxy: proxy,
+                authenticate: first_raw_header(&response.headers, authenticate),
+            })
+        });
+        match credentials {
+            Some(credentials) => {
+                let basic = credentials.basic();
+                let header = if proxy { "Proxy-Authorization" } else { "Authorization" };
+                prepared.headers.set_raw(header, vec![basic.as_bytes().to_vec()]);
+                self.send_redirected(&prepared)
+            }
+            None => Ok(response),
+        }
+    }
+
+    fn send_redirected(&self, prepared: &PreparedRequest) -> CursResult<Response> {
+        let mut response = try!(self.send_with_retries(prepared, true));
+        let mut hop = prepared.reborrow();
+        // Once off the original origin the signer's headers would be credentials
+        // handed to someone else, so it doesn't run again.
+        let mut sign = true;
+        for _ in 0..MAX_REDIRECTS {
+            let code = response.status.to_u16();
+            if code != 301 && code != 302 && code != 303 && code != 307 && code != 308 {
+                break;
+            }
+            let next = match response.headers.get::<Location>() {
+                Some(&Location(ref location)) => {
+                    match Url::parse(&hop.url).and_then(|url| url.join(location)) {
+                        Ok(next) => next,
+                        Err(_) => break,
+                    }
+                }
+                None => break,
+            };
+            let follow = match self.redirects {
+                RedirectPolicy::FollowAll => true,
+                RedirectPolicy::FollowIf(follow) => follow(&next),
+                RedirectPolicy::FollowNone => false,
+            };
+            if !follow || (next.scheme != "http" && next.scheme != "https") {
+                break;
+            }
+            let next = next.serialize();
+            if origin(&hop.url) != origin(&next) {
+                for name in &["Authorization", "Proxy-Authorization", "Cookie"] {
+                    hop.headers.remove_raw(name);
+                }
+                sign = false;
+            }
+            let to_get = code == 303 || (code != 307 && code != 308 && hop.method == Method::Post);
+            if to_get && hop.method != Method::Head {
+                hop.method = Method::Get;
+                hop.body = None;
+                hop.body_source = None;
+                hop.headers.remove::<ContentType>();
+            }
+            hop.url = next;
+            response = try!(self.send_with_retries(&hop, sign));
+        }
+        Ok(response)
+    }
+
+    fn send_with_retries(&self, prepared: &PreparedRequest, sign: bool) -> CursResult<Response> {
+        // Sending a POST twice could charge a card twice, so those need an opt in.
+        let repeatable = is_idempotent(&prepared.method) || prepared.retry_non_idempotent ||
+                         prepared.headers.get_raw("Idempotency-Key").is_some();
+        let policy = match self.retry {
+            Some(ref policy) if repeatable => policy,
+            _ => return self.send_resending(prepared, repeatable, sign),
+        };
+        let started = self.clock.now();
+        let mut number = 1;
+        let mut previous_delay = None;
+        loop {
+            let result = self.send_resending(prepared, repeatable, sign);
+            let failed = match result {
+                Ok(ref response) => {
+                    match response.status.class() {
+                        StatusClass::ClientError | StatusClass::ServerError |
+                        StatusClass::NoClass => true,
+                        _ => false,
+                    }
+                }
+                Err(_) => true,
+            };
+            if !failed {
+                return result;
+            }
+            let decision = {
+                let (outcome, asked) = match result {
+                    Ok(ref response) => {
+                        let asked = retry_after(&response.headers, &*self.clock);
+                        (Outcome::Status(response.status), asked)
+                    }
+                    Err(ref error) => (Outcome::Error(error), None),
+                };
+                policy.decide(&Attempt {
+                    number: number,
+                    method: &prepared.method,
+                    url: &prepared.url,
+                    outcome: outcome,
+                    elapsed: clock::since(&*self.clock, started),
+                    previous_delay: previous_delay,
+                    retry_after: asked,
+                })
+            };
+            match decision {
+                RetryDecision::Retry(delay) => {
+                    let over_budget = self.retry_budget
+                                          .map(|budget| {
+                                              clock::since(&*self.clock, started) + delay > budget
+                                          })
+                                          .unwrap_or(false);
+                    if over_budget {
+                        let last = match result {
+                            Ok(response) => CursError::Status(response),
+                            Err(error) => error,
+                        };
+                        return Err(CursError::RetriesExhausted(number, Box::new(last)));
+                    }
+                    self.clock.sleep(delay);
+                    previous_delay = Some(delay);
+                }
+                RetryDecision::Stop => return result,
+            }
+            number += 1;
+        }
+    }
+
+    fn send_resending(&self,
+                      prepared: &PreparedRequest,
+                      repeatable: bool,
+                      sign: bool)
+                      -> CursResult<Response> {
+        match self.sign_and_send(prepared, sign) {
+            Err(ref error) if self.resend_after_reset && repeatable &&
+                              is_connection_reset(error) => self.sign_and_send(prepared, sign),
+            result => result,
+        }
+    }
+
+    fn sign_and_send(&self, prepared: &PreparedRequest, sign: bool) -> CursResult<Response> {
+        let mut signed = prepared.reborrow();
+        match self.signer {
+            Some(ref signer) if sign => signer(&mut signed),
+            _ => {}
+        }
+        try!(check_headers(&signed.headers));
+        let PreparedRequest { method, url, headers, body, body_source, proxy, sni_hostname,
+                              verify_hostname, priority, .. } = signed;
+        if let Some(ref limiter) = self.rate_limiter {
+            let host = Url::parse(&url).ok().and_then(|u| u.serialize_host());
+            limiter.wait(host.as_ref().map(|h| &**h).unwrap_or(""));
+        }
+        let _turn = self.gate.as_ref().map(|gate| gate.enter(priority));
+        // Requests with proxies or TLS names of their own get a hyper client just
+        // for them, set up like the Client's.
+        let own_hyper;
+        let hyper = if proxy.is_some() || sni_hostname.is_some() || verify_hostname.is_some() {
+            let mut tls = self.tls.clone();
+            tls.sni_hostname = sni_hostname.or(tls.sni_hostname);
+            tls.verify_hostname = verify_hostname.or(tls.verify_hostname);
+            let proxies = proxy.unwrap_or(self.proxies.clone());
+            let (resolver, credentials) = (self.resolver.clone(), self.credentials.clone());
+            let mut hyper = try!(Client::hyper_client(proxies, &tls, resolver, credentials)).0;
+            self.set_timeouts(&mut hyper);
+            own_hyper = hyper;
+            &own_hyper
+        } else {
+            &self.hyper
+        };
+        let mut source = match (body.as_ref(), body_source) {
+            (None, Some(source)) => Some(try!(source.open())),
+            _ => None,
+        };
+        if let Some(limit) = self.max_request_bytes {
+            let length = match (body.as_ref(), source.as_ref()) {
+                (Some(body), _) => Some(body.len() as u64),
+                (None, Some(source)) => source.length,
+                (None, None) => None,
+            };
+            match length {
+                Some(length) if length > limit => {
+                    return Err(CursError::InvalidInput(format!("The body is {} bytes, more \
+                                                                than the {} allowed",
+                                                               length,
+                                                               limit)));
+                }
+                None => {
+                    source = source.map(|open| {
+                        OpenBody {
+                            reader: Box::new(Capped {
+                                inner: open.reader,
+                                left: limit,
+                            }),
+                            length: None,
+                        }
+                    });
+                }
+                _ => {}
+            }
+        }
+        let mut request = hyper.request(method, &*url).headers(headers);
+        if let Some(ref body) = body {
+            request = request.body(&**body);
+        } else if let Some(ref mut source) = source {
+            request = request.body(match source.length {
+                Some(length) => Body::SizedBody(&mut source.reader, length),
+                None => Body::ChunkedBody(&mut source.reader),
+            });
+        }
+        Ok(try!(request.send()))
+    }
+}
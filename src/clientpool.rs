@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::CursResult;
+use client::Client;
+use clock::{Clock, SystemClock};
+
+struct Entry {
+    client: Arc<Client>,
+    /// Since the unix epoch, on the pool's clock.
+    last_used: Duration,
+    used: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    map: HashMap<String, Entry>,
+    // Ticks on every use, so the least recently used entry has the lowest `used`.
+    clock: u64,
+}
+
+/// Clients for many tenants, like the customers a SaaS backend calls APIs for,
+/// each with its own credentials, token store and connections. They're made the
+/// first time a tenant's key is asked for and shared after that. Past `capacity`
+/// tenants the least recently used one is dropped, and so are those unused for
+/// longer than the idle timeout, if there's one. Requests still using a dropped
+/// Client finish fine, the next one for that tenant gets a new Client.
+///
+/// ```no_run
+/// # use curs::{Client, ClientPool};
+/// # use curs::hyper::header::Authorization;
+/// # fn api_key_for(tenant: &str) -> String { tenant.to_string() }
+/// let pool = ClientPool::new(500, |tenant: &str| {
+///     let mut client = Client::new();
+///     client.header(Authorization(format!("Token {}", api_key_for(tenant))));
+///     Ok(client)
+/// });
+/// let client = pool.get("acme").unwrap();
+/// client.get("https://api.example.com/invoices").send().unwrap();
+/// ```
+pub struct ClientPool {
+    make: Box<Fn(&str) -> CursResult<Client> + Send + Sync>,
+    capacity: usize,
+    idle_timeout: Option<Duration>,
+    entries: Mutex<Entries>,
+    clock: Arc<Clock + Send + Sync>,
+}
+
+impl ClientPool {
+    /// `make` sets up the Client for a tenant's key.
+    pub fn new<F>(capacity: usize, make: F) -> ClientPool
+        where F: Fn(&str) -> CursResult<Client> + Send + Sync + 'static
+    {
+        ClientPool {
+            make: Box::new(make),
+            capacity: capacity,
+            idle_timeout: None,
+            entries: Mutex::new(Entries::default()),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Drop the Clients of tenants that went this long without a request.
+    pub fn idle_timeout(self, timeout: Duration) -> ClientPool {
+        ClientPool { idle_timeout: Some(timeout), ..self }
+    }
+
+    /// Tell time with this clock instead of the system's.
+    pub fn with_clock(self, clock: Arc<Clock + Send + Sync>) -> ClientPool {
+        ClientPool { clock: clock, ..self }
+    }
+
+    /// The tenant's Client, made now if there's none. Clients are made without
+    /// holding up the pool, if two threads make one for the same tenant at once
+    /// the first one in is kept.
+    pub fn get(&self, key: &str) -> CursResult<Arc<Client>> {
+        if let Some(client) = self.cached(key) {
+            return Ok(client);
+        }
+        let client = Arc::new(try!((self.make)(key)));
+        let now = self.clock.now();
+        let mut entries = self.entries.lock().unwrap();
+        self.expire(&mut entries, now);
+        entries.clock += 1;
+        let used = entries.clock;
+        if let Some(entry) = entries.map.get_mut(key) {
+            entry.last_used = now;
+            entry.used = used;
+            return Ok(entry.client.clone());
+        }
+        if entries.map.len() >= self.capacity {
+            let oldest = entries.map
+                                .iter()
+                                .min_by_key(|&(_, entry)| entry.used)
+                                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.map.remove(&oldest);
+            }
+        }
+        entries.map.insert(key.to_string(),
+                           Entry {
+                               client: client.clone(),
+                               last_used: now,
+                               used: used,
+                           });
+        Ok(client)
+    }
+
+    /// Drop this tenant's Client, like when its credentials change.
+    pub fn evict(&self, key: &str) {
+        self.entries.lock().unwrap().map.remove(key);
+    }
+
+    /// How many tenants have a Client now.
+    pub fn len(&self) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        self.expire(&mut entries, self.clock.now());
+        entries.map.len()
+    }
+
+    fn cached(&self, key: &str) -> Option<Arc<Client>> {
+        let now = self.clock.now();
+        let mut entries = self.entries.lock().unwrap();
+        self.expire(&mut entries, now);
+        entries.clock += 1;
+        let used = entries.clock;
+        entries.map.get_mut(key).map(|entry| {
+            entry.last_used = now;
+            entry.used = used;
+            entry.client.clone()
+        })
+    }
+
+    fn expire(&self, entries: &mut Entries, now: Duration) {
+        if let Some(timeout) = self.idle_timeout {
+            let idle: Vec<String> = entries.map
+                                           .iter()
+                                           .filter(|&(_, entry)| {
+                                               now >= entry.last_used &&
+                                               now - entry.last_used >= timeout
+                                           })
+                                           .map(|(key, _)| key.clone())
+                                           .collect();
+            for key in idle {
+                entries.map.remove(&key);
+            }
+        }
+    }
+}
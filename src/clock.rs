@@ -0,0 +1,69 @@
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Where retries, caching, memoizing and rate limiting get the time from, and how
+/// they wait. SystemClock unless told otherwise, see Client::clock.
+pub trait Clock {
+    /// Time since the unix epoch.
+    fn now(&self) -> Duration;
+    fn sleep(&self, duration: Duration);
+
+    /// The unix timestamp, in seconds.
+    fn unix_time(&self) -> i64 {
+        self.now().as_secs() as i64
+    }
+}
+
+/// The operating system's clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        // A clock set before 1970 is as good as one set at 1970.
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::new(0, 0))
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+/// A clock that only moves when told to, for tests. Sleeping moves it forward
+/// instead of waiting.
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<Duration>,
+}
+
+impl ManualClock {
+    /// Starting at this time since the unix epoch.
+    pub fn new(now: Duration) -> ManualClock {
+        ManualClock { now: Mutex::new(now) }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        *self.now.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration)
+    }
+}
+
+/// Time from `earlier` to now on this clock, nothing if the clock went back.
+pub fn since(clock: &Clock, earlier: Duration) -> Duration {
+    let now = clock.now();
+    if now > earlier {
+        now - earlier
+    } else {
+        Duration::new(0, 0)
+    }
+}
@@ -0,0 +1,169 @@
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+
+use hyper::header::Headers;
+use toml::{Parser, Table, Value};
+use url::Url;
+
+use super::{Client, CursError, CursResult};
+use proxy::{NoProxy, ProxySettings, parse_proxy_url};
+
+/// Settings for building a Client, as read from a curs.toml file.
+/// Top level keys apply to every profile, `[profiles.<name>]` tables override them:
+///
+/// ```toml
+/// base_url = "https://api.example.com/v1/"
+/// timeout = 30
+///
+/// [headers]
+/// User-Agent = "my-tool"
+///
+/// [profiles.staging]
+/// base_url = "https://staging.example.com/v1/"
+/// proxy = "http://proxy.corp:3128"
+/// no_proxy = "localhost,.corp"
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    pub base_url: Option<String>,
+    pub headers: Vec<(String, String)>,
+    pub proxy: Option<String>,
+    pub no_proxy: Option<String>,
+    /// Read and write timeout, in seconds.
+    pub timeout: Option<u64>,
+}
+
+fn invalid(key: &str, expected: &str) -> CursError {
+    CursError::Config(format!("{} should be {}", key, expected))
+}
+
+fn string(table: &Table, key: &str) -> CursResult<Option<String>> {
+    match table.get(key) {
+        Some(value) => {
+            value.as_str().map(|s| Some(s.to_string())).ok_or_else(|| invalid(key, "a string"))
+        }
+        None => Ok(None),
+    }
+}
+
+impl Profile {
+    fn merge(&mut self, table: &Table) -> CursResult<()> {
+        if let Some(base_url) = try!(string(table, "base_url")) {
+            self.base_url = Some(base_url);
+        }
+        if let Some(proxy) = try!(string(table, "proxy")) {
+            self.proxy = Some(proxy);
+        }
+        if let Some(no_proxy) = try!(string(table, "no_proxy")) {
+            self.no_proxy = Some(no_proxy);
+        }
+        if let Some(timeout) = table.get("timeout") {
+            let seconds = try!(timeout.as_integer()
+                                      .and_then(|t| if t >= 0 { Some(t as u64) } else { None })
+                                      .ok_or_else(|| invalid("timeout", "a number of seconds")));
+            self.timeout = Some(seconds);
+        }
+        if let Some(headers) = table.get("headers") {
+            let headers = try!(headers.as_table().ok_or_else(|| invalid("headers", "a table")));
+            for (name, value) in headers {
+                let value = try!(value.as_str().ok_or_else(|| invalid(name, "a string")));
+                let lowercase = name.to_lowercase();
+                self.headers.retain(|&(ref existing, _)| existing.to_lowercase() != lowercase);
+                self.headers.push((name.clone(), value.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// A new Client with these settings.
+    pub fn client(&self) -> CursResult<Client> {
+        let mut client = Client::new();
+        if let Some(ref proxy) = self.proxy {
+            let proxy = try!(parse_proxy_url(proxy));
+            client.proxy(ProxySettings {
+                http: Some(proxy.clone()),
+                https: Some(proxy),
+                no_proxy: NoProxy::parse(self.no_proxy.as_ref().map(|n| &**n).unwrap_or("")),
+            });
+        }
+        if let Some(ref base_url) = self.base_url {
+            client.base_url(try!(Url::parse(base_url)));
+        }
+        if let Some(timeout) = self.timeout {
+            client.read_timeout(Some(Duration::from_secs(timeout)))
+                  .write_timeout(Some(Duration::from_secs(timeout)));
+        }
+        let mut headers = Headers::new();
+        for &(ref name, ref value) in &self.headers {
+            headers.set_raw(name.clone(), vec![value.clone().into_bytes()]);
+        }
+        client.headers(&headers);
+        Ok(client)
+    }
+}
+
+/// A parsed curs.toml file.
+#[derive(Clone, Debug)]
+pub struct Config {
+    table: Table,
+}
+
+impl Config {
+    pub fn parse(contents: &str) -> CursResult<Config> {
+        let mut parser = Parser::new(contents);
+        match parser.parse() {
+            Some(table) => Ok(Config { table: table }),
+            None => {
+                let errors: Vec<String> = parser.errors.iter().map(|e| e.to_string()).collect();
+                Err(CursError::Config(errors.join(", ")))
+            }
+        }
+    }
+
+    pub fn load(path: &Path) -> CursResult<Config> {
+        let mut contents = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut contents));
+        Config::parse(&contents)
+    }
+
+    /// Loads the file named in CURS_CONFIG, or ./curs.toml.
+    /// It's fine for ./curs.toml not to exist, you just get None.
+    pub fn discover() -> CursResult<Option<Config>> {
+        if let Ok(path) = env::var("CURS_CONFIG") {
+            return Config::load(Path::new(&path)).map(Some);
+        }
+        let path = Path::new("curs.toml");
+        if path.exists() {
+            Config::load(path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// The top level settings merged with those of the named profile.
+    /// Asking for "default" works even when there's no such profile.
+    pub fn profile(&self, name: &str) -> CursResult<Profile> {
+        let mut profile = Profile::default();
+        try!(profile.merge(&self.table));
+        match self.table.get("profiles").and_then(|p| p.as_table()).and_then(|p| p.get(name)) {
+            Some(&Value::Table(ref overrides)) => try!(profile.merge(overrides)),
+            Some(_) => return Err(invalid(&format!("profiles.{}", name), "a table")),
+            None if name == "default" => {}
+            None => return Err(CursError::Config(format!("No profile named {}", name))),
+        }
+        Ok(profile)
+    }
+}
+
+/// A Client configured from curs.toml (see Config::discover) with the profile
+/// named in CURS_PROFILE, or "default". Without a config file it's just Client::new().
+pub fn client_from_env() -> CursResult<Client> {
+    let profile = env::var("CURS_PROFILE").unwrap_or("default".to_string());
+    match try!(Config::discover()) {
+        Some(config) => try!(config.profile(&profile)).client(),
+        None => Ok(Client::new()),
+    }
+}
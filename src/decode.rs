@@ -0,0 +1,264 @@
+use std::fmt;
+use std::str::FromStr;
+
+use hyper::header::Headers;
+use serde::{Deserialize, Deserializer};
+use serde::de::Error as DeError;
+use serde_json::{self, Value};
+
+use super::{CursError, CursResult};
+
+/// Turns a successful response's body into a value, see
+/// DecodableResult::decode_success_with. Implement it for other formats, or for
+/// APIs that wrap everything in an envelope.
+pub trait Decoder {
+    fn decode<T: Deserialize>(&self, body: &[u8], headers: &Headers) -> CursResult<T>;
+}
+
+// How much of the body around the problem a JsonError keeps, each way.
+const SNIPPET_RADIUS: usize = 40;
+
+/// A response body that isn't the JSON expected, with where it went wrong and a
+/// bit of the body around there, to see what changed in the API without dumping
+/// the whole body.
+#[derive(Debug)]
+pub struct JsonError {
+    pub error: serde_json::Error,
+    /// Both count from 1, and are 0 when serde doesn't know where it was.
+    pub line: usize,
+    pub column: usize,
+    /// Up to 80 bytes of the body around line and column, or from its start.
+    pub snippet: String,
+    /// The field that didn't match the type, like `orders[3].price`. Empty for
+    /// the top level, or when serde doesn't know where it was.
+    pub path: String,
+}
+
+impl JsonError {
+    pub fn new(error: serde_json::Error, body: &[u8]) -> JsonError {
+        let (line, column) = match error {
+            serde_json::Error::Syntax(_, line, column) => (line, column),
+            serde_json::Error::Io(_) => (0, 0),
+        };
+        let mut offset = 0;
+        if line > 0 {
+            offset = body.split(|byte| *byte == b'\n')
+                         .take(line - 1)
+                         .map(|line| line.len() + 1)
+                         .sum::<usize>() + column;
+        }
+        let offset = offset.min(body.len());
+        let start = offset.saturating_sub(SNIPPET_RADIUS);
+        let end = (offset + SNIPPET_RADIUS).min(body.len());
+        let snippet = String::from_utf8_lossy(&body[start..end])
+                          .chars()
+                          .map(|c| if c.is_control() { ' ' } else { c })
+                          .collect();
+        let path = if line > 0 { path_at(&body[..offset]) } else { String::new() };
+        JsonError {
+            error: error,
+            line: line,
+            column: column,
+            snippet: snippet,
+            path: path,
+        }
+    }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}, near `{}`", self.error, self.snippet)
+        } else {
+            write!(f, "{} at {}, near `{}`", self.error, self.path, self.snippet)
+        }
+    }
+}
+
+enum Frame {
+    Object { key: Option<String>, in_value: bool },
+    Array(usize),
+}
+
+// Where in the document the end of this bit of JSON is, like `orders[3].price`.
+fn path_at(json: &[u8]) -> String {
+    let mut frames = vec![];
+    let mut i = 0;
+    while i < json.len() {
+        match json[i] {
+            b'{' => frames.push(Frame::Object { key: None, in_value: false }),
+            b'[' => frames.push(Frame::Array(0)),
+            b'}' | b']' => {
+                frames.pop();
+            }
+            b':' => {
+                if let Some(&mut Frame::Object { ref mut in_value, .. }) = frames.last_mut() {
+                    *in_value = true;
+                }
+            }
+            b',' => {
+                match frames.last_mut() {
+                    Some(&mut Frame::Object { ref mut in_value, .. }) => *in_value = false,
+                    Some(&mut Frame::Array(ref mut index)) => *index += 1,
+                    None => {}
+                }
+            }
+            b'"' => {
+                let start = i + 1;
+                i = start;
+                while i < json.len() && json[i] != b'"' {
+                    i += if json[i] == b'\\' { 2 } else { 1 };
+                }
+                if let Some(&mut Frame::Object { ref mut key, in_value: false }) =
+                       frames.last_mut() {
+                    let end = i.min(json.len());
+                    *key = Some(String::from_utf8_lossy(&json[start..end]).into_owned());
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    let mut path = String::new();
+    for frame in &frames {
+        match *frame {
+            Frame::Object { key: Some(ref key), .. } => {
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(key);
+            }
+            Frame::Object { key: None, .. } => {}
+            Frame::Array(index) => path.push_str(&format!("[{}]", index)),
+        }
+    }
+    path
+}
+
+/// Deserializes a response body, failing with a JsonError that tells where.
+pub fn from_json<T: Deserialize>(body: &[u8]) -> CursResult<T> {
+    serde_json::from_slice(body).map_err(|error| CursError::JsonBody(JsonError::new(error, body)))
+}
+
+/// Plain JSON, what decode_success uses.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonDecoder;
+
+impl Decoder for JsonDecoder {
+    fn decode<T: Deserialize>(&self, body: &[u8], _: &Headers) -> CursResult<T> {
+        from_json(body)
+    }
+}
+
+/// JSON as some APIs send it: with NaN, Infinity and -Infinity, which become
+/// nulls, and trailing commas in objects and arrays. Unknown fields are always
+/// fine. For numbers sent as strings, see number_or_string.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LenientJson;
+
+impl Decoder for LenientJson {
+    fn decode<T: Deserialize>(&self, body: &[u8], _: &Headers) -> CursResult<T> {
+        from_json(&relax(body))
+    }
+}
+
+const NON_FINITE: &'static [&'static [u8]] = &[b"-Infinity", b"Infinity", b"NaN"];
+
+// The body as strict JSON, leaving strings alone.
+fn relax(body: &[u8]) -> Vec<u8> {
+    let mut relaxed = Vec::with_capacity(body.len());
+    let (mut in_string, mut escaped) = (false, false);
+    let mut i = 0;
+    while i < body.len() {
+        let byte = body[i];
+        if in_string {
+            relaxed.push(byte);
+            if escaped {
+                escaped = false;
+            } else if byte == b'\\' {
+                escaped = true;
+            } else if byte == b'"' {
+                in_string = false;
+            }
+            i += 1;
+            continue;
+        }
+        if let Some(token) = NON_FINITE.iter().find(|token| body[i..].starts_with(token)) {
+            relaxed.extend_from_slice(b"null");
+            i += token.len();
+            continue;
+        }
+        if byte == b',' {
+            let next = body[i + 1..].iter().find(|b| !(**b as char).is_whitespace());
+            if next == Some(&b'}') || next == Some(&b']') {
+                i += 1;
+                continue;
+            }
+        }
+        in_string = byte == b'"';
+        relaxed.push(byte);
+        i += 1;
+    }
+    relaxed
+}
+
+/// For fields holding numbers that some APIs send as strings, like `"12.50"`, with
+/// `#[serde(deserialize_with = "curs::decode::number_or_string")]`. Takes real
+/// numbers too.
+pub fn number_or_string<T, D>(deserializer: &mut D) -> Result<T, D::Error>
+    where T: Deserialize + FromStr,
+          D: Deserializer
+{
+    match try!(Value::deserialize(deserializer)) {
+        Value::String(text) => {
+            text.trim()
+                .parse()
+                .map_err(|_| DeError::invalid_value(&format!("{:?} is not a number", text)))
+        }
+        value => serde_json::from_value(value).map_err(|error| DeError::custom(error.to_string())),
+    }
+}
+
+/// JSON wrapped in an envelope, decoding just this field of the top level object,
+/// like `data` in `{"data": {...}, "meta": {...}}`.
+#[derive(Clone, Copy, Debug)]
+pub struct JsonField<'a>(pub &'a str);
+
+impl<'a> Decoder for JsonField<'a> {
+    fn decode<T: Deserialize>(&self, body: &[u8], _: &Headers) -> CursResult<T> {
+        let field = match try!(from_json(body)) {
+            Value::Object(mut fields) => fields.remove(self.0),
+            _ => None,
+        };
+        match field {
+            Some(field) => Ok(try!(serde_json::from_value(field))),
+            None => Err(CursError::Decode(format!("No {} field in the response", self.0))),
+        }
+    }
+}
+
+/// Like JsonField, also keeping another field of the envelope, like its
+/// pagination meta. It decodes into a pair of the data and an Option of the
+/// meta, which is None when the response has none:
+///
+/// ```ignore
+/// let (users, meta): (Vec<User>, Option<Meta>) =
+///     try!(client.get("users").send().decode_success_with(&JsonEnvelope("data", "meta")));
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct JsonEnvelope<'a>(pub &'a str, pub &'a str);
+
+impl<'a> Decoder for JsonEnvelope<'a> {
+    fn decode<T: Deserialize>(&self, body: &[u8], _: &Headers) -> CursResult<T> {
+        let mut fields = match try!(from_json(body)) {
+            Value::Object(fields) => fields,
+            _ => return Err(CursError::Decode("The response is not a JSON object".to_string())),
+        };
+        let data = match fields.remove(self.0) {
+            Some(data) => data,
+            None => return Err(CursError::Decode(format!("No {} field in the response", self.0))),
+        };
+        let meta = fields.remove(self.1).unwrap_or(Value::Null);
+        Ok(try!(serde_json::from_value(Value::Array(vec![data, meta]))))
+    }
+}
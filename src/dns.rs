@@ -0,0 +1,328 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+
+use hyper::method::Method;
+use hyper::status::StatusCode;
+use rustc_serialize::base64::{ToBase64, URL_SAFE};
+
+use super::Request;
+
+/// Finds the addresses to connect to for a host name.
+pub trait Resolver {
+    /// Addresses for this host, the ones to try first first.
+    fn resolve(&self, host: &str) -> io::Result<Vec<IpAddr>>;
+
+    /// Whether connecting to this address is fine, be it one this resolver found
+    /// or one given in the url. All of them are unless told otherwise.
+    fn allows(&self, _address: &IpAddr) -> bool {
+        true
+    }
+
+    /// Whether the addresses found for a host should be kept while a request is
+    /// sent, retries and redirects included, instead of looking it up again for
+    /// every connection. Resolvers that check or override addresses, like
+    /// PublicOnly, want that so a DNS answer that changes halfway can't take the
+    /// request somewhere else. See pinning.
+    fn pins(&self) -> bool {
+        false
+    }
+}
+
+thread_local!(static PINS: RefCell<Option<HashMap<String, Vec<IpAddr>>>> = RefCell::new(None));
+
+// Ends the pinning scope that made it, even if sending panics.
+struct PinScope;
+
+impl Drop for PinScope {
+    fn drop(&mut self) {
+        PINS.with(|pins| *pins.borrow_mut() = None);
+    }
+}
+
+/// Runs `send` keeping the addresses found for each host, for resolvers that pin
+/// them, until it returns. Calls inside another one share its pins. Clients send
+/// every request like this.
+pub fn pinning<T, F: FnOnce() -> T>(send: F) -> T {
+    let outermost = PINS.with(|pins| {
+        let mut pins = pins.borrow_mut();
+        if pins.is_some() {
+            return false;
+        }
+        *pins = Some(HashMap::new());
+        true
+    });
+    let _scope = if outermost { Some(PinScope) } else { None };
+    send()
+}
+
+fn pinned(host: &str) -> Option<Vec<IpAddr>> {
+    PINS.with(|pins| pins.borrow().as_ref().and_then(|pins| pins.get(host).cloned()))
+}
+
+fn pin(host: &str, addresses: &[IpAddr]) {
+    PINS.with(|pins| {
+        if let Some(ref mut pins) = *pins.borrow_mut() {
+            pins.insert(host.to_string(), addresses.to_vec());
+        }
+    })
+}
+
+/// Whether an address can be reached from the internet at large, as opposed to
+/// private networks (RFC 1918, RFC 4193, carrier-grade NAT), loopback, link-local
+/// and unspecified, broadcast and multicast ones.
+pub fn is_public(address: &IpAddr) -> bool {
+    match *address {
+        IpAddr::V4(ref v4) => {
+            let octets = v4.octets();
+            let shared = octets[0] == 100 && octets[1] & 0xC0 == 64;
+            !(v4.is_private() || v4.is_loopback() || v4.is_link_local() || v4.is_broadcast() ||
+              v4.is_multicast() || octets[0] == 0 || shared)
+        }
+        IpAddr::V6(ref v6) => {
+            let first = v6.segments()[0];
+            if let Some(v4) = v6.to_ipv4() {
+                // IPv4-mapped and -compatible addresses go where their IPv4 one does.
+                return is_public(&IpAddr::V4(v4));
+            }
+            !(v6.is_loopback() || v6.is_unspecified() || v6.is_multicast() ||
+              first & 0xFE00 == 0xFC00 || first & 0xFFC0 == 0xFE80)
+        }
+    }
+}
+
+/// Refuses to connect to addresses that aren't public (see is_public), for services
+/// that fetch urls users give them and mustn't be turned against the network they
+/// run in. Every connection is checked, redirects and retries included, whether
+/// the address came from the wrapped resolver or the url. HTTP and `socks5h`
+/// proxies resolve names themselves, so only IP addresses in urls are checked
+/// when using those. See Client::block_private_addresses.
+pub struct PublicOnly(Arc<Resolver + Send + Sync>);
+
+impl PublicOnly {
+    pub fn new<R: Resolver + Send + Sync + 'static>(resolver: R) -> PublicOnly {
+        PublicOnly(Arc::new(resolver))
+    }
+
+    /// Checking what this shared resolver finds.
+    pub fn wrapping(resolver: Arc<Resolver + Send + Sync>) -> PublicOnly {
+        PublicOnly(resolver)
+    }
+}
+
+impl Resolver for PublicOnly {
+    fn resolve(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        self.0.resolve(host)
+    }
+
+    fn allows(&self, address: &IpAddr) -> bool {
+        is_public(address) && self.0.allows(address)
+    }
+
+    fn pins(&self) -> bool {
+        true
+    }
+}
+
+/// Asks the operating system, like everything else on the machine does.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        Ok(try!((host, 0).to_socket_addrs()).map(|address| address.ip()).collect())
+    }
+}
+
+/// Resolves names with DNS over HTTPS (RFC 8484), for networks where plain DNS is
+/// blocked or can't be trusted. The endpoint's own name is resolved by the system,
+/// so give it as an IP address if even that can't be trusted.
+///
+/// ```no_run
+/// # use curs::Client;
+/// # use curs::dns::DohResolver;
+/// let mut client = Client::new();
+/// client.resolver(DohResolver::new("https://1.1.1.1/dns-query"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct DohResolver {
+    endpoint: String,
+}
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+
+fn invalid(why: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, why.to_string())
+}
+
+fn read_u16(message: &[u8], at: usize) -> io::Result<u16> {
+    if at + 2 > message.len() {
+        return Err(invalid("Truncated DNS message"));
+    }
+    Ok((message[at] as u16) << 8 | message[at + 1] as u16)
+}
+
+// Where the name starting here ends. There's no need to follow compression pointers
+// for that, a pointer is always the last thing in a name.
+fn skip_name(message: &[u8], mut at: usize) -> io::Result<usize> {
+    loop {
+        match message.get(at) {
+            Some(&0) => return Ok(at + 1),
+            Some(&length) if length & 0xC0 == 0xC0 => return Ok(at + 2),
+            Some(&length) => at += 1 + length as usize,
+            None => return Err(invalid("Truncated DNS message")),
+        }
+    }
+}
+
+/// A query for one name, with recursion desired and an ID of 0 so answers can be
+/// cached by HTTP caches, as RFC 8484 recommends.
+pub fn query_message(host: &str, record_type: u16) -> Vec<u8> {
+    let mut message = vec![0, 0, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in host.trim_right_matches('.').split('.') {
+        message.push(label.len() as u8);
+        message.extend(label.as_bytes());
+    }
+    message.extend(&[0, (record_type >> 8) as u8, record_type as u8, 0, 1]);
+    message
+}
+
+/// The A and AAAA records in an answer, in the order they came.
+pub fn answer_addresses(message: &[u8]) -> io::Result<Vec<IpAddr>> {
+    let flags = try!(read_u16(message, 2));
+    match flags & 0x000F {
+        0 => {}
+        3 => return Err(io::Error::new(io::ErrorKind::NotFound, "No such domain")),
+        _ => return Err(invalid("The DNS server failed to answer")),
+    }
+    let questions = try!(read_u16(message, 4));
+    let answers = try!(read_u16(message, 6));
+    let mut at = 12;
+    for _ in 0..questions {
+        at = try!(skip_name(message, at)) + 4;
+    }
+    let mut addresses = vec![];
+    for _ in 0..answers {
+        at = try!(skip_name(message, at));
+        let record_type = try!(read_u16(message, at));
+        let length = try!(read_u16(message, at + 8)) as usize;
+        at += 10;
+        if at + length > message.len() {
+            return Err(invalid("Truncated DNS message"));
+        }
+        let data = &message[at..at + length];
+        match (record_type, length) {
+            (TYPE_A, 4) => {
+                addresses.push(IpAddr::V4(Ipv4Addr::new(data[0], data[1], data[2], data[3])))
+            }
+            (TYPE_AAAA, 16) => {
+                let mut segments = [0u16; 8];
+                for (i, segment) in segments.iter_mut().enumerate() {
+                    *segment = (data[i * 2] as u16) << 8 | data[i * 2 + 1] as u16;
+                }
+                addresses.push(IpAddr::V6(Ipv6Addr::new(segments[0],
+                                                        segments[1],
+                                                        segments[2],
+                                                        segments[3],
+                                                        segments[4],
+                                                        segments[5],
+                                                        segments[6],
+                                                        segments[7])))
+            }
+            // CNAMEs and such, the addresses they lead to come along anyway.
+            _ => {}
+        }
+        at += length;
+    }
+    Ok(addresses)
+}
+
+impl DohResolver {
+    /// The endpoint is the full url, like `https://dns.example.com/dns-query`.
+    pub fn new(endpoint: &str) -> DohResolver {
+        DohResolver { endpoint: endpoint.to_string() }
+    }
+
+    fn lookup(&self, host: &str, record_type: u16) -> io::Result<Vec<IpAddr>> {
+        let query = query_message(host, record_type).to_base64(URL_SAFE);
+        let separator = if self.endpoint.contains('?') { '&' } else { '?' };
+        let url = format!("{}{}dns={}", self.endpoint, separator, query);
+        let sent = Request::new(Method::Get, &url)
+                       .raw_header("Accept", "application/dns-message")
+                       .send();
+        let mut response = try!(sent.map_err(|error| {
+            io::Error::new(io::ErrorKind::Other, format!("DNS over HTTPS failed: {:?}", error))
+        }));
+        if response.status != StatusCode::Ok {
+            return Err(io::Error::new(io::ErrorKind::Other,
+                                      format!("DNS over HTTPS answered {}", response.status)));
+        }
+        let mut message = vec![];
+        try!(response.read_to_end(&mut message));
+        answer_addresses(&message)
+    }
+}
+
+impl Resolver for DohResolver {
+    // Some servers fail the lookup for a kind of address the host just doesn't have,
+    // so one failing is fine while the other finds something.
+    fn resolve(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        let mut addresses = vec![];
+        let mut failure = None;
+        for record_type in &[TYPE_A, TYPE_AAAA] {
+            match self.lookup(host, *record_type) {
+                Ok(found) => addresses.extend(found),
+                Err(error) => failure = Some(error),
+            }
+        }
+        if addresses.is_empty() {
+            return Err(failure.unwrap_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, format!("{} has no addresses", host))
+            }));
+        }
+        Ok(addresses)
+    }
+}
+
+/// The addresses the resolver has for this host and allows connecting to, or just
+/// the host if it's an IP address and that's allowed. Fails with PermissionDenied
+/// when none are. Resolvers that pin get the same addresses again while pinning.
+pub fn addresses(resolver: &Resolver, host: &str) -> io::Result<Vec<IpAddr>> {
+    if resolver.pins() {
+        if let Some(addresses) = pinned(host) {
+            return Ok(addresses);
+        }
+    }
+    let found = match host.parse::<IpAddr>() {
+        Ok(address) => vec![address],
+        Err(_) => try!(resolver.resolve(host)),
+    };
+    let allowed: Vec<IpAddr> = found.iter().filter(|a| resolver.allows(a)).cloned().collect();
+    if allowed.is_empty() && !found.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied,
+                                  format!("Refusing to connect to {} at {}", host, found[0])));
+    }
+    if resolver.pins() {
+        pin(host, &allowed);
+    }
+    Ok(allowed)
+}
+
+/// Connects to the first address the resolver has for this host that takes the
+/// connection. IP addresses are connected to as they are. Only addresses the
+/// resolver allows are tried.
+pub fn connect(resolver: &Resolver, host: &str, port: u16) -> io::Result<TcpStream> {
+    let addresses = try!(addresses(resolver, host));
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound,
+                                        format!("{} has no addresses", host));
+    for address in addresses {
+        match TcpStream::connect(&SocketAddr::new(address, port)) {
+            Ok(stream) => return Ok(stream),
+            Err(error) => last_error = error,
+        }
+    }
+    Err(last_error)
+}
@@ -0,0 +1,133 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use hyper::header::{ByteRangeSpec, ContentRange, ContentRangeSpec, Range};
+
+use super::{replace_file, CursError, CursResult, Request, Response, StatusCode};
+
+// The validator of a download is kept next to it, in `<file>.etag`, for as long as
+// the file is there: it's what later resumes send in If-Range.
+fn etag_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".etag");
+    PathBuf::from(name)
+}
+
+fn read_validator(path: &Path) -> Option<String> {
+    let mut validator = String::new();
+    match File::open(path).and_then(|mut file| file.read_to_string(&mut validator)) {
+        Ok(_) if !validator.trim().is_empty() => Some(validator.trim().to_string()),
+        _ => None,
+    }
+}
+
+// What If-Range can name the response's version by: a strong ETag, or else its
+// Last-Modified date. Weak ETags can't vouch for bytes.
+fn response_validator(response: &Response) -> Option<String> {
+    let header = |name: &str| {
+        response.headers
+                .get_raw(name)
+                .and_then(|values| values.first())
+                .map(|value| String::from_utf8_lossy(value).trim().to_string())
+    };
+    header("ETag").into_iter()
+                  .filter(|etag| !etag.starts_with("W/"))
+                  .chain(header("Last-Modified"))
+                  .next()
+}
+
+// The whole length a 416 says there is, in `Content-Range: bytes */length`.
+fn unsatisfied_length(response: &Response) -> Option<u64> {
+    match response.headers.get::<ContentRange>() {
+        Some(&ContentRange(ContentRangeSpec::Bytes { range: None, instance_length })) => {
+            instance_length
+        }
+        _ => None,
+    }
+}
+
+fn range_start(response: &Response) -> Option<u64> {
+    match response.headers.get::<ContentRange>() {
+        Some(&ContentRange(ContentRangeSpec::Bytes { range: Some((first, _)), .. })) => {
+            Some(first)
+        }
+        _ => None,
+    }
+}
+
+/// See Request::download_with_resume.
+pub fn resume_until_done(request: &Request, path: &Path, attempts: u32) -> CursResult<u64> {
+    let mut attempt = 1;
+    loop {
+        match resume(request, path) {
+            // What made it to the file stays there, so the next try only gets the rest.
+            Err(CursError::Network(_)) if attempt < attempts => attempt += 1,
+            result => return result,
+        }
+    }
+}
+
+/// See Request::resume_download.
+pub fn resume(request: &Request, path: &Path) -> CursResult<u64> {
+    let etag_path = etag_path(path);
+    let validator = read_validator(&etag_path);
+    let mut offset = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    loop {
+        let mut ranged = request.clone();
+        if offset > 0 {
+            ranged.header(Range::Bytes(vec![ByteRangeSpec::AllFrom(offset)]));
+            if let Some(ref validator) = validator {
+                ranged.raw_header("If-Range", validator);
+            }
+        }
+        let mut response = try!(ranged.send());
+        let status = response.status;
+        let append = match status {
+            StatusCode::PartialContent if offset > 0 => {
+                let same_version = match (validator.as_ref(), response_validator(&response)) {
+                    (Some(old), Some(new)) => *old == new,
+                    _ => true,
+                };
+                if !same_version || range_start(&response) != Some(offset) {
+                    // It's not the rest of what we have, get it all again.
+                    offset = 0;
+                    continue;
+                }
+                true
+            }
+            StatusCode::Ok => false,
+            // There's nothing past what we have, so it was already complete, as long
+            // as what we have is as long as the whole thing.
+            StatusCode::RangeNotSatisfiable if offset > 0 => {
+                if unsatisfied_length(&response) == Some(offset) {
+                    return Ok(offset);
+                }
+                offset = 0;
+                continue;
+            }
+            _ => return Err(CursError::Status(response)),
+        };
+
+        match response_validator(&response) {
+            Some(ref validator) => {
+                try!(replace_file(&etag_path, validator.as_bytes(), OpenOptions::new()))
+            }
+            None => {
+                let _ = fs::remove_file(&etag_path);
+            }
+        }
+        let mut file = if append {
+            try!(OpenOptions::new().append(true).open(path))
+        } else {
+            try!(File::create(path))
+        };
+        let copied = try!(io::copy(&mut response, &mut file));
+        return Ok(if append {
+            offset + copied
+        } else {
+            copied
+        });
+    }
+}
@@ -0,0 +1,27 @@
+use hyper::mime::Mime;
+use serde::Serialize;
+use serde_json;
+
+use super::CursResult;
+
+/// Turns a value into a request body, see Request::body_with.
+/// Implement it for wire formats curs doesn't know about.
+pub trait BodyEncoder {
+    /// Sent as the Content-Type of whatever encode makes.
+    fn content_type(&self) -> Mime;
+    fn encode<T: Serialize>(&self, value: &T) -> CursResult<Vec<u8>>;
+}
+
+/// Plain JSON, what Request::json sends.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonEncoder;
+
+impl BodyEncoder for JsonEncoder {
+    fn content_type(&self) -> Mime {
+        "application/json".parse().unwrap()
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> CursResult<Vec<u8>> {
+        Ok(try!(serde_json::to_vec(value)))
+    }
+}
@@ -0,0 +1,59 @@
+use hyper::method::Method;
+use serde::Deserialize;
+use serde_json::Value;
+use url;
+
+/// One endpoint of an API, declared once and sent with Client::execute. The input
+/// has everything that varies from one request to the next, and the output is
+/// what the JSON response decodes to. Being plain functions of the input, the
+/// path, query and body can be tested without sending anything.
+///
+/// ```no_run
+/// # use curs::Method;
+/// # use curs::endpoint::Endpoint;
+/// # use curs::serde_json::Value;
+/// struct GetOrder;
+///
+/// impl Endpoint for GetOrder {
+///     type Input = u64;
+///     type Output = Value;
+///
+///     fn method() -> Method {
+///         Method::Get
+///     }
+///
+///     fn path(id: &u64) -> String {
+///         format!("orders/{}", id)
+///     }
+/// }
+/// ```
+pub trait Endpoint {
+    type Input;
+    type Output: Deserialize;
+
+    fn method() -> Method;
+
+    /// Relative to the Client's base url, params filled in and percent-encoded.
+    fn path(input: &Self::Input) -> String;
+
+    /// Params for the query string, whatever the method.
+    fn query(_input: &Self::Input) -> Vec<(String, String)> {
+        vec![]
+    }
+
+    /// The JSON body, if it has one. serde_json::to_value makes one from any type.
+    fn body(_input: &Self::Input) -> Option<Value> {
+        None
+    }
+}
+
+/// The path and query an input goes to, as Client::execute sends it.
+pub fn path_and_query<E: Endpoint>(input: &E::Input) -> String {
+    let path = E::path(input);
+    let query = E::query(input);
+    if query.is_empty() {
+        path
+    } else {
+        format!("{}?{}", path, url::form_urlencoded::serialize(&query))
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+use super::{Client, CursError, CursResult, Method, Response};
+
+/// A single link from a HAL document's `_links` section.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HalLink {
+    pub href: String,
+    pub templated: bool,
+    pub title: Option<String>,
+}
+
+impl HalLink {
+    fn from_value(value: &Value) -> Option<HalLink> {
+        value.find("href").and_then(|h| h.as_str()).map(|href| {
+            HalLink {
+                href: href.to_string(),
+                templated: value.find("templated").and_then(|t| t.as_bool()).unwrap_or(false),
+                title: value.find("title").and_then(|t| t.as_str()).map(|t| t.to_string()),
+            }
+        })
+    }
+}
+
+/// A HAL response. Decode it like any other JSON with decode_success, then
+/// follow its links instead of hardcoding URLs. The whole document is kept in body.
+#[derive(Clone, Debug)]
+pub struct HalResource {
+    pub links: BTreeMap<String, Vec<HalLink>>,
+    pub body: Value,
+}
+
+impl HalResource {
+    pub fn from_value(body: Value) -> HalResource {
+        let mut links = BTreeMap::new();
+        if let Some(found) = body.find("_links").and_then(|l| l.as_object()) {
+            for (rel, value) in found {
+                // A relation may be a single link object or an array of them.
+                let parsed: Vec<HalLink> = match *value {
+                    Value::Array(ref many) => many.iter().filter_map(HalLink::from_value).collect(),
+                    ref one => HalLink::from_value(one).into_iter().collect(),
+                };
+                links.insert(rel.clone(), parsed);
+            }
+        }
+        HalResource {
+            links: links,
+            body: body,
+        }
+    }
+
+    /// The first link for the given relation, if any.
+    pub fn link(&self, rel: &str) -> Option<&HalLink> {
+        self.links.get(rel).and_then(|l| l.first())
+    }
+
+    /// GETs the first link for the given relation using the same Client.
+    /// Relative links are resolved against the Client's base url.
+    /// Templated links are not expanded.
+    pub fn follow(&self, client: &Client, rel: &str) -> CursResult<Response> {
+        let link = try!(self.link(rel).ok_or_else(|| CursError::MissingLink(rel.to_string())));
+        client.request(Method::Get, &link.href).send()
+    }
+}
+
+impl Deserialize for HalResource {
+    fn deserialize<D: Deserializer>(deserializer: &mut D) -> Result<HalResource, D::Error> {
+        Value::deserialize(deserializer).map(HalResource::from_value)
+    }
+}
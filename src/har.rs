@@ -0,0 +1,129 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use hyper::method::Method;
+use serde_json::{self, Value};
+use url::Url;
+
+use super::{Client, CursError, CursResult, Request, Response};
+
+// Set by whoever sends the request, or HTTP/2 pseudo headers like `:authority`.
+const SKIPPED_HEADERS: &'static [&'static str] = &["host",
+                                                   "content-length",
+                                                   "connection",
+                                                   "transfer-encoding"];
+
+/// A request recorded in a HAR (HTTP Archive) file, like the ones browsers and
+/// proxies export, ready to be sent again.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HarEntry {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+}
+
+fn not_har(why: &str) -> CursError {
+    CursError::Config(format!("Not a HAR file: {}", why))
+}
+
+fn entry_from_value(value: &Value) -> CursResult<HarEntry> {
+    let request = try!(value.find("request").ok_or_else(|| not_har("entry without request")));
+    let method = try!(request.find("method")
+                             .and_then(|m| m.as_str())
+                             .ok_or_else(|| not_har("request without method")));
+    let url = try!(request.find("url")
+                          .and_then(|u| u.as_str())
+                          .ok_or_else(|| not_har("request without url")));
+    let headers = request.find("headers")
+                         .and_then(|h| h.as_array())
+                         .map(|headers| {
+                             headers.iter()
+                                    .filter_map(|header| {
+                                        match (header.find("name").and_then(|n| n.as_str()),
+                                               header.find("value").and_then(|v| v.as_str())) {
+                                            (Some(name), Some(value)) => {
+                                                Some((name.to_string(), value.to_string()))
+                                            }
+                                            _ => None,
+                                        }
+                                    })
+                                    .filter(|&(ref name, _)| {
+                                        let name = name.to_lowercase();
+                                        !name.starts_with(':') &&
+                                        !SKIPPED_HEADERS.iter().any(|skipped| *skipped == name)
+                                    })
+                                    .collect()
+                         })
+                         .unwrap_or_else(Vec::new);
+    let body = request.find_path(&["postData", "text"])
+                      .and_then(|t| t.as_str())
+                      .map(|text| text.as_bytes().to_vec());
+    Ok(HarEntry {
+        method: try!(Method::from_str(method).map_err(|_| not_har("bad method"))),
+        url: url.to_string(),
+        headers: headers,
+        body: body,
+    })
+}
+
+/// The requests in a HAR document, in the order they were recorded.
+pub fn parse(contents: &[u8]) -> CursResult<Vec<HarEntry>> {
+    let document: Value = try!(serde_json::from_slice(contents));
+    let entries = try!(document.find_path(&["log", "entries"])
+                               .and_then(|e| e.as_array())
+                               .ok_or_else(|| not_har("no log.entries")));
+    entries.iter().map(entry_from_value).collect()
+}
+
+pub fn load(path: &Path) -> CursResult<Vec<HarEntry>> {
+    let mut contents = vec![];
+    try!(try!(File::open(path)).read_to_end(&mut contents));
+    parse(&contents)
+}
+
+impl HarEntry {
+    /// The same request, sent to the scheme, host and port of `base` instead,
+    /// keeping its path and query. Like production traffic replayed on staging.
+    pub fn rebased(&self, base: &Url) -> CursResult<HarEntry> {
+        let url = try!(Url::parse(&self.url));
+        let mut path = url.serialize_path().unwrap_or_else(|| "/".to_string());
+        if let Some(ref query) = url.query {
+            path.push('?');
+            path.push_str(query);
+        }
+        let mut rebased = self.clone();
+        rebased.url = try!(base.join(&path)).serialize();
+        Ok(rebased)
+    }
+
+    /// A request with this entry's method, url, headers and body, to be sent by
+    /// this client. Add to it or change it before sending if needed.
+    pub fn request<'a>(&'a self, client: &'a Client) -> Request<'a> {
+        let mut request = client.request(self.method.clone(), &self.url);
+        for &(ref name, ref value) in &self.headers {
+            request.raw_header(name, value);
+        }
+        request.raw_body = self.body.as_ref().map(|body| Cow::Borrowed(&body[..]));
+        request
+    }
+}
+
+/// Sends the entries again one after the other, against `base` instead of the
+/// recorded hosts when given. A failing request doesn't stop the rest.
+pub fn replay(client: &Client,
+              entries: &[HarEntry],
+              base: Option<&Url>)
+              -> Vec<CursResult<Response>> {
+    entries.iter()
+           .map(|entry| {
+               match base {
+                   Some(base) => entry.rebased(base).and_then(|e| e.request(client).send()),
+                   None => entry.request(client).send(),
+               }
+           })
+           .collect()
+}
@@ -0,0 +1,38 @@
+use url::{ParseError, Url};
+use url::idna::domain_to_ascii;
+use url::punycode::decode_to_string;
+
+use super::CursResult;
+
+/// Converts a Unicode host name to the punycode form that goes over the wire.
+/// ASCII names come back lowercased and otherwise untouched.
+pub fn to_ascii(domain: &str) -> CursResult<String> {
+    Ok(try!(domain_to_ascii(domain).map_err(|_| ParseError::InvalidDomainCharacter)))
+}
+
+/// Converts a punycode host name back to Unicode for display. Labels that can't
+/// be decoded are left as they were.
+pub fn to_unicode(domain: &str) -> String {
+    let labels: Vec<String> = domain.split('.')
+                                    .map(|label| {
+                                        if label.starts_with("xn--") {
+                                            decode_to_string(&label[4..])
+                                                .unwrap_or(label.to_string())
+                                        } else {
+                                            label.to_string()
+                                        }
+                                    })
+                                    .collect();
+    labels.join(".")
+}
+
+/// The url as people would type it, with a Unicode host name.
+/// Urls parse into punycode (that's what gets resolved and sent), use this to show them.
+pub fn display_url(url: &Url) -> String {
+    let mut display = url.clone();
+    if let Some(domain) = display.domain_mut() {
+        let unicode = to_unicode(domain);
+        *domain = unicode;
+    }
+    display.serialize()
+}
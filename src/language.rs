@@ -0,0 +1,99 @@
+use hyper::client::response::Response;
+use hyper::header::Headers;
+
+// Like `es-AR`, `zh-Hant-TW` or `*` (RFC 4647, 2.1).
+fn is_language_range(range: &str) -> bool {
+    range == "*" ||
+    (!range.is_empty() &&
+     range.split('-').all(|part| {
+        !part.is_empty() && part.len() <= 8 &&
+        part.chars().all(|c| {
+            match c {
+                'a'...'z' | 'A'...'Z' | '0'...'9' => true,
+                _ => false,
+            }
+        })
+    }))
+}
+
+// Weights have at most three decimals, from 0 to 1 (RFC 7231, 5.3.1).
+fn parse_weight(weight: &str) -> Option<u16> {
+    let mut parts = weight.splitn(2, '.');
+    let whole = parts.next().unwrap_or("");
+    let decimals = parts.next().unwrap_or("");
+    if (whole != "0" && whole != "1") || decimals.len() > 3 ||
+       !decimals.chars().all(|c| c.is_digit(10)) {
+        return None;
+    }
+    let thousandths = format!("{}{:0<3}", whole, decimals);
+    match thousandths.parse() {
+        Ok(weight) if weight <= 1000 => Some(weight),
+        _ => None,
+    }
+}
+
+fn format_weight(thousandths: u16) -> String {
+    let decimals = format!("{:03}", thousandths % 1000);
+    format!("{}.{}", thousandths / 1000, decimals.trim_right_matches('0'))
+}
+
+/// An Accept-Language value for these languages, most wanted first, like
+/// `es-AR, es;q=0.9, en;q=0.5`. Languages can have their own `;q=` weight, those
+/// without one after the first get a tenth less than the one before, so the order
+/// is kept. Tells why when a language or weight isn't valid.
+pub fn accept_language(languages: &[&str]) -> Result<String, String> {
+    let mut ranges = vec![];
+    let mut previous = 1000;
+    for (i, language) in languages.iter().enumerate() {
+        let mut parts = language.splitn(2, ';');
+        let range = parts.next().unwrap_or("").trim();
+        if !is_language_range(range) {
+            return Err(format!("{:?} is not a language", language));
+        }
+        let weight = match parts.next().map(|param| param.trim()) {
+            Some(param) if param.starts_with("q=") => {
+                match parse_weight(&param[2..]) {
+                    Some(weight) => weight,
+                    None => return Err(format!("{:?} has an invalid weight", language)),
+                }
+            }
+            Some(_) => return Err(format!("{:?} has something other than a weight", language)),
+            None if i == 0 => 1000,
+            None => previous.saturating_sub(100).max(1),
+        };
+        previous = weight;
+        if weight == 1000 {
+            ranges.push(range.to_string());
+        } else {
+            ranges.push(format!("{};q={}", range, format_weight(weight)));
+        }
+    }
+    Ok(ranges.join(", "))
+}
+
+/// Lets you ask a Response what languages its body is in.
+pub trait Localized {
+    /// The languages in its Content-Language header, like `es-AR`, none when it has
+    /// no such header.
+    fn content_language(&self) -> Vec<String>;
+}
+
+impl Localized for Response {
+    fn content_language(&self) -> Vec<String> {
+        content_language(&self.headers)
+    }
+}
+
+fn content_language(headers: &Headers) -> Vec<String> {
+    headers.get_raw("Content-Language")
+           .into_iter()
+           .flat_map(|values| values.iter())
+           .flat_map(|value| {
+               String::from_utf8_lossy(value)
+                   .split(',')
+                   .map(|language| language.trim().to_string())
+                   .filter(|language| !language.is_empty())
+                   .collect::<Vec<_>>()
+           })
+           .collect()
+}
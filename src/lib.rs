@@ -21,14 +21,14 @@
 //! use curs::{Request, FileUpload, DecodableResult, Method};
 //!
 //! // Just stuff needed for this particular test.
-//! extern crate http_stub;
-//! use http_stub as hs;
+//! use curs::testing::StubServer;
 //! use std::env;
 //! use curs::hyper::header::UserAgent;
 //!
 //! fn main(){
 //!  // Nevermind this stub HTTP server. Find the actual curs code below.
-//!  let url = hs::HttpStub::run(|s|{ s.send_body(r#"["foo", "bar"]"#) });
+//!  let server = StubServer::run(|s|{ s.send_body(r#"["foo", "bar"]"#); });
+//!  let url = server.url();
 //!
 //!  let file = FileUpload{
 //!    name: "shim.png".to_string(),
@@ -51,21 +51,115 @@ pub extern crate hyper;
 extern crate url;
 extern crate rand;
 extern crate mime_guess;
+extern crate openssl;
+extern crate rustc_serialize;
+extern crate time;
+extern crate uuid;
+#[cfg(feature = "config")]
+extern crate toml;
+#[cfg(feature = "mmap")]
+extern crate libc;
+#[cfg(feature = "os-keyring")]
+extern crate keyring;
+
+pub mod archive;
+pub mod audit;
+pub mod auth;
+mod background;
+mod benchmark;
+pub mod body;
+mod buffered;
+pub mod cache;
+pub mod call;
+pub mod charset;
+mod client;
+mod clientpool;
+pub mod clock;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod decode;
+pub mod dns;
+mod download;
+pub mod encode;
+pub mod endpoint;
+mod hal;
+pub mod har;
+pub mod idn;
+pub mod language;
+pub mod loadgen;
+mod local;
+mod memo;
+#[cfg(all(feature = "mmap", unix))]
+pub mod mmap;
+mod pieces;
+pub mod pagination;
+pub mod netrc;
+pub mod odata;
+pub mod openapi;
+pub mod progress;
+pub mod proxy;
+mod queue;
+pub mod ratelimit;
+pub mod redact;
+pub mod resource;
+pub mod retry;
+pub mod secret;
+mod spooled;
+mod template;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod tls;
+pub mod tokens;
+pub mod transform;
+pub mod uploads;
+pub mod validate;
+pub mod webhook;
+
+pub use background::BackgroundQueue;
+pub use benchmark::Benchmark;
+pub use buffered::BufferedResponse;
+pub use client::Client;
+pub use clientpool::ClientPool;
+pub use hal::{HalLink, HalResource};
+pub use odata::ODataQuery;
+pub use queue::{Priority, QueueDepth};
+pub use spooled::{SpooledBody, SpooledResponse};
+pub use template::{RequestTemplate, TemplatedRequest};
+pub use webhook::WebhookSigner;
 
 pub use self::hyper::method::Method;
 pub use self::hyper::client::response::Response;
 pub use self::hyper::status::StatusCode;
 
-use std::path::Path;
-use std::fs::File;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::fs::{self, File};
 use std::io::Error as IoError;
-use std::io::Read;
+use std::io::{self, Read, Seek};
+use std::time::Duration;
 use self::rand::Rng;
+use self::rustc_serialize::base64::{MIME, ToBase64};
 use self::serde::{Deserialize, Serialize};
-use self::hyper::header::{Headers, Header, HeaderFormat, ContentType};
-use self::hyper::client::{Client, IntoUrl};
+use self::hyper::header::{ByteRangeSpec, Headers, Header, HeaderFormat, ContentType, Range};
+use self::hyper::client::IntoUrl;
 use self::hyper::error::Error as HyperError;
-use self::hyper::mime::Mime;
+use self::hyper::mime::{Mime, SubLevel};
+use self::url::ParseError as UrlError;
+use self::url::Url;
+use archive::TarDirectory;
+use body::{BodyProducer, BodySource, OpenBody, Produced, Rewindable};
+use cache::CachePolicy;
+use decode::{Decoder, JsonDecoder, JsonError};
+use encode::BodyEncoder;
+use pagination::Pages;
+use pieces::{Piece, Pieces};
+use progress::{PartSpan, ProgressReader, UploadProgress};
+use proxy::{NoProxy, ProxySettings};
+use redact::{RedactedHeaders, RedactedResponse};
+use transform::{BodyTransformer, Unwrap};
 
 /// Your result may be text or a struct deserialized from JSON.
 /// The error is always a CursError
@@ -73,18 +167,121 @@ pub type CursResult<T> = Result<T, CursError>;
 
 pub trait DecodableResult {
     fn decode_success<D: Deserialize>(self) -> CursResult<D>;
+    fn decode_cached<D: Deserialize>(self) -> CursResult<Cached<D>>;
+    fn decode_success_with<D: Deserialize, E: Decoder>(self, decoder: &E) -> CursResult<D>;
+}
+
+/// What a conditional GET got back, see Request::if_none_match.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Cached<D> {
+    /// A new representation, and the validators to send next time.
+    Fresh {
+        body: D,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+    /// A 304: whatever you got before is still current.
+    NotModified,
+}
+
+impl<D> Cached<D> {
+    /// The ETag of a fresh representation, to update it with Request::if_match.
+    pub fn etag(&self) -> Option<&str> {
+        match *self {
+            Cached::Fresh { etag: Some(ref etag), .. } => Some(etag),
+            _ => None,
+        }
+    }
+}
+
+fn first_raw_header(headers: &Headers, name: &str) -> Option<String> {
+    headers.get_raw(name)
+           .and_then(|values| values.first())
+           .map(|value| String::from_utf8_lossy(value).into_owned())
+}
+
+// A line break in a header would end it early and let whatever follows pass for
+// more headers, or another request altogether. Tabs are the only control
+// characters values can have.
+fn is_bad_header(name: &str, value: &str) -> bool {
+    name.is_empty() || name.chars().any(|c| c.is_control() || c.is_whitespace() || c == ':') ||
+    value.chars().any(|c| c.is_control() && c != '\t')
+}
+
+// Missing Content-Types get the benefit of the doubt.
+fn says_json(headers: &Headers) -> bool {
+    match headers.get::<ContentType>() {
+        Some(&ContentType(Mime(_, SubLevel::Json, _))) => true,
+        Some(&ContentType(Mime(_, SubLevel::Ext(ref sub), _))) => sub.ends_with("+json"),
+        Some(_) => false,
+        None => true,
+    }
+}
+
+// Writes the contents next to path, then renames them over it, so whatever reads
+// it sees the old file or the new one and never half of one. The new file is
+// opened with these options, to set its mode.
+fn replace_file(path: &Path, contents: &[u8], mut options: fs::OpenOptions) -> io::Result<()> {
+    use std::io::Write;
+
+    let mut temporary = path.as_os_str().to_os_string();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    // Left over from a crash, maybe with some other mode.
+    let _ = fs::remove_file(&temporary);
+    let mut file = try!(options.write(true).create(true).truncate(true).open(&temporary));
+    try!(file.write_all(contents));
+    try!(file.sync_all());
+    fs::rename(&temporary, path)
+}
+
+// The same response with its body rewritten, read whole to do so.
+fn transform_incoming(mut response: Response,
+                      method: &Method,
+                      transformer: &BodyTransformer)
+                      -> CursResult<Response> {
+    let mut body = vec![];
+    try!(response.read_to_end(&mut body));
+    let body = try!(transformer.incoming(body, &response.headers));
+    let headers: Vec<(String, String)> = response.headers
+                                                 .iter()
+                                                 .map(|h| (h.name().to_string(), h.value_string()))
+                                                 .collect();
+    local::response_with_headers(response.url.clone(), method, response.status, &headers, &body)
 }
 
 impl DecodableResult for CursResult<Response> {
     /// You can chain a decode_success call to your CursResult
     /// to deserialize a successful (2xx) JSON response. Using serde.
     fn decode_success<D: Deserialize>(self) -> CursResult<D> {
+        self.decode_success_with(&JsonDecoder)
+    }
+
+    /// Like decode_success, but a 304 Not Modified is not an error.
+    /// Successful responses come with their ETag and Last-Modified headers.
+    fn decode_cached<D: Deserialize>(self) -> CursResult<Cached<D>> {
+        let response = try!(self);
+        if response.status == StatusCode::NotModified {
+            return Ok(Cached::NotModified);
+        }
+        let etag = first_raw_header(&response.headers, "ETag");
+        let last_modified = first_raw_header(&response.headers, "Last-Modified");
+        let result: CursResult<Response> = Ok(response);
+        Ok(Cached::Fresh {
+            body: try!(result.decode_success()),
+            etag: etag,
+            last_modified: last_modified,
+        })
+    }
+
+    /// Like decode_success, with any Decoder instead of plain JSON.
+    fn decode_success_with<D: Deserialize, E: Decoder>(self, decoder: &E) -> CursResult<D> {
         let mut response = try!(self);
         match response.status {
             StatusCode::Ok | StatusCode::Created | StatusCode::Accepted => {
-                let mut response_string = String::new();
-                try!(response.read_to_string(&mut response_string));
-                Ok(try!(serde_json::from_str(&response_string)))
+                let mut body = vec![];
+                try!(response.read_to_end(&mut body));
+                decoder.decode(&body, &response.headers)
             }
             _ => Err(CursError::Status(response)),
         }
@@ -92,11 +289,90 @@ impl DecodableResult for CursResult<Response> {
 }
 
 /// Sending your request may fail for any of the following reasons.
-#[derive(Debug)]
+/// Debug output leaves out the values of sensitive headers, see redact.
 pub enum CursError {
     Status(Response),
+    /// The response's status is none of the ones the request expected, see
+    /// Request::expect_status.
+    UnexpectedStatus(Response),
+    /// A Client's ResponseValidator turned the response down, for this reason.
+    InvalidResponse(Response, String),
+    /// A 412 to a conditional request: someone else changed the resource since
+    /// its ETag was read, see Request::if_match. Read it again and retry.
+    PreconditionFailed(Response),
     Network(HyperError),
     Json(serde_json::Error),
+    /// A response body isn't the JSON expected, see decode::JsonError.
+    JsonBody(JsonError),
+    Url(UrlError),
+    MissingLink(String),
+    Config(String),
+    /// Something set on the request can't go on the wire as it is, like a header
+    /// value with a line break in it.
+    InvalidInput(String),
+    /// A Decoder couldn't make sense of a response body.
+    Decode(String),
+    /// The retry budget ran out after this many attempts, the last one failing like so.
+    RetriesExhausted(u32, Box<CursError>),
+    /// Pagination stopped after this many pages, and there were more.
+    TooManyPages(usize),
+    /// Everything wrong with how the request was built, found as it was built, see
+    /// Request::problems. It's never sent.
+    InvalidRequest(Vec<String>),
+}
+
+impl CursError {
+    /// How long the server asked to wait before trying again, when this is a
+    /// status error (or ran out of retries on one) with a Retry-After header.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match *self {
+            CursError::Status(ref response) |
+            CursError::UnexpectedStatus(ref response) => {
+                retry::retry_after(&response.headers, &clock::SystemClock)
+            }
+            CursError::RetriesExhausted(_, ref last) => last.retry_after(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Debug for CursError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CursError::Status(ref response) => {
+                f.debug_tuple("Status").field(&RedactedResponse(response)).finish()
+            }
+            CursError::UnexpectedStatus(ref response) => {
+                f.debug_tuple("UnexpectedStatus").field(&RedactedResponse(response)).finish()
+            }
+            CursError::PreconditionFailed(ref response) => {
+                f.debug_tuple("PreconditionFailed").field(&RedactedResponse(response)).finish()
+            }
+            CursError::InvalidResponse(ref response, ref reason) => {
+                f.debug_tuple("InvalidResponse")
+                 .field(&RedactedResponse(response))
+                 .field(reason)
+                 .finish()
+            }
+            CursError::Network(ref error) => f.debug_tuple("Network").field(error).finish(),
+            CursError::Json(ref error) => f.debug_tuple("Json").field(error).finish(),
+            CursError::JsonBody(ref error) => {
+                f.debug_tuple("JsonBody").field(&format!("{}", error)).finish()
+            }
+            CursError::Url(ref error) => f.debug_tuple("Url").field(error).finish(),
+            CursError::MissingLink(ref rel) => f.debug_tuple("MissingLink").field(rel).finish(),
+            CursError::Config(ref why) => f.debug_tuple("Config").field(why).finish(),
+            CursError::InvalidInput(ref why) => f.debug_tuple("InvalidInput").field(why).finish(),
+            CursError::Decode(ref why) => f.debug_tuple("Decode").field(why).finish(),
+            CursError::RetriesExhausted(attempts, ref last) => {
+                f.debug_tuple("RetriesExhausted").field(&attempts).field(last).finish()
+            }
+            CursError::TooManyPages(pages) => f.debug_tuple("TooManyPages").field(&pages).finish(),
+            CursError::InvalidRequest(ref problems) => {
+                f.debug_tuple("InvalidRequest").field(problems).finish()
+            }
+        }
+    }
 }
 
 impl From<HyperError> for CursError {
@@ -117,6 +393,12 @@ impl From<serde_json::Error> for CursError {
     }
 }
 
+impl From<UrlError> for CursError {
+    fn from(err: UrlError) -> CursError {
+        CursError::Url(err)
+    }
+}
+
 /// All your params should go in a vector.
 pub type Params<'a> = Vec<Param<'a>>;
 
@@ -131,6 +413,43 @@ pub struct FileUpload<'a> {
     pub path: &'a Path,
 }
 
+/// An upload with contents in memory, or from somewhere that can only be read
+/// once, like stdin or a pipe. Those are read whole when the upload is made, so
+/// retries and redirects can send them again.
+#[derive(Clone)]
+pub struct BytesUpload {
+    pub name: String,
+    pub filename: String,
+    /// Guessed from the filename when not set.
+    pub mime: Option<Mime>,
+    pub bytes: Vec<u8>,
+}
+
+impl BytesUpload {
+    pub fn from_reader<R: Read>(name: &str,
+                                filename: &str,
+                                mime: Option<Mime>,
+                                mut reader: R)
+                                -> CursResult<BytesUpload> {
+        let mut bytes = vec![];
+        try!(reader.read_to_end(&mut bytes));
+        Ok(BytesUpload {
+            name: name.to_string(),
+            filename: filename.to_string(),
+            mime: mime,
+            bytes: bytes,
+        })
+    }
+
+    /// Everything piped in, for tools that upload what comes out of another command.
+    pub fn from_stdin(name: &str, filename: &str, mime: Option<Mime>) -> CursResult<BytesUpload> {
+        BytesUpload::from_reader(name, filename, mime, io::stdin())
+    }
+}
+
+// What Request::to_canonical_string separates multipart parts with.
+const CANONICAL_BOUNDARY: &'static str = "curs-canonical-boundary";
+
 /// You're not expected to be using the MultipartBodyBuilder on your own,
 /// Make a Request and it will know when to delegate if there are any files
 /// to be posted. It's still exported publicly because it may come in handy
@@ -138,12 +457,13 @@ pub struct FileUpload<'a> {
 pub struct MultipartBodyBuilder {
     body: Vec<u8>,
     boundary: String,
+    base64: Vec<String>,
 }
 
-macro_rules! w {
-  ($b:ident, $f:expr, $a: expr) => (
-    $b.body.extend(format!($f, $a).as_bytes())
-  )
+/// Percent-encodes the quotes and line breaks in a part's name or filename, like
+/// browsers do, so they can't end the quoted string or inject headers and parts.
+fn escape_disposition(value: &str) -> String {
+    value.replace('"', "%22").replace('\r', "%0D").replace('\n', "%0A")
 }
 
 impl MultipartBodyBuilder {
@@ -153,49 +473,457 @@ impl MultipartBodyBuilder {
         MultipartBodyBuilder {
             body: vec![],
             boundary: boundary,
+            base64: vec![],
+        }
+    }
+
+    /// A builder that separates parts with this boundary instead of a random one,
+    /// for bodies that have to come out the same every time.
+    pub fn with_boundary(boundary: &str) -> MultipartBodyBuilder {
+        MultipartBodyBuilder {
+            body: vec![],
+            boundary: boundary.to_string(),
+            base64: vec![],
         }
     }
 
-    pub fn build<'a>(mut self,
+    /// Send the files and uploads with these names in base64, with a
+    /// `Content-Transfer-Encoding: base64` header, for endpoints that can't take raw
+    /// binary parts.
+    pub fn base64_parts(mut self, names: Vec<String>) -> MultipartBodyBuilder {
+        self.base64 = names;
+        self
+    }
+
+    pub fn build<'a>(self,
                      files: Vec<FileUpload>,
                      params: Params<'a>)
                      -> Result<MultipartBodyBuilder, CursError> {
+        self.build_with_bytes(files, vec![], params)
+    }
+
+    /// Like build, with uploads from memory after the files.
+    pub fn build_with_bytes<'a>(mut self,
+                                files: Vec<FileUpload>,
+                                uploads: Vec<BytesUpload>,
+                                params: Params<'a>)
+                                -> Result<MultipartBodyBuilder, CursError> {
         for (name, value) in params {
-            w!(self, "\r\n--{}\r\n", self.boundary);
-            w!(self, "Content-Disposition: form-data; name=\"{}\"", name);
-            w!(self, "\r\n{}\r\n", value);
+            self.body.extend(param_part(&self.boundary, name, value).as_bytes());
         }
 
         for FileUpload { name, path, mime } in files {
-            w!(self, "\r\n--{}\r\n", self.boundary);
-            w!(self, "Content-Disposition: form-data; name=\"{}\"", name);
-            w!(self,
-               "; filename=\"{}\"",
-               path.file_name().unwrap().to_str().unwrap());
-            w!(self,
-               "\r\nContent-Type: {}\r\n\r\n",
-               mime.unwrap_or_else(|| self::mime_guess::guess_mime_type(path)));
+            let base64 = self.base64.contains(&name);
+            let filename = file_upload_name(path);
+            let head = file_part_head(&self.boundary, &name, &filename, mime, path, base64);
+            self.body.extend(head.as_bytes());
+            let mut contents = vec![];
+            try!(try!(File::open(path)).read_to_end(&mut contents));
+            self.body.extend(part_contents(contents, base64));
+            self.body.extend(PART_END.as_bytes());
+        }
 
-            let mut contents = try!(File::open(path));
-            try!(contents.read_to_end(&mut self.body));
-            self.body.extend("\r\n\r\n".as_bytes());
+        for BytesUpload { name, filename, mime, bytes } in uploads {
+            let base64 = self.base64.contains(&name);
+            let head = file_part_head(&self.boundary,
+                                      &name,
+                                      &filename,
+                                      mime,
+                                      Path::new(&filename),
+                                      base64);
+            self.body.extend(head.as_bytes());
+            self.body.extend(part_contents(bytes, base64));
+            self.body.extend(PART_END.as_bytes());
         }
 
-        w!(self, "\r\n--{}--", self.boundary);
+        self.body.extend(closing_boundary(&self.boundary).as_bytes());
 
         Ok(self)
     }
 }
 
+// The pieces of a multipart body, shared by MultipartBodyBuilder and MultipartSource.
+const PART_END: &'static str = "\r\n\r\n";
+
+fn param_part(boundary: &str, name: &str, value: &str) -> String {
+    format!("\r\n--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n{}\r\n",
+            boundary,
+            escape_disposition(name),
+            value)
+}
+
+// Without a mime, it's guessed from the extension of `guess_from`.
+fn file_part_head(boundary: &str,
+                  name: &str,
+                  filename: &str,
+                  mime: Option<Mime>,
+                  guess_from: &Path,
+                  base64: bool)
+                  -> String {
+    format!("\r\n--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\
+             Content-Type: {}\r\n{}\r\n",
+            boundary,
+            escape_disposition(name),
+            escape_disposition(filename),
+            mime.unwrap_or_else(|| self::mime_guess::guess_mime_type(guess_from)),
+            if base64 { "Content-Transfer-Encoding: base64\r\n" } else { "" })
+}
+
+// Base64 lines are kept to 76 characters, like MIME asks.
+fn part_contents(contents: Vec<u8>, base64: bool) -> Vec<u8> {
+    if base64 {
+        contents.to_base64(MIME).into_bytes()
+    } else {
+        contents
+    }
+}
+
+fn file_upload_name(path: &Path) -> String {
+    path.file_name().unwrap().to_string_lossy().into_owned()
+}
+
+fn closing_boundary(boundary: &str) -> String {
+    format!("\r\n--{}--", boundary)
+}
+
+/// A multipart body streamed from the files on disk while it's sent, instead of
+/// read into memory first like MultipartBodyBuilder does. The parts' sizes are all
+/// known, so it still goes with a Content-Length. See Request::stream_multipart.
+#[derive(Clone)]
+pub struct MultipartSource {
+    boundary: String,
+    params: Vec<(String, String)>,
+    files: Vec<(String, PathBuf, Option<Mime>)>,
+    uploads: Vec<BytesUpload>,
+    progress: Option<Arc<UploadProgress + Send + Sync>>,
+    mmap: bool,
+    base64: Vec<String>,
+}
+
+impl MultipartSource {
+    pub fn new(files: Vec<FileUpload>,
+               uploads: Vec<BytesUpload>,
+               params: Params)
+               -> MultipartSource {
+        let mut rng = rand::thread_rng();
+        MultipartSource {
+            boundary: rng.gen_ascii_chars().take(30).collect(),
+            params: params.into_iter().map(|(n, v)| (n.to_string(), v.to_string())).collect(),
+            files: files.into_iter()
+                        .map(|file| (file.name, file.path.to_path_buf(), file.mime))
+                        .collect(),
+            uploads: uploads,
+            progress: None,
+            mmap: false,
+            base64: vec![],
+        }
+    }
+
+    /// Map the files into memory instead of reading them, see mmap::MappedFile.
+    /// Nothing may truncate or write to them until the body is sent.
+    #[cfg(all(feature = "mmap", unix))]
+    pub fn mmap_files(mut self) -> MultipartSource {
+        self.mmap = true;
+        self
+    }
+
+    /// Tell this listener about every part while the body is sent.
+    pub fn on_progress<P>(mut self, progress: P) -> MultipartSource
+        where P: UploadProgress + Send + Sync + 'static
+    {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Send the files and uploads with these names in base64, see
+    /// MultipartBodyBuilder::base64_parts. Those files are read whole to encode them.
+    pub fn base64_parts(mut self, names: Vec<String>) -> MultipartSource {
+        self.base64 = names;
+        self
+    }
+
+    /// Separate the parts with this boundary instead of a random one.
+    pub fn with_boundary(mut self, boundary: &str) -> MultipartSource {
+        self.boundary = boundary.to_string();
+        self
+    }
+
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+}
+
+impl BodySource for MultipartSource {
+    fn open(&self) -> io::Result<OpenBody> {
+        let mut pieces = VecDeque::new();
+        let mut parts = vec![];
+        for &(ref name, ref value) in &self.params {
+            let part = param_part(&self.boundary, name, value);
+            // The value is right before the part's trailing line break.
+            let start = queued(&pieces) + (part.len() - value.len() - 2) as u64;
+            parts.push(part_span(name, None, start, value.len() as u64));
+            pieces.push_back(Piece::bytes(part));
+        }
+        for &(ref name, ref path, ref mime) in &self.files {
+            let filename = file_upload_name(path);
+            let base64 = self.base64.contains(name);
+            let head = file_part_head(&self.boundary, name, &filename, mime.clone(), path, base64);
+            pieces.push_back(Piece::bytes(head));
+            let contents = if base64 {
+                let mut contents = vec![];
+                try!(try!(File::open(path)).read_to_end(&mut contents));
+                Piece::bytes(part_contents(contents, true))
+            } else {
+                try!(self.file_piece(path))
+            };
+            parts.push(part_span(name, Some(filename), queued(&pieces), contents.len()));
+            pieces.push_back(contents);
+            pieces.push_back(Piece::bytes(PART_END));
+        }
+        for upload in &self.uploads {
+            let base64 = self.base64.contains(&upload.name);
+            let head = file_part_head(&self.boundary,
+                                      &upload.name,
+                                      &upload.filename,
+                                      upload.mime.clone(),
+                                      Path::new(&upload.filename),
+                                      base64);
+            pieces.push_back(Piece::bytes(head));
+            let contents = part_contents(upload.bytes.clone(), base64);
+            let (start, len) = (queued(&pieces), contents.len() as u64);
+            parts.push(part_span(&upload.name, Some(upload.filename.clone()), start, len));
+            pieces.push_back(Piece::bytes(contents));
+            pieces.push_back(Piece::bytes(PART_END));
+        }
+        pieces.push_back(Piece::bytes(closing_boundary(&self.boundary)));
+        let reader = Pieces(pieces);
+        let length = reader.len();
+        let reader: Box<Read + Send> = match self.progress {
+            Some(ref progress) => {
+                Box::new(ProgressReader::new(reader, parts, length, progress.clone()))
+            }
+            None => Box::new(reader),
+        };
+        Ok(OpenBody {
+            reader: reader,
+            length: Some(length),
+        })
+    }
+}
+
+impl MultipartSource {
+    #[cfg(all(feature = "mmap", unix))]
+    fn file_piece(&self, path: &Path) -> io::Result<Piece> {
+        if self.mmap {
+            // mmap_files passes MappedFile::open's contract on to whoever asked for it.
+            let mapped = try!(unsafe { mmap::MappedFile::open(path) });
+            return Ok(Piece::Mapped(io::Cursor::new(mapped)));
+        }
+        Ok(Piece::File(path.to_path_buf(), None, try!(fs::metadata(path)).len()))
+    }
+
+    #[cfg(not(all(feature = "mmap", unix)))]
+    fn file_piece(&self, path: &Path) -> io::Result<Piece> {
+        Ok(Piece::File(path.to_path_buf(), None, try!(fs::metadata(path)).len()))
+    }
+}
+
+fn queued(pieces: &VecDeque<Piece>) -> u64 {
+    pieces.iter().map(|piece| piece.len()).sum::<u64>()
+}
+
+fn part_span(name: &str, filename: Option<String>, start: u64, len: u64) -> PartSpan {
+    PartSpan {
+        name: name.to_string(),
+        filename: filename,
+        start: start,
+        len: len,
+    }
+}
+
+// What Request::render works out.
+struct Rendered<'a> {
+    url: String,
+    headers: Headers,
+    body: Option<Cow<'a, [u8]>>,
+    body_source: Option<Arc<BodySource + Send + Sync>>,
+}
+
+/// A request with its final url, headers and body, just about to be sent.
+/// Client signers get a mutable one so they can sign exactly what goes on the wire.
+#[derive(Clone)]
+pub struct PreparedRequest<'a> {
+    pub method: Method,
+    pub url: String,
+    pub headers: Headers,
+    /// Borrowed from the Request when sending, owned when it comes from Request::prepare.
+    pub body: Option<Cow<'a, [u8]>>,
+    /// Where the body comes from when it's made while it's sent, body is None then.
+    pub body_source: Option<Arc<BodySource + Send + Sync>>,
+    /// Overrides the Client's proxies when set.
+    pub proxy: Option<ProxySettings>,
+    /// Overrides the name sent in the TLS SNI extension when set.
+    pub sni_hostname: Option<String>,
+    /// Checks the server certificate is valid for this name when set.
+    pub verify_hostname: Option<String>,
+    /// Where it goes in line when the Client is at its concurrency limit.
+    pub priority: Priority,
+    /// Lets the Client's retry policy retry it even if its method isn't idempotent.
+    pub retry_non_idempotent: bool,
+    /// Overrides the Client's cache policy when set.
+    pub cache_policy: Option<CachePolicy>,
+    /// Goes in the request's audit record, see Client::audit.
+    pub audit_tags: Vec<(String, String)>,
+    /// Signs the body again after the Client's body transformers rewrite it, see
+    /// Request::sign_webhook.
+    pub webhook_signer: Option<WebhookSigner>,
+}
+
+impl<'a> fmt::Debug for PreparedRequest<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PreparedRequest")
+         .field("method", &self.method)
+         .field("url", &self.url)
+         .field("headers", &RedactedHeaders::new(&self.headers))
+         .field("body", &self.body.as_ref().map(|body| format!("<{} bytes>", body.len())))
+         .finish()
+    }
+}
+
+impl<'a> PreparedRequest<'a> {
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// The final url, resolved against the base url and with the params of GETs
+    /// and HEADs in its query.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The headers it's sent with, including those curs added on its own, like the
+    /// Content-Type of form and multipart bodies.
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// The first value of this header, if it's set. Names are case insensitive.
+    pub fn header(&self, name: &str) -> Option<String> {
+        first_raw_header(&self.headers, name)
+    }
+
+    /// The exact bytes sent as the body, if there's one.
+    pub fn body(&self) -> Option<&[u8]> {
+        self.body.as_ref().map(|body| &**body)
+    }
+
+    /// How long the body is, 0 without one.
+    pub fn body_len(&self) -> usize {
+        self.body().map(|body| body.len()).unwrap_or(0)
+    }
+
+    /// The same request owning its body, to keep it around or hand it to other
+    /// threads after the Request it came from is gone.
+    pub fn into_owned(self) -> PreparedRequest<'static> {
+        PreparedRequest {
+            method: self.method,
+            url: self.url,
+            headers: self.headers,
+            body: self.body.map(|body| Cow::Owned(body.into_owned())),
+            body_source: self.body_source,
+            proxy: self.proxy,
+            sni_hostname: self.sni_hostname,
+            verify_hostname: self.verify_hostname,
+            priority: self.priority,
+            retry_non_idempotent: self.retry_non_idempotent,
+            cache_policy: self.cache_policy,
+            audit_tags: self.audit_tags,
+            webhook_signer: self.webhook_signer,
+        }
+    }
+
+    // A copy that borrows this one's body, cheap enough to make for every attempt.
+    fn reborrow(&self) -> PreparedRequest {
+        PreparedRequest {
+            method: self.method.clone(),
+            url: self.url.clone(),
+            headers: self.headers.clone(),
+            body: self.body.as_ref().map(|body| Cow::Borrowed(&**body)),
+            body_source: self.body_source.clone(),
+            proxy: self.proxy.clone(),
+            sni_hostname: self.sni_hostname.clone(),
+            verify_hostname: self.verify_hostname.clone(),
+            priority: self.priority,
+            retry_non_idempotent: self.retry_non_idempotent,
+            cache_policy: self.cache_policy,
+            audit_tags: self.audit_tags.clone(),
+            webhook_signer: self.webhook_signer.clone(),
+        }
+    }
+}
+
 /// The main entry point. Craft your request and send it.
 #[derive(Clone)]
 pub struct Request<'a> {
     method: Method,
-    url: &'a str,
+    url: Cow<'a, str>,
     params: Params<'a>,
     headers: Headers,
     files: Vec<FileUpload<'a>>,
-    raw_body: Option<String>,
+    uploads: Vec<BytesUpload>,
+    raw_body: Option<Cow<'a, [u8]>>,
+    body_source: Option<Arc<BodySource + Send + Sync>>,
+    no_body: bool,
+    stream_multipart: bool,
+    force_multipart: bool,
+    mmap_files: bool,
+    base64_parts: Vec<String>,
+    upload_progress: Option<Arc<UploadProgress + Send + Sync>>,
+    client: Option<&'a Client>,
+    webhook_signer: Option<&'a WebhookSigner>,
+    proxy: Option<ProxySettings>,
+    sni_hostname: Option<String>,
+    verify_hostname: Option<String>,
+    priority: Priority,
+    retry_non_idempotent: bool,
+    cache_policy: Option<CachePolicy>,
+    expected_statuses: Vec<StatusCode>,
+    unwrap_field: Option<String>,
+    redacted: Vec<String>,
+    audit_tags: Vec<(String, String)>,
+    problems: Vec<String>,
+}
+
+/// Sensitive headers and the params and headers named with redact show up as
+/// `<redacted>`, and bodies only by their length.
+impl<'a> fmt::Debug for Request<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params: Vec<(&str, &str)> = self.params
+                                            .iter()
+                                            .map(|&(name, value)| {
+                                                if redact::is_sensitive(name, &self.redacted) {
+                                                    (name, "<redacted>")
+                                                } else {
+                                                    (name, value)
+                                                }
+                                            })
+                                            .collect();
+        let headers = RedactedHeaders {
+            headers: &self.headers,
+            extra: &self.redacted,
+        };
+        let files: Vec<&Path> = self.files.iter().map(|file| file.path).collect();
+        let uploads: Vec<&str> = self.uploads.iter().map(|upload| &*upload.filename).collect();
+        f.debug_struct("Request")
+         .field("method", &self.method)
+         .field("url", &self.url)
+         .field("params", &params)
+         .field("headers", &headers)
+         .field("files", &files)
+         .field("uploads", &uploads)
+         .field("body", &self.raw_body.as_ref().map(|body| format!("<{} bytes>", body.len())))
+         .finish()
+    }
 }
 
 impl<'a> Request<'a> {
@@ -203,11 +931,57 @@ impl<'a> Request<'a> {
     pub fn new(method: Method, url: &'a str) -> Request<'a> {
         Request {
             method: method,
-            url: url,
+            url: Cow::Borrowed(url),
             params: vec![],
             headers: Headers::new(),
             files: vec![],
+            uploads: vec![],
             raw_body: None,
+            body_source: None,
+            no_body: false,
+            stream_multipart: false,
+            force_multipart: false,
+            mmap_files: false,
+            base64_parts: vec![],
+            upload_progress: None,
+            client: None,
+            webhook_signer: None,
+            proxy: None,
+            sni_hostname: None,
+            verify_hostname: None,
+            priority: Priority::default(),
+            retry_non_idempotent: false,
+            cache_policy: None,
+            expected_statuses: vec![],
+            unwrap_field: None,
+            redacted: vec![],
+            audit_tags: vec![],
+            problems: vec![],
+        }.checking_url()
+    }
+
+    // Relative urls are fine, the Client may have a base url for them.
+    fn checking_url(mut self) -> Request<'a> {
+        match Url::parse(&self.url) {
+            Ok(_) | Err(UrlError::RelativeUrlWithoutBase) => {}
+            Err(error) => self.problems.push(format!("Invalid url {:?}: {}", self.url, error)),
+        }
+        self
+    }
+
+    /// What's wrong with the request so far: urls that can't be parsed, headers
+    /// with line breaks or other control characters, files to upload that aren't
+    /// there. Sending it fails with CursError::InvalidRequest listing them all,
+    /// before anything goes on the wire.
+    pub fn problems(&self) -> &[String] {
+        &self.problems
+    }
+
+    fn check_problems(&self) -> CursResult<()> {
+        if self.problems.is_empty() {
+            Ok(())
+        } else {
+            Err(CursError::InvalidRequest(self.problems.clone()))
         }
     }
 
@@ -230,21 +1004,163 @@ impl<'a> Request<'a> {
         self
     }
 
+    /// Sets a JSON Merge Patch body (RFC 7396), for PATCH requests: fields in it are
+    /// changed, fields set to null are removed and the rest are left alone. Like
+    /// json, with `application/merge-patch+json` as the Content-Type.
+    pub fn merge_patch<S: Serialize>(&mut self, patch: S) -> &mut Request<'a> {
+        self.json(patch);
+        self.header(ContentType("application/merge-patch+json".parse().unwrap()))
+    }
+
     /// Sets a raw body, overriding anything that was previously set in params.
     /// Make sure to set the content-type header to match whatever you're adding here.
     pub fn override_body(&mut self, body: String) -> &mut Request<'a> {
-        self.raw_body = Some(body);
+        self.raw_body = Some(Cow::Owned(body.into_bytes()));
+        self
+    }
+
+    /// Sets a raw body from bytes, like override_body. Borrowed ones aren't copied,
+    /// not even when the request is retried.
+    pub fn bytes_body<B: Into<Cow<'a, [u8]>>>(&mut self, body: B) -> &mut Request<'a> {
+        self.raw_body = Some(body.into());
         self
     }
 
+    /// Sets the body to this value as the encoder makes it, and the Content-Type to
+    /// match. Like json and override_body it replaces anything set in params.
+    pub fn body_with<E, T>(&mut self, encoder: &E, value: &T) -> CursResult<&mut Request<'a>>
+        where E: BodyEncoder,
+              T: Serialize
+    {
+        self.raw_body = Some(Cow::Owned(try!(encoder.encode(value))));
+        self.header(ContentType(encoder.content_type()));
+        Ok(self)
+    }
+
     /// Add files to upload. This extends the existing files vector.
     pub fn files<F>(&mut self, additional: F) -> &mut Request<'a>
         where F: IntoIterator<Item = FileUpload<'a>>
     {
-        self.files.extend(additional);
+        for file in additional {
+            if !file.path.is_file() {
+                self.problems.push(format!("No file to upload at {}", file.path.display()));
+            }
+            self.files.push(file);
+        }
+        self
+    }
+
+    /// Add uploads from memory, stdin or pipes. They go after the files.
+    pub fn bytes_uploads<U>(&mut self, additional: U) -> &mut Request<'a>
+        where U: IntoIterator<Item = BytesUpload>
+    {
+        self.uploads.extend(additional);
+        self
+    }
+
+    /// Send the file or upload with this name in base64, with a
+    /// `Content-Transfer-Encoding: base64` header, for endpoints that can't take raw
+    /// binary parts.
+    pub fn base64_part(&mut self, name: &str) -> &mut Request<'a> {
+        self.base64_parts.push(name.to_string());
+        self
+    }
+
+    /// Send everything this reader has as the raw body, like stdin in a pipeline.
+    /// It's read whole right away.
+    pub fn body_from_reader<R: Read>(&mut self, mut reader: R) -> CursResult<&mut Request<'a>> {
+        let mut body = vec![];
+        try!(reader.read_to_end(&mut body));
+        self.raw_body = Some(Cow::Owned(body));
+        Ok(self)
+    }
+
+    /// Send what this reader has from where it is now, like a File, reading it
+    /// while it's sent instead of into memory first. It seeks back for every
+    /// retry, see body::Rewindable.
+    pub fn seekable_body<R>(&mut self, reader: R) -> CursResult<&mut Request<'a>>
+        where R: Read + Seek + Send + 'static
+    {
+        Ok(self.body_source(try!(Rewindable::new(reader))))
+    }
+
+    /// Send a body made on the fly while it's sent, see body::BodySource. It's
+    /// left out of memoizing, and webhook signatures sign an empty body.
+    pub fn body_source<S>(&mut self, source: S) -> &mut Request<'a>
+        where S: BodySource + Send + Sync + 'static
+    {
+        self.body_source = Some(Arc::new(source));
+        self
+    }
+
+    /// Send a body a producer makes a chunk at a time, asked for the next chunk
+    /// only once the connection has taken the last one. `start` makes a new
+    /// producer for every attempt. See body::BodyProducer.
+    pub fn body_producer<F, P>(&mut self, start: F) -> &mut Request<'a>
+        where F: Fn() -> P + Send + Sync + 'static,
+              P: BodyProducer + Send + 'static
+    {
+        self.body_source(Produced(start))
+    }
+
+    /// Send it without a body even if its method usually has one, with a
+    /// `Content-Length: 0` and no Content-Type. Params go in the query then, like
+    /// for a GET. POSTs, PUTs and DELETEs without params, files or a body of any
+    /// kind go like this anyway.
+    pub fn no_body(&mut self) -> &mut Request<'a> {
+        self.no_body = true;
+        self
+    }
+
+    /// Send params as multipart/form-data even without any files, for endpoints
+    /// that take nothing else. They're urlencoded otherwise.
+    pub fn force_multipart(&mut self) -> &mut Request<'a> {
+        self.force_multipart = true;
         self
     }
 
+    /// Stream files from disk while sending them instead of reading them all into
+    /// memory first. The Content-Length is still worked out up front, from the
+    /// files' sizes. Like body_source, it's left out of memoizing and webhook
+    /// signatures. See MultipartSource.
+    pub fn stream_multipart(&mut self) -> &mut Request<'a> {
+        self.stream_multipart = true;
+        self
+    }
+
+    /// Stream the files like stream_multipart does, telling this listener how far
+    /// along each part is while they're sent, for showing per-file status.
+    pub fn upload_progress<P>(&mut self, progress: P) -> &mut Request<'a>
+        where P: UploadProgress + Send + Sync + 'static
+    {
+        self.upload_progress = Some(Arc::new(progress));
+        self.stream_multipart()
+    }
+
+    /// Stream the files like stream_multipart does, mapping them into memory
+    /// instead of reading them. A file truncated while it's mapped crashes the
+    /// process, so only use it for files nothing else changes. See mmap::MappedFile.
+    #[cfg(all(feature = "mmap", unix))]
+    pub fn mmap_files(&mut self) -> &mut Request<'a> {
+        self.mmap_files = true;
+        self.stream_multipart()
+    }
+
+    /// Send this file as the raw body through a memory mapping, for files too
+    /// big to read into memory. Nothing may truncate or write to the file while
+    /// it's sent, see mmap::MappedFile::open.
+    #[cfg(all(feature = "mmap", unix))]
+    pub fn mmap_body(&mut self, path: &Path) -> &mut Request<'a> {
+        self.body_source(mmap::MappedBody(path.to_path_buf()))
+    }
+
+    /// Send this directory as a tar archive, made while it's sent. Sets the
+    /// Content-Type to application/x-tar. See archive::TarDirectory.
+    pub fn tar_directory(&mut self, dir: &Path) -> &mut Request<'a> {
+        self.header(ContentType("application/x-tar".parse().unwrap()));
+        self.body_source(TarDirectory::new(dir))
+    }
+
     /// Add a single header.
     pub fn header<H>(&mut self, additional: H) -> &mut Request<'a>
         where H: Header + HeaderFormat
@@ -253,37 +1169,499 @@ impl<'a> Request<'a> {
         self
     }
 
+    /// Add a single header by name, for headers hyper has no type for.
+    /// Sending fails with CursError::InvalidRequest if either has line breaks or
+    /// other control characters, so untrusted values can't smuggle in more headers.
+    pub fn raw_header(&mut self, name: &str, value: &str) -> &mut Request<'a> {
+        if is_bad_header(name, value) {
+            self.problems.push(format!("Header {:?} has control characters", name));
+        }
+        self.headers.set_raw(name.to_string(), vec![value.as_bytes().to_vec()]);
+        self
+    }
+
+    /// Ask for the body in these languages, most wanted first, like
+    /// `&["es-AR", "es;q=0.9", "en;q=0.5"]`. See language::accept_language for how
+    /// weights are given when left out. Sending fails with CursError::InvalidRequest
+    /// if any of them isn't valid. The response's languages are in
+    /// language::Localized.
+    pub fn accept_language(&mut self, languages: &[&str]) -> &mut Request<'a> {
+        match language::accept_language(languages) {
+            Ok(value) => self.raw_header("Accept-Language", &value),
+            Err(problem) => {
+                self.problems.push(problem);
+                self
+            }
+        }
+    }
+
+    /// Only change the resource if its ETag is still this one, exactly as the server
+    /// sent it, so a read-modify-write doesn't overwrite someone else's changes.
+    /// If it's not, send fails with CursError::PreconditionFailed.
+    pub fn if_match(&mut self, etag: &str) -> &mut Request<'a> {
+        self.raw_header("If-Match", etag)
+    }
+
+    /// Only change the resource if it hasn't changed since this HTTP date, usually
+    /// the Last-Modified header of an earlier response, for servers without ETags.
+    /// If it has, send fails with CursError::PreconditionFailed.
+    pub fn if_unmodified_since(&mut self, date: &str) -> &mut Request<'a> {
+        self.raw_header("If-Unmodified-Since", date)
+    }
+
+    /// Only get the range asked for if the resource is still the one with this ETag
+    /// or Last-Modified date, exactly as the server sent them, and the whole of it
+    /// otherwise. For resuming downloads, see range.
+    pub fn if_range(&mut self, etag_or_date: &str) -> &mut Request<'a> {
+        self.raw_header("If-Range", etag_or_date)
+    }
+
+    /// Only get the resource if its ETag changed from this one, exactly as the
+    /// server sent it (quotes included). See DecodableResult::decode_cached.
+    pub fn if_none_match(&mut self, etag: &str) -> &mut Request<'a> {
+        self.raw_header("If-None-Match", etag)
+    }
+
+    /// Only get the resource if it changed after this HTTP date, usually the
+    /// Last-Modified header of an earlier response.
+    pub fn if_modified_since(&mut self, date: &str) -> &mut Request<'a> {
+        self.raw_header("If-Modified-Since", date)
+    }
+
+    /// Adds an Idempotency-Key header with a random UUID to POST, PUT, PATCH and DELETE
+    /// requests. The key is kept once set, so sending this request (or a clone) again
+    /// reuses it and the server can tell it's a retry of the same operation.
+    pub fn idempotent(&mut self) -> &mut Request<'a> {
+        let is_unsafe = match self.method {
+            Method::Post | Method::Put | Method::Patch | Method::Delete => true,
+            _ => false,
+        };
+        if is_unsafe && self.headers.get_raw("Idempotency-Key").is_none() {
+            let key = uuid::Uuid::new_v4().to_hyphenated_string();
+            self.headers.set_raw("Idempotency-Key", vec![key.into_bytes()]);
+        }
+        self
+    }
+
+    /// Send this request through the given proxy, whatever the Client uses.
+    /// The connection is made just for this request, it's not pooled.
+    pub fn via_proxy(&mut self, proxy: Url) -> &mut Request<'a> {
+        self.proxy = Some(ProxySettings {
+            http: Some(proxy.clone()),
+            https: Some(proxy),
+            no_proxy: NoProxy::default(),
+        });
+        self
+    }
+
+    /// Connect directly for this request, even if the Client uses a proxy.
+    /// The connection is made just for this request, it's not pooled.
+    pub fn no_proxy(&mut self) -> &mut Request<'a> {
+        self.proxy = Some(ProxySettings::default());
+        self
+    }
+
+    /// Present this name in the TLS handshake instead of the url's host.
+    /// The connection is made just for this request, it's not pooled.
+    pub fn sni_hostname(&mut self, name: &str) -> &mut Request<'a> {
+        self.sni_hostname = Some(name.to_string());
+        self
+    }
+
+    /// Verify the server certificate against the system CAs, and check it's valid
+    /// for this name. Usually paired with sni_hostname.
+    pub fn verify_hostname(&mut self, name: &str) -> &mut Request<'a> {
+        self.verify_hostname = Some(name.to_string());
+        self
+    }
+
+    /// Go ahead of (or behind) other requests waiting on a Client's concurrency limit.
+    pub fn priority(&mut self, priority: Priority) -> &mut Request<'a> {
+        self.priority = priority;
+        self
+    }
+
+    /// Let the Client's retry policy retry this request even if it's a POST or PATCH.
+    /// Only do it when the server won't act twice on it. Requests with an
+    /// Idempotency-Key header (see idempotent) are already retried.
+    pub fn retry_non_idempotent(&mut self) -> &mut Request<'a> {
+        self.retry_non_idempotent = true;
+        self
+    }
+
+    /// Use the Client's cache differently for this request, like OnlyIfCached to
+    /// stay offline. Only GETs go through the cache.
+    pub fn cache_policy(&mut self, policy: CachePolicy) -> &mut Request<'a> {
+        self.cache_policy = Some(policy);
+        self
+    }
+
+    /// Always check with the server, even if the Client's cache has a fresh
+    /// response. Sends Cache-Control: no-cache, like CachePolicy::Revalidate.
+    pub fn no_cache(&mut self) -> &mut Request<'a> {
+        self.cache_directive("no-cache")
+    }
+
+    /// Don't take stored responses older than this from the Client's cache, however
+    /// long the server said they'd stay fresh. Sends Cache-Control: max-age.
+    pub fn max_age(&mut self, max_age: Duration) -> &mut Request<'a> {
+        self.cache_directive(&format!("max-age={}", max_age.as_secs()))
+    }
+
+    /// Only answer from the Client's cache, with a 504 Gateway Timeout when there's
+    /// nothing stored. Like CachePolicy::OnlyIfCached, sends Cache-Control:
+    /// only-if-cached for any caches along the way.
+    pub fn only_if_cached(&mut self) -> &mut Request<'a> {
+        self.cache_directive("only-if-cached")
+    }
+
+    // Adds to the Cache-Control header instead of replacing it.
+    fn cache_directive(&mut self, directive: &str) -> &mut Request<'a> {
+        let value = match first_raw_header(&self.headers, "Cache-Control") {
+            Some(ref existing) if !existing.is_empty() => format!("{}, {}", existing, directive),
+            _ => directive.to_string(),
+        };
+        self.raw_header("Cache-Control", &value)
+    }
+
+    /// Make send fail with CursError::UnexpectedStatus unless the response has this
+    /// status. Call it again to accept any of several statuses.
+    pub fn expect_status(&mut self, status: StatusCode) -> &mut Request<'a> {
+        self.expected_statuses.push(status);
+        self
+    }
+
+    /// Decode a JSON response wrapped in an envelope, like `{"data": ..., "meta": ...}`,
+    /// as whatever is in this field. See transform::Unwrap.
+    pub fn unwrap_field(&mut self, field: &str) -> &mut Request<'a> {
+        self.unwrap_field = Some(field.to_string());
+        self
+    }
+
+    /// Hide this header or param's value in Debug output, besides the headers in
+    /// redact::SENSITIVE_HEADERS which always are.
+    pub fn redact(&mut self, name: &str) -> &mut Request<'a> {
+        self.redacted.push(name.to_string());
+        self
+    }
+
+    /// Add a field to this request's audit record, like the user or job that
+    /// asked for it. See Client::audit.
+    pub fn audit_tag(&mut self, name: &str, value: &str) -> &mut Request<'a> {
+        self.audit_tags.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Sign the request body for a webhook receiver. See WebhookSigner.
+    pub fn sign_webhook(&mut self, signer: &'a WebhookSigner) -> &mut Request<'a> {
+        self.webhook_signer = Some(signer);
+        self
+    }
+
+    /// Ask for just these bytes, `end` included, or everything from `start` on
+    /// without an end. Servers that can do it answer 206 Partial Content.
+    pub fn range(&mut self, start: u64, end: Option<u64>) -> &mut Request<'a> {
+        let spec = match end {
+            Some(end) => ByteRangeSpec::FromTo(start, end),
+            None => ByteRangeSpec::AllFrom(start),
+        };
+        self.headers.set(Range::Bytes(vec![spec]));
+        self
+    }
+
+    /// Download into this file, picking up where an interrupted download left off:
+    /// only the bytes past the ones already there are asked for. The strong ETag
+    /// or Last-Modified date is kept in `<file>.etag` and sent in If-Range, and if
+    /// the resource changed since, the whole thing is downloaded again. Returns the
+    /// file's length.
+    pub fn resume_download(&self, path: &Path) -> CursResult<u64> {
+        download::resume(self, path)
+    }
+
+    /// Like resume_download, and when the connection drops midway the rest is asked
+    /// for right away, up to `attempts` tries in all. Made for big files on flaky
+    /// networks.
+    pub fn download_with_resume(&self, path: &Path, attempts: u32) -> CursResult<u64> {
+        download::resume_until_done(self, path, attempts)
+    }
+
+    /// Go through every page of a paginated JSON array, following the
+    /// `rel="next"` Link header of each page. Later pages keep this request's
+    /// client, method and headers, so a page linking to another origin fails with
+    /// CursError::InvalidResponse rather than taking them there.
+    pub fn paginate<T: Deserialize>(&self) -> Pages<'a, T> {
+        Pages::new(self.clone())
+    }
+
+    /// Go through every page of an API that links pages with cursors or offsets.
+    /// `next_cursor` finds the cursor in a page's headers or JSON body, and
+    /// `apply_cursor` makes the next page's url out of the current one and the cursor,
+    /// see pagination::with_query_param.
+    pub fn paginate_with<T, N, A>(&self, next_cursor: N, apply_cursor: A) -> Pages<'a, T>
+        where T: Deserialize,
+              N: Fn(&Headers, &serde_json::Value) -> Option<String> + 'a,
+              A: Fn(&Url, &str) -> Url + 'a
+    {
+        Pages::with_cursor(self.clone(), next_cursor, apply_cursor)
+    }
+
+    /// Send and decode a successful JSON response in one go, like
+    /// `send().decode_success()`. Successful responses with a Content-Type other
+    /// than JSON fail with CursError::Decode instead of a confusing parse error.
+    pub fn send_and_decode<T: Deserialize>(&self) -> CursResult<T> {
+        let response = try!(self.send());
+        let success = match response.status {
+            StatusCode::Ok | StatusCode::Created | StatusCode::Accepted => true,
+            _ => false,
+        };
+        if success && !says_json(&response.headers) {
+            let content_type = first_raw_header(&response.headers, "Content-Type");
+            return Err(CursError::Decode(format!("Expected JSON, got {}",
+                                                 content_type.unwrap_or_else(String::new))));
+        }
+        let result: CursResult<Response> = Ok(response);
+        result.decode_success()
+    }
+
+    /// Like send, for a response whose status and headers can be checked before
+    /// deciding what to do with its body. See BufferedResponse.
+    pub fn send_buffered(&self) -> CursResult<BufferedResponse> {
+        Ok(BufferedResponse::new(try!(self.send())))
+    }
+
+    /// Send it `count` times, `concurrency` at a time, and see how long it took.
+    /// Each concurrent sender gets a Client of its own, a clone of the request's
+    /// Client if it has one (see Client::try_clone). Bodies are read and thrown away.
+    pub fn benchmark(&self, count: usize, concurrency: usize) -> CursResult<Benchmark> {
+        benchmark::run(self, count, concurrency)
+    }
+
+    /// Like send, reading the whole body right away: into memory while it's under
+    /// `threshold` bytes, into a temporary file past that. See SpooledBody.
+    pub fn send_spooled(&self, threshold: usize) -> CursResult<SpooledResponse> {
+        SpooledResponse::new(try!(self.send()), threshold)
+    }
+
     /// Send your request and see what happens.
     pub fn send(&self) -> CursResult<Response> {
-        let multipart_raw_body: Box<[u8]>; // We define it here for lifetime reasons.
-        let params_as_query = &*url::form_urlencoded::serialize(&self.params);
-        let mut url_string = self.url.into_url().unwrap().serialize();
-        if self.params.len() > 0 && (self.method == Method::Get || self.method == Method::Head) {
-            url_string = [&*url_string, "?", params_as_query].concat()
-        }
-        let client = Client::new();
-        let mut request = client.request(self.method.clone(), &*url_string)
-                                .headers(self.headers.clone());
-
-        if let Some(ref body) = self.raw_body {
-            request = request.body(&*body)
-        } else {
-            if self.method != Method::Get && self.method != Method::Head {
-                request = if self.files.len() == 0 {
-                    request.header(ContentType("application/x-www-form-urlencoded"
-                                                   .parse()
-                                                   .unwrap()))
-                           .body(params_as_query)
-                } else {
-                    let builder = try!(MultipartBodyBuilder::new()
-                                           .build(self.files.clone(), self.params.clone()));
-                    let raw_mime = ["multipart/form-data; boundary=", &*builder.boundary].concat();
-                    multipart_raw_body = builder.body.into_boxed_slice();
-                    request.header(ContentType(raw_mime.parse().unwrap()))
-                           .body(&*multipart_raw_body)
+        let response = try!(self.send_unchecked());
+        self.check_sent(response)
+    }
+
+    // What send does with the response once it's here.
+    fn check_sent(&self, response: Response) -> CursResult<Response> {
+        let conditional = self.headers.get_raw("If-Match").is_some() ||
+                          self.headers.get_raw("If-Unmodified-Since").is_some();
+        if conditional && response.status == StatusCode::PreconditionFailed {
+            return Err(CursError::PreconditionFailed(response));
+        }
+        if !self.expected_statuses.is_empty() &&
+           !self.expected_statuses.contains(&response.status) {
+            return Err(CursError::UnexpectedStatus(response));
+        }
+        match self.unwrap_field {
+            Some(ref field) => transform_incoming(response, &self.method, &Unwrap(field.clone())),
+            None => Ok(response),
+        }
+    }
+
+    /// An immutable template of this request, to send many times with different
+    /// `{name}` path params, query values and bodies. Its url is resolved against
+    /// the Client's base url and its headers and body worked out just once, here.
+    /// See RequestTemplate.
+    pub fn freeze(&self) -> CursResult<RequestTemplate<'a>> {
+        RequestTemplate::new(self)
+    }
+
+    /// The request as it would go out, rendered the same way every time so tests can
+    /// compare it with a snapshot: the method and url (query as it was built, not
+    /// sorted), the headers sorted by their lowercased names, a blank line and the
+    /// body. Multipart bodies use `curs-canonical-boundary` as their boundary, and
+    /// bytes that aren't UTF-8 are replaced. The Client's default headers, credentials
+    /// and signer aren't included, they're only added when sending, and neither is a
+    /// webhook signature, whose timestamp changes every second.
+    pub fn to_canonical_string(&self) -> CursResult<String> {
+        let url = try!(self.resolve_url());
+        let Rendered { url, mut headers, body, body_source } =
+            try!(self.render(url, Some(CANONICAL_BOUNDARY)));
+        if let Some(signer) = self.webhook_signer {
+            headers.remove_raw(signer.header());
+        }
+        let mut lines: Vec<String> = headers.iter()
+                                            .map(|header| {
+                                                format!("{}: {}",
+                                                        header.name().to_lowercase(),
+                                                        header.value_string())
+                                            })
+                                            .collect();
+        lines.sort();
+        let mut canonical = format!("{} {}\n", self.method, url);
+        for line in lines {
+            canonical.push_str(&line);
+            canonical.push('\n');
+        }
+        canonical.push('\n');
+        if let Some(body) = body {
+            canonical.push_str(&String::from_utf8_lossy(&body));
+        } else if let Some(source) = body_source {
+            let mut streamed = vec![];
+            try!(try!(source.open()).reader.read_to_end(&mut streamed));
+            canonical.push_str(&String::from_utf8_lossy(&streamed));
+        }
+        Ok(canonical)
+    }
+
+    fn resolve_url(&self) -> CursResult<Url> {
+        try!(self.check_problems());
+        Ok(match self.client.and_then(|c| c.base()) {
+            Some(base) => try!(base.join(&self.url)),
+            None => try!((&*self.url).into_url()),
+        })
+    }
+
+    // The final url, headers and body (or where it streams from), before the Client
+    // adds its own.
+    // Multipart bodies get this boundary, or a random one.
+    fn render<'s>(&'s self,
+                  mut url: Url,
+                  boundary: Option<&str>)
+                  -> CursResult<Rendered<'s>> {
+        let params_as_query = url::form_urlencoded::serialize(&self.params);
+        let bodyless = self.no_body || self.method == Method::Get || self.method == Method::Head;
+        if self.params.len() > 0 && bodyless {
+            // Going through Url keeps any existing query, fragments and IPv6 hosts intact.
+            url.query = Some(match url.query.take() {
+                Some(ref query) if !query.is_empty() => [&**query, "&", &*params_as_query].concat(),
+                _ => params_as_query.clone(),
+            });
+        }
+        let mut headers = self.headers.clone();
+
+        let mut source = if self.no_body { None } else { self.body_source.clone() };
+        let nothing_to_send = self.params.is_empty() && self.files.is_empty() &&
+                              self.uploads.is_empty() && !self.force_multipart;
+        let body: Option<Cow<[u8]>> = if self.no_body {
+            None
+        } else if let Some(ref body) = self.raw_body {
+            Some(Cow::Borrowed(&body[..]))
+        } else if source.is_some() {
+            None
+        } else if !bodyless && !nothing_to_send {
+            if self.files.len() == 0 && self.uploads.len() == 0 && !self.force_multipart {
+                headers.set(ContentType("application/x-www-form-urlencoded".parse().unwrap()));
+                Some(Cow::Owned(params_as_query.into_bytes()))
+            } else if self.stream_multipart || self.over_stream_threshold() {
+                let mut multipart = MultipartSource::new(self.files.clone(),
+                                                         self.uploads.clone(),
+                                                         self.params.clone());
+                if let Some(boundary) = boundary {
+                    multipart = multipart.with_boundary(boundary);
                 }
+                multipart.progress = self.upload_progress.clone();
+                multipart.mmap = self.mmap_files;
+                multipart.base64 = self.base64_parts.clone();
+                let raw_mime = ["multipart/form-data; boundary=", multipart.boundary()].concat();
+                headers.set(ContentType(raw_mime.parse().unwrap()));
+                source = Some(Arc::new(multipart));
+                None
+            } else {
+                let builder = match boundary {
+                    Some(boundary) => MultipartBodyBuilder::with_boundary(boundary),
+                    None => MultipartBodyBuilder::new(),
+                };
+                let builder = builder.base64_parts(self.base64_parts.clone());
+                let builder = try!(builder.build_with_bytes(self.files.clone(),
+                                                            self.uploads.clone(),
+                                                            self.params.clone()));
+                let raw_mime = ["multipart/form-data; boundary=", &*builder.boundary].concat();
+                headers.set(ContentType(raw_mime.parse().unwrap()));
+                Some(Cow::Owned(builder.body))
+            }
+        } else {
+            None
+        };
+
+        if let Some(signer) = self.webhook_signer {
+            let signature = signer.sign(body.as_ref().map(|b| &**b).unwrap_or(&[]));
+            headers.set_raw(signer.header().to_string(), vec![signature.into_bytes()]);
+        }
+        Ok(Rendered {
+            url: url.serialize(),
+            headers: headers,
+            body: body,
+            body_source: source,
+        })
+    }
+
+    // Whether the Client wants the multipart body streamed, see
+    // Client::stream_bodies_above.
+    fn over_stream_threshold(&self) -> bool {
+        let threshold = match self.client.and_then(|client| client.stream_threshold()) {
+            Some(threshold) => threshold,
+            None => return false,
+        };
+        let files = self.files
+                        .iter()
+                        .map(|file| fs::metadata(file.path).map(|m| m.len()).unwrap_or(0))
+                        .sum::<u64>();
+        let uploads = self.uploads.iter().map(|upload| upload.bytes.len() as u64).sum::<u64>();
+        files + uploads > threshold
+    }
+
+    /// Everything send does before touching the network: resolving the url against
+    /// the Client's base url, adding params, building the body and its Content-Type
+    /// and signing webhooks. Handy to show or confirm what would be sent, or to test
+    /// a Client signer. The Client's default headers and credentials are added later,
+    /// when it's sent with Client::send_prepared.
+    pub fn prepare(&self) -> CursResult<PreparedRequest> {
+        let url = try!(self.resolve_url());
+        self.prepare_url(url)
+    }
+
+    fn prepare_url(&self, url: Url) -> CursResult<PreparedRequest> {
+        let Rendered { url, headers, body, body_source } = try!(self.render(url, None));
+        Ok(PreparedRequest {
+            method: self.method.clone(),
+            url: url,
+            headers: headers,
+            body: body,
+            body_source: body_source,
+            proxy: self.proxy.clone(),
+            sni_hostname: self.sni_hostname.clone(),
+            verify_hostname: self.verify_hostname.clone(),
+            priority: self.priority,
+            retry_non_idempotent: self.retry_non_idempotent,
+            cache_policy: self.cache_policy,
+            audit_tags: self.audit_tags.clone(),
+            webhook_signer: self.webhook_signer.cloned(),
+        })
+    }
+
+    fn send_unchecked(&self) -> CursResult<Response> {
+        let url = try!(self.resolve_url());
+        let guarded = self.client.map(|client| client.refuses_private_addresses()).unwrap_or(false);
+        match &*url.scheme {
+            "http" | "https" => {}
+            // Reading local files or anything else but the network would get around it.
+            scheme if guarded => {
+                let why = format!("{}: urls are refused while private addresses are blocked",
+                                  scheme);
+                return Err(CursError::from(io::Error::new(io::ErrorKind::PermissionDenied, why)));
+            }
+            "file" if self.client.map(|client| client.allows_file_urls()).unwrap_or(false) => {
+                return local::file_response(url, &self.method)
             }
+            "file" => {
+                let why = "file: urls need Client::allow_file_urls".to_string();
+                return Err(CursError::from(io::Error::new(io::ErrorKind::PermissionDenied, why)));
+            }
+            "data" => return local::data_response(url, &self.method),
+            _ => {}
+        }
+        let prepared = try!(self.prepare_url(url));
+        match self.client {
+            Some(client) => client.send_prepared(prepared),
+            None => Client::new().send_prepared(prepared),
         }
-        Ok(try!(request.send()))
     }
 }
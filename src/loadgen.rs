@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::Duration;
+
+use super::{CursError, CursResult, Request};
+use benchmark::{self, Benchmark};
+use clock::{self, Clock, SystemClock};
+
+/// How hard to push an endpoint, see run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoadPlan {
+    /// Requests started per second.
+    pub rate: u32,
+    /// For how long to keep starting them.
+    pub duration: Duration,
+    /// At most this many in flight at once. When they're all busy the next
+    /// requests wait for one to finish, so the rate can fall behind.
+    pub concurrency: usize,
+}
+
+/// How one of the requests went, as it comes in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadResult {
+    /// Counting from 0, in the order they were scheduled.
+    pub sequence: usize,
+    /// When it was due, since the run started.
+    pub scheduled: Duration,
+    /// Until the whole body was read.
+    pub latency: Duration,
+    pub status: Option<u16>,
+    /// Why it failed, when it did.
+    pub error: Option<String>,
+}
+
+// Whole seconds and the rest apart, so no product overflows: a fraction of a
+// second times a u32 rate still fits in a u64.
+fn request_count(plan: &LoadPlan) -> Option<usize> {
+    let rate = plan.rate as u64;
+    let fraction = plan.duration.subsec_nanos() as u64 * rate / 1_000_000_000;
+    let total = match plan.duration.as_secs().checked_mul(rate) {
+        Some(whole) => whole.checked_add(fraction),
+        None => None,
+    };
+    match total {
+        Some(total) if total <= usize::max_value() as u64 => Some(total as usize),
+        _ => None,
+    }
+}
+
+// When the request with this sequence number is due, since the run started.
+fn due(sequence: usize, rate: u32) -> Duration {
+    let (sequence, rate) = (sequence as u64, rate as u64);
+    Duration::new(sequence / rate, (sequence % rate * 1_000_000_000 / rate) as u32)
+}
+
+/// Sends this request `plan.rate` times a second for `plan.duration`, for smoke
+/// testing endpoints under some load. `on_result` gets every result as it comes
+/// in, on the calling thread. Like Request::benchmark, each concurrent sender
+/// gets a Client of its own. Returns the whole run's numbers.
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use curs::{Method, Request};
+/// # use curs::loadgen::{self, LoadPlan};
+/// let plan = LoadPlan {
+///     rate: 50,
+///     duration: Duration::from_secs(10),
+///     concurrency: 8,
+/// };
+/// let request = Request::new(Method::Get, "http://example.com/health");
+/// let summary = loadgen::run(&request, &plan, |result| {
+///     if result.error.is_some() { println!("#{} failed", result.sequence) }
+/// });
+/// ```
+pub fn run<F>(request: &Request, plan: &LoadPlan, mut on_result: F) -> CursResult<Benchmark>
+    where F: FnMut(&LoadResult)
+{
+    let total = try!(request_count(plan).ok_or_else(|| {
+        CursError::Config(format!("{:?} is too many requests to count", plan))
+    }));
+    let rate = plan.rate;
+    let prepared = Arc::new(try!(request.prepare()).into_owned());
+    let clients = try!(benchmark::worker_clients(request, plan.concurrency.max(1).min(total)));
+
+    // Tickets are handed over only to a sender that's free, that's the cap.
+    let (tickets, ticket_receiver) = mpsc::sync_channel::<(usize, Duration)>(0);
+    let ticket_receiver = Arc::new(Mutex::new(ticket_receiver));
+    let (sender, receiver) = mpsc::channel();
+    let started = SystemClock.now();
+    let workers: Vec<_> = clients.into_iter()
+                                 .map(|client| {
+                                     let prepared = prepared.clone();
+                                     let tickets = ticket_receiver.clone();
+                                     let sender = sender.clone();
+                                     thread::spawn(move || {
+                                         loop {
+                                             let ticket = tickets.lock().unwrap().recv();
+                                             let (sequence, scheduled) = match ticket {
+                                                 Ok(ticket) => ticket,
+                                                 Err(_) => return,
+                                             };
+                                             let (latency, result) =
+                                                 benchmark::timed(&client, &prepared);
+                                             let _ = sender.send(LoadResult {
+                                                 sequence: sequence,
+                                                 scheduled: scheduled,
+                                                 latency: latency,
+                                                 status: result.as_ref()
+                                                               .ok()
+                                                               .map(|r| r.status.to_u16()),
+                                                 error: result.err()
+                                                              .map(|e| format!("{:?}", e)),
+                                             });
+                                         }
+                                     })
+                                 })
+                                 .collect();
+    drop(sender);
+    let dispatcher = thread::spawn(move || {
+        for sequence in 0..total {
+            let scheduled = due(sequence, rate);
+            let elapsed = clock::since(&SystemClock, started);
+            if scheduled > elapsed {
+                SystemClock.sleep(scheduled - elapsed);
+            }
+            if tickets.send((sequence, scheduled)).is_err() {
+                return;
+            }
+        }
+    });
+
+    let (mut latencies, mut errors, mut statuses) = (vec![], 0, BTreeMap::new());
+    for result in receiver {
+        on_result(&result);
+        match result.status {
+            Some(status) => {
+                latencies.push(result.latency);
+                *statuses.entry(status).or_insert(0) += 1;
+            }
+            None => errors += 1,
+        }
+    }
+    let _ = dispatcher.join();
+    for worker in workers {
+        let _ = worker.join();
+    }
+    let elapsed = clock::since(&SystemClock, started);
+    Ok(Benchmark::new(&latencies, errors, statuses, elapsed))
+}
@@ -0,0 +1,125 @@
+use std::fs::File;
+use std::io::{self, Cursor, Read, Write};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use hyper::net::NetworkStream;
+use mime_guess::guess_mime_type;
+use rustc_serialize::base64::FromBase64;
+use url::Url;
+use url::percent_encoding::percent_decode;
+
+use super::{CursResult, Method, Response, StatusCode};
+
+/// A fake connection that replays a response we made up, for urls that never hit the network.
+struct LocalStream(Cursor<Vec<u8>>);
+
+impl Read for LocalStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for LocalStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl NetworkStream for LocalStream {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        Err(io::Error::new(io::ErrorKind::NotConnected, "Local responses have no peer"))
+    }
+
+    fn set_read_timeout(&self, _: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_write_timeout(&self, _: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Builds a Response as if a server had sent it, with a Content-Length matching the body.
+/// HEAD requests get the headers only.
+pub fn response(url: Url,
+                method: &Method,
+                status: StatusCode,
+                content_type: &str,
+                body: &[u8])
+                -> CursResult<Response> {
+    let headers = [("Content-Type".to_string(), content_type.to_string())];
+    response_with_headers(url, method, status, &headers, body)
+}
+
+/// Same as response, with any headers. Content-Length and Transfer-Encoding are
+/// left out since the body is all there.
+pub fn response_with_headers(url: Url,
+                             method: &Method,
+                             status: StatusCode,
+                             headers: &[(String, String)],
+                             body: &[u8])
+                             -> CursResult<Response> {
+    let mut raw = format!("HTTP/1.1 {}\r\n", status).into_bytes();
+    for &(ref name, ref value) in headers {
+        let lowercase = name.to_lowercase();
+        if lowercase != "content-length" && lowercase != "transfer-encoding" {
+            raw.extend(format!("{}: {}\r\n", name, value).as_bytes());
+        }
+    }
+    raw.extend(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes());
+    if *method != Method::Head {
+        raw.extend(body);
+    }
+    Ok(try!(Response::new(url, Box::new(LocalStream(Cursor::new(raw))))))
+}
+
+/// Reads a file:// url from disk. The Content-Type is guessed from the file name.
+/// Requests only get here when their Client allows it, see Client::allow_file_urls.
+pub fn file_response(url: Url, method: &Method) -> CursResult<Response> {
+    let path = try!(url.to_file_path().map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidInput, "Not a local file path")
+    }));
+    let mut body = vec![];
+    try!(try!(File::open(&path)).read_to_end(&mut body));
+    let content_type = guess_mime_type(&path).to_string();
+    response(url, method, StatusCode::Ok, &content_type, &body)
+}
+
+/// Decodes a data: url, base64 or percent-encoded, into a response.
+/// Without a media type it's text/plain;charset=US-ASCII, as RFC 2397 says.
+pub fn data_response(url: Url, method: &Method) -> CursResult<Response> {
+    let invalid = |why: &str| io::Error::new(io::ErrorKind::InvalidData, why.to_string());
+    let (content_type, body) = {
+        let data = try!(url.non_relative_scheme_data().ok_or(invalid("Malformed data url")));
+        let comma = try!(data.find(',').ok_or(invalid("Data url without a comma")));
+        let (meta, payload) = (&data[..comma], &data[comma + 1..]);
+
+        let base64 = meta.ends_with(";base64");
+        let media_type = if base64 {
+            &meta[..meta.len() - ";base64".len()]
+        } else {
+            meta
+        };
+        let media_type = if media_type.is_empty() {
+            "text/plain;charset=US-ASCII".to_string()
+        } else if media_type.starts_with(';') {
+            format!("text/plain{}", media_type)
+        } else {
+            media_type.to_string()
+        };
+
+        let decoded = percent_decode(payload.as_bytes());
+        let bytes = if base64 {
+            try!(decoded.from_base64().map_err(|_| invalid("Invalid base64 in data url")))
+        } else {
+            decoded
+        };
+        (media_type, bytes)
+    };
+    response(url, method, StatusCode::Ok, &content_type, &body)
+}
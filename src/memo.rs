@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use openssl::crypto::hash::{Type, hash};
+use rustc_serialize::hex::ToHex;
+
+use super::PreparedRequest;
+use cache::CachedResponse;
+
+/// How many responses a Client memoizes before forgetting the least recently used.
+pub const CAPACITY: usize = 1000;
+
+struct Entry {
+    response: CachedResponse,
+    /// Since the unix epoch, on the Client's clock.
+    stored: Duration,
+    used: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    map: HashMap<String, Entry>,
+    // Ticks on every use, so the least recently used entry has the lowest `used`.
+    clock: u64,
+}
+
+/// Responses kept for a fixed time, keyed by the request that got them.
+/// Unlike the HTTP cache it doesn't care what the server says about caching.
+pub struct Memo {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+/// Headers that change who is asking or what they get back, so requests only
+/// differing in them must not share a memoized response.
+const VARYING: [&'static str; 3] = ["Accept", "Authorization", "Cookie"];
+
+/// Method, url (query included), a hash of the body and one of the VARYING headers.
+pub fn fingerprint(prepared: &PreparedRequest) -> String {
+    let body = hash(Type::SHA256, prepared.body().unwrap_or(&[])).to_hex();
+    let mut varying = vec![];
+    for name in VARYING.iter() {
+        varying.extend_from_slice(name.as_bytes());
+        for value in prepared.headers.get_raw(name).unwrap_or(&[]) {
+            varying.push(b'\n');
+            varying.extend_from_slice(value);
+        }
+        varying.push(0);
+    }
+    let headers = hash(Type::SHA256, &varying).to_hex();
+    format!("{} {} {} {}", prepared.method, prepared.url, body, headers)
+}
+
+impl Memo {
+    pub fn new(ttl: Duration, capacity: usize) -> Memo {
+        Memo {
+            ttl: ttl,
+            capacity: capacity,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// `now` is the Client's clock's time.
+    pub fn get(&self, key: &str, now: Duration) -> Option<CachedResponse> {
+        let mut entries = self.entries.lock().unwrap();
+        let expired = match entries.map.get(key) {
+            Some(entry) => now < entry.stored || now - entry.stored >= self.ttl,
+            None => return None,
+        };
+        if expired {
+            entries.map.remove(key);
+            return None;
+        }
+        entries.clock += 1;
+        let clock = entries.clock;
+        entries.map.get_mut(key).map(|entry| {
+            entry.used = clock;
+            entry.response.clone()
+        })
+    }
+
+    pub fn put(&self, key: String, response: CachedResponse, now: Duration) {
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.map.contains_key(&key) && entries.map.len() >= self.capacity {
+            let oldest = entries.map
+                                .iter()
+                                .min_by_key(|&(_, entry)| entry.used)
+                                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.map.remove(&oldest);
+            }
+        }
+        entries.clock += 1;
+        let entry = Entry {
+            response: response,
+            stored: now,
+            used: entries.clock,
+        };
+        entries.map.insert(key, entry);
+    }
+}
@@ -0,0 +1,103 @@
+use std::fs::File;
+use std::io::{self, Cursor};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::slice;
+
+use libc;
+
+use body::{BodySource, OpenBody};
+
+/// A file mapped into memory read only, so sending it copies straight from the
+/// page cache instead of reading it into a buffer of its own first. Files bigger
+/// than the address space, like multi-gigabyte ones on 32-bit platforms, can't be
+/// mapped.
+pub struct MappedFile {
+    ptr: *mut u8,
+    len: usize,
+}
+
+// The mapping is read only and lives until it's dropped.
+unsafe impl Send for MappedFile {}
+unsafe impl Sync for MappedFile {}
+
+impl MappedFile {
+    /// Maps the whole file.
+    ///
+    /// # Safety
+    ///
+    /// Nothing may truncate or write to the file while the mapping lives. Reading
+    /// past a truncated end kills the process with SIGBUS, and writes show up in
+    /// slices already handed out, which are supposed to never change.
+    pub unsafe fn open(path: &Path) -> io::Result<MappedFile> {
+        let file = try!(File::open(path));
+        let len = try!(file.metadata()).len();
+        if len > usize::max_value() as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("{} is too big to map", path.display())));
+        }
+        // Empty mappings aren't allowed, and there'd be nothing to read anyway.
+        if len == 0 {
+            return Ok(MappedFile {
+                ptr: ptr::null_mut(),
+                len: 0,
+            });
+        }
+        let ptr = libc::mmap(ptr::null_mut(),
+                             len as libc::size_t,
+                             libc::PROT_READ,
+                             libc::MAP_PRIVATE,
+                             file.as_raw_fd(),
+                             0);
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(MappedFile {
+            ptr: ptr as *mut u8,
+            len: len as usize,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl AsRef<[u8]> for MappedFile {
+    fn as_ref(&self) -> &[u8] {
+        if self.len == 0 {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(self.ptr, self.len) }
+        }
+    }
+}
+
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            unsafe {
+                libc::munmap(self.ptr as *mut libc::c_void, self.len as libc::size_t);
+            }
+        }
+    }
+}
+
+/// A file sent as the raw body through a mapping, see Request::mmap_body.
+/// It's mapped again every time it's opened, so retries see it as it is then.
+/// Like MappedFile::open, it needs the file left alone while it's being sent.
+#[derive(Clone, Debug)]
+pub struct MappedBody(pub PathBuf);
+
+impl BodySource for MappedBody {
+    fn open(&self) -> io::Result<OpenBody> {
+        // Whoever asked for a MappedBody vouched for the file, see Request::mmap_body.
+        let mapped = try!(unsafe { MappedFile::open(&self.0) });
+        let length = mapped.len() as u64;
+        Ok(OpenBody {
+            reader: Box::new(Cursor::new(mapped)),
+            length: Some(length),
+        })
+    }
+}
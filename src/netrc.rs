@@ -0,0 +1,104 @@
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use idn::to_ascii;
+use secret::Secret;
+
+/// Login and password for a machine in a .netrc file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetrcEntry {
+    pub login: String,
+    pub password: Option<Secret>,
+}
+
+/// The parsed contents of a .netrc file, like curl --netrc reads them.
+#[derive(Clone, Debug, Default)]
+pub struct Netrc {
+    machines: Vec<(String, NetrcEntry)>,
+    default: Option<NetrcEntry>,
+}
+
+impl Netrc {
+    /// Reads ~/.netrc. A missing or unreadable file is just an empty Netrc.
+    pub fn from_home() -> Netrc {
+        env::home_dir()
+            .and_then(|home| Netrc::from_file(&home.join(".netrc")).ok())
+            .unwrap_or_else(Netrc::default)
+    }
+
+    pub fn from_file(path: &Path) -> ::std::io::Result<Netrc> {
+        let mut contents = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut contents));
+        Ok(Netrc::parse(&contents))
+    }
+
+    pub fn parse(contents: &str) -> Netrc {
+        let mut netrc = Netrc::default();
+        // Machine name (None for default) and the entry being filled in.
+        let mut current: Option<(Option<String>, NetrcEntry)> = None;
+        let mut lines = contents.lines();
+
+        while let Some(line) = lines.next() {
+            let mut tokens = line.split_whitespace();
+            while let Some(token) = tokens.next() {
+                match token {
+                    "machine" | "default" => {
+                        netrc.push(current.take());
+                        let name = if token == "machine" {
+                            // Hosts come out of urls in punycode, so compare in punycode.
+                            tokens.next().map(|n| to_ascii(n).unwrap_or(n.to_string()))
+                        } else {
+                            None
+                        };
+                        let entry = NetrcEntry {
+                            login: String::new(),
+                            password: None,
+                        };
+                        current = Some((name, entry));
+                    }
+                    "login" | "password" | "account" => {
+                        let value = tokens.next().unwrap_or("").to_string();
+                        if let Some((_, ref mut entry)) = current {
+                            match token {
+                                "login" => entry.login = value,
+                                "password" => entry.password = Some(Secret::from(value)),
+                                _ => {}
+                            }
+                        }
+                    }
+                    "macdef" => {
+                        // Macro definitions run until the next blank line.
+                        while let Some(macro_line) = lines.next() {
+                            if macro_line.trim().is_empty() {
+                                break;
+                            }
+                        }
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        netrc.push(current);
+        netrc
+    }
+
+    fn push(&mut self, parsed: Option<(Option<String>, NetrcEntry)>) {
+        match parsed {
+            Some((Some(machine), entry)) => self.machines.push((machine, entry)),
+            Some((None, entry)) => self.default = Some(entry),
+            None => {}
+        }
+    }
+
+    /// The first entry for this host, falling back to the default one.
+    pub fn lookup(&self, host: &str) -> Option<&NetrcEntry> {
+        self.machines
+            .iter()
+            .find(|&&(ref machine, _)| machine == host)
+            .map(|&(_, ref entry)| entry)
+            .or(self.default.as_ref())
+    }
+}
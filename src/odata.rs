@@ -0,0 +1,76 @@
+use super::Params;
+
+/// Builds OData system query options ($filter, $select, $top, $skip, $orderby).
+/// Pass its params to your Request, they get urlencoded like any other param.
+#[derive(Clone, Debug, Default)]
+pub struct ODataQuery {
+    filter: Option<String>,
+    select: Option<String>,
+    top: Option<String>,
+    skip: Option<String>,
+    orderby: Option<String>,
+}
+
+/// Quotes a string as an OData literal, doubling any single quotes in it.
+/// Use it when building filters from user input: `format!("Name eq {}", literal(name))`.
+pub fn literal(value: &str) -> String {
+    format!("'{}'", value.replace("'", "''"))
+}
+
+fn append(target: &mut Option<String>, separator: &str, value: &str) {
+    *target = Some(match target.take() {
+        Some(existing) => format!("{}{}{}", existing, separator, value),
+        None => value.to_string(),
+    });
+}
+
+impl ODataQuery {
+    pub fn new() -> ODataQuery {
+        ODataQuery::default()
+    }
+
+    /// Add a $filter expression. Calling it again joins expressions with `and`.
+    pub fn filter(&mut self, expression: &str) -> &mut ODataQuery {
+        self.filter = Some(match self.filter.take() {
+            Some(existing) => format!("({}) and ({})", existing, expression),
+            None => expression.to_string(),
+        });
+        self
+    }
+
+    /// Add fields to $select. This extends the existing selection.
+    pub fn select(&mut self, fields: &[&str]) -> &mut ODataQuery {
+        for field in fields {
+            append(&mut self.select, ",", field);
+        }
+        self
+    }
+
+    pub fn top(&mut self, count: u64) -> &mut ODataQuery {
+        self.top = Some(count.to_string());
+        self
+    }
+
+    pub fn skip(&mut self, count: u64) -> &mut ODataQuery {
+        self.skip = Some(count.to_string());
+        self
+    }
+
+    /// Add an $orderby clause, like "Name desc". This extends the existing ordering.
+    pub fn order_by(&mut self, clause: &str) -> &mut ODataQuery {
+        append(&mut self.orderby, ",", clause);
+        self
+    }
+
+    /// The query options as params, ready for Request::params.
+    pub fn params(&self) -> Params {
+        let options = [("$filter", &self.filter),
+                       ("$select", &self.select),
+                       ("$top", &self.top),
+                       ("$skip", &self.skip),
+                       ("$orderby", &self.orderby)];
+        options.iter()
+               .filter_map(|&(name, value)| value.as_ref().map(|v| (name, &**v)))
+               .collect()
+    }
+}
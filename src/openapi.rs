@@ -0,0 +1,345 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use serde_json::{self, Value};
+use url;
+
+use super::{CursError, CursResult};
+
+/// Writes the Rust code for an OpenAPI spec into `out`, for build scripts. See
+/// generate.
+///
+/// ```no_run
+/// // build.rs
+/// extern crate curs;
+/// use std::env;
+/// use std::path::Path;
+///
+/// fn main() {
+///     let out = Path::new(&env::var("OUT_DIR").unwrap()).join("petstore.rs");
+///     curs::openapi::generate_file(Path::new("petstore.json"), &out).unwrap();
+/// }
+/// ```
+pub fn generate_file(spec: &Path, out: &Path) -> CursResult<()> {
+    let mut contents = String::new();
+    try!(try!(File::open(spec)).read_to_string(&mut contents));
+    let code = try!(generate(&contents));
+    try!(try!(File::create(out)).write_all(code.as_bytes()));
+    Ok(())
+}
+
+/// Rust code for an OpenAPI 3 or Swagger 2 spec, in JSON. Every schema becomes a
+/// struct deriving serde's Serialize and Deserialize, so the crate including the
+/// code needs serde's derive. Every operation becomes a function that takes a
+/// Client, the path and query params and the JSON body, and decodes the JSON
+/// response with decode_success:
+///
+/// ```text
+/// pub fn get_pet(client: &::curs::Client, pet_id: &str) -> ::curs::CursResult<Pet>
+/// ```
+///
+/// Parameters shared by a whole path come first, and an operation's own parameter
+/// replaces the shared one with the same name and location. Paths are relative,
+/// so the Client needs a base_url. Include the code with
+/// `include!(concat!(env!("OUT_DIR"), "/petstore.rs"))`.
+pub fn generate(spec: &str) -> CursResult<String> {
+    let spec: Value = try!(serde_json::from_str(spec));
+    let mut code = String::from("// Generated by curs::openapi, edit the spec instead.\n");
+
+    let schemas = spec.find_path(&["components", "schemas"])
+                      .or_else(|| spec.find("definitions"))
+                      .and_then(|schemas| schemas.as_object());
+    if let Some(schemas) = schemas {
+        for (name, schema) in schemas {
+            code.push_str(&schema_struct(name, schema));
+        }
+    }
+
+    let paths = match spec.find("paths").and_then(|paths| paths.as_object()) {
+        Some(paths) => paths,
+        None => return Err(CursError::Config("Not an OpenAPI spec: it has no paths".to_string())),
+    };
+    for (path, item) in paths {
+        let operations = match item.as_object() {
+            Some(operations) => operations,
+            None => continue,
+        };
+        let shared = item.find("parameters").and_then(|p| p.as_array()).cloned();
+        for method in &["get", "put", "post", "delete", "patch", "head"] {
+            if let Some(operation) = operations.get(*method) {
+                code.push_str(&operation_fn(path, method, operation, shared.as_ref()));
+            }
+        }
+    }
+    Ok(code)
+}
+
+/// Percent-encodes everything but unreserved characters, for values that go in a
+/// single path segment. Used by generated code.
+pub fn path_segment(value: &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// The url with the params that are there in its query. Used by generated code.
+pub fn with_query(url: &str, params: &[(&str, Option<&str>)]) -> String {
+    let present: Vec<(&str, &str)> = params.iter()
+                                           .filter_map(|&(name, value)| value.map(|v| (name, v)))
+                                           .collect();
+    if present.is_empty() {
+        url.to_string()
+    } else {
+        format!("{}?{}", url, url::form_urlencoded::serialize(&present))
+    }
+}
+
+const KEYWORDS: &'static [&'static str] = &["as", "box", "break", "const", "continue", "crate",
+                                            "else", "enum", "extern", "false", "fn", "for", "if",
+                                            "impl", "in", "let", "loop", "match", "mod", "move",
+                                            "mut", "pub", "ref", "return", "self", "static",
+                                            "struct", "super", "trait", "true", "type", "unsafe",
+                                            "use", "where", "while"];
+
+fn words(name: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut previous_lower = false;
+    for c in name.chars() {
+        if !c.is_alphanumeric() {
+            if !word.is_empty() {
+                words.push(word.clone());
+                word.clear();
+            }
+            previous_lower = false;
+            continue;
+        }
+        if c.is_uppercase() && previous_lower && !word.is_empty() {
+            words.push(word.clone());
+            word.clear();
+        }
+        previous_lower = c.is_lowercase() || c.is_numeric();
+        word.extend(c.to_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn snake_case(name: &str) -> String {
+    let snake = words(name).join("_");
+    let first = snake.chars().next();
+    let snake = match first {
+        Some(c) if c.is_numeric() => format!("_{}", snake),
+        None => "_".to_string(),
+        _ => snake,
+    };
+    if KEYWORDS.iter().any(|keyword| *keyword == snake) {
+        format!("{}_", snake)
+    } else {
+        snake
+    }
+}
+
+fn camel_case(name: &str) -> String {
+    let camel: String = words(name)
+                            .iter()
+                            .map(|word| {
+                                let mut chars = word.chars();
+                                match chars.next() {
+                                    Some(first) => {
+                                        first.to_uppercase().chain(chars).collect::<String>()
+                                    }
+                                    None => String::new(),
+                                }
+                            })
+                            .collect();
+    let first = camel.chars().next();
+    match first {
+        Some(c) if c.is_numeric() => format!("_{}", camel),
+        None => "_".to_string(),
+        _ => camel,
+    }
+}
+
+// The Rust type for a schema. Inline objects and anything unknown stay as JSON.
+fn rust_type(schema: &Value) -> String {
+    if let Some(reference) = schema.find("$ref").and_then(|r| r.as_str()) {
+        return camel_case(reference.rsplit('/').next().unwrap_or(reference));
+    }
+    match schema.find("type").and_then(|t| t.as_str()) {
+        Some("string") => "String".to_string(),
+        Some("integer") => "i64".to_string(),
+        Some("number") => "f64".to_string(),
+        Some("boolean") => "bool".to_string(),
+        Some("array") => {
+            let items = schema.find("items").map(rust_type);
+            format!("Vec<{}>", items.unwrap_or_else(|| "::curs::serde_json::Value".to_string()))
+        }
+        _ => "::curs::serde_json::Value".to_string(),
+    }
+}
+
+fn doc_comment(value: Option<&Value>, indent: &str) -> String {
+    match value.and_then(|v| v.as_str()) {
+        Some(text) => {
+            text.lines()
+                .map(|line| format!("{}/// {}\n", indent, line).replace("/// \n", "///\n"))
+                .collect()
+        }
+        None => String::new(),
+    }
+}
+
+fn schema_struct(name: &str, schema: &Value) -> String {
+    let mut code = String::from("\n");
+    code.push_str(&doc_comment(schema.find("description"), ""));
+    let name = camel_case(name);
+    let properties = match schema.find("properties").and_then(|p| p.as_object()) {
+        Some(properties) => properties,
+        // Not an object, so just a name for some other type.
+        None => return format!("{}pub type {} = {};\n", code, name, rust_type(schema)),
+    };
+    let required: Vec<&str> = schema.find("required")
+                                    .and_then(|r| r.as_array())
+                                    .map(|r| r.iter().filter_map(|n| n.as_str()).collect())
+                                    .unwrap_or_else(Vec::new);
+    code.push_str("#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]\n");
+    code.push_str(&format!("pub struct {} {{\n", name));
+    for (property, property_schema) in properties {
+        code.push_str(&doc_comment(property_schema.find("description"), "    "));
+        let field = snake_case(property);
+        if field != *property {
+            code.push_str(&format!("    #[serde(rename = \"{}\")]\n", property));
+        }
+        let mut field_type = rust_type(property_schema);
+        if !required.iter().any(|r| *r == &**property) {
+            field_type = format!("Option<{}>", field_type);
+        }
+        code.push_str(&format!("    pub {}: {},\n", field, field_type));
+    }
+    code.push_str("}\n");
+    code
+}
+
+fn json_schema<'a>(value: &'a Value) -> Option<&'a Value> {
+    value.find_path(&["content", "application/json", "schema"]).or_else(|| value.find("schema"))
+}
+
+fn operation_fn(path: &str,
+                method: &str,
+                operation: &Value,
+                shared: Option<&Vec<Value>>)
+                -> String {
+    let name = match operation.find("operationId").and_then(|id| id.as_str()) {
+        Some(id) => snake_case(id),
+        None => snake_case(&format!("{} {}", method, path)),
+    };
+    let mut arguments = vec!["client: &::curs::Client".to_string()];
+    let mut path_params = BTreeMap::new();
+    let mut query = vec![];
+    let mut body = None;
+    // An operation's parameter overrides the path's with the same name and location.
+    let mut parameters: Vec<&Value> = shared.into_iter().flat_map(|p| p.iter()).collect();
+    let own = operation.find("parameters").and_then(|p| p.as_array());
+    let key = |p: &Value| (p.find("name").cloned(), p.find("in").cloned());
+    for parameter in own.into_iter().flat_map(|p| p.iter()) {
+        match parameters.iter().position(|shared| key(shared) == key(parameter)) {
+            Some(index) => parameters[index] = parameter,
+            None => parameters.push(parameter),
+        }
+    }
+    for parameter in parameters {
+        let name = parameter.find("name").and_then(|n| n.as_str()).unwrap_or("");
+        let required = parameter.find("required").and_then(|r| r.as_bool()).unwrap_or(false);
+        match parameter.find("in").and_then(|i| i.as_str()) {
+            Some("path") => {
+                arguments.push(format!("{}: &str", snake_case(name)));
+                path_params.insert(name.to_string(), snake_case(name));
+            }
+            Some("query") if required => {
+                arguments.push(format!("{}: &str", snake_case(name)));
+                query.push(format!("(\"{}\", Some({}))", name, snake_case(name)));
+            }
+            Some("query") => {
+                arguments.push(format!("{}: Option<&str>", snake_case(name)));
+                query.push(format!("(\"{}\", {})", name, snake_case(name)));
+            }
+            Some("body") => body = parameter.find("schema").map(rust_type),
+            _ => {}
+        }
+    }
+    if let Some(schema) = operation.find("requestBody").and_then(json_schema) {
+        body = Some(rust_type(schema));
+    }
+    if let Some(ref body) = body {
+        arguments.push(format!("body: &{}", body));
+    }
+    let response = ["200", "201", "202"]
+                       .iter()
+                       .filter_map(|status| operation.find_path(&["responses", *status]))
+                       .filter_map(json_schema)
+                       .next()
+                       .map(rust_type);
+
+    // The path template with every {param} taken out, and the params in order.
+    let mut template = String::new();
+    let mut values = vec![];
+    let mut rest = path;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        template.push_str(&rest[..start]);
+        template.push_str("{}");
+        let param = &rest[start + 1..end];
+        let argument = path_params.get(param).cloned().unwrap_or_else(|| snake_case(param));
+        values.push(format!(", ::curs::openapi::path_segment({})", argument));
+        rest = &rest[end + 1..];
+    }
+    template.push_str(rest);
+    // Relative to the base url, which may have a path of its own.
+    let template = template.trim_left_matches('/');
+
+    let mut code = String::from("\n");
+    code.push_str(&doc_comment(operation.find("summary"), ""));
+    code.push_str(&format!("pub fn {}({}) -> ::curs::CursResult<{}> {{\n",
+                           name,
+                           arguments.join(", "),
+                           response.as_ref().map_or("::curs::Response", |r| &**r)));
+    code.push_str(&format!("    let url = format!(\"{}\"{});\n", template, values.concat()));
+    if !query.is_empty() {
+        code.push_str(&format!("    let url = ::curs::openapi::with_query(&url, &[{}]);\n",
+                               query.join(", ")));
+    }
+    let variant = {
+        let mut chars = method.chars();
+        chars.next().unwrap().to_uppercase().chain(chars).collect::<String>()
+    };
+    let binding = if body.is_some() { "let mut" } else { "let" };
+    code.push_str(&format!("    {} request = client.request(::curs::Method::{}, &url);\n",
+                           binding,
+                           variant));
+    if body.is_some() {
+        code.push_str("    request.json(body);\n");
+    }
+    match response {
+        Some(_) => {
+            code.push_str("    ::curs::DecodableResult::decode_success(request.send())\n");
+        }
+        None => code.push_str("    request.send()\n"),
+    }
+    code.push_str("}\n");
+    code
+}
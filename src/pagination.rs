@@ -0,0 +1,269 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io::Read;
+
+use hyper::header::Headers;
+use serde::Deserialize;
+use serde_json::{self, Value};
+use url::Url;
+
+use super::{CursError, CursResult, Method, Request, StatusCode};
+
+/// The target of the first link with this rel in the Link headers (RFC 5988),
+/// like the next page in `Link: <https://api.example.com/items?page=2>; rel="next"`.
+pub fn link_with_rel(headers: &Headers, rel: &str) -> Option<String> {
+    let values = match headers.get_raw("Link") {
+        Some(values) => values,
+        None => return None,
+    };
+    for value in values {
+        let value = String::from_utf8_lossy(value);
+        let mut rest = &*value;
+        while let Some(start) = rest.find('<') {
+            let end = match rest[start..].find('>') {
+                Some(end) => start + end,
+                None => break,
+            };
+            let params_end = rest[end..].find('<').map(|i| end + i).unwrap_or(rest.len());
+            let has_rel = rest[end + 1..params_end].split(';').any(|param| {
+                let mut parts = param.splitn(2, '=');
+                let name = parts.next().unwrap_or("").trim();
+                let rels = parts.next().unwrap_or("").trim().trim_right_matches(',');
+                let rels = rels.trim().trim_matches('"').to_lowercase();
+                name == "rel" && rels.split_whitespace().any(|r| r == rel)
+            });
+            if has_rel {
+                return Some(rest[start + 1..end].to_string());
+            }
+            rest = &rest[params_end..];
+        }
+    }
+    None
+}
+
+/// Sets a query param on a url, replacing it if it's already there.
+/// Handy for building the next page's url out of a cursor or offset.
+pub fn with_query_param(url: &Url, name: &str, value: &str) -> Url {
+    let mut url = url.clone();
+    let mut pairs: Vec<(String, String)> = url.query_pairs()
+                                              .unwrap_or(vec![])
+                                              .into_iter()
+                                              .filter(|&(ref n, _)| n != name)
+                                              .collect();
+    pairs.push((name.to_string(), value.to_string()));
+    url.set_query_from_pairs::<_, String, String>(&pairs);
+    url
+}
+
+fn same_origin(url: &Url, other: &Url) -> bool {
+    url.scheme == other.scheme && url.serialize_host() == other.serialize_host() &&
+    url.port_or_default() == other.port_or_default()
+}
+
+/// One page's worth of items, see Pages::by_page.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Where this page came from.
+    pub url: Url,
+    /// Where the next one is, if there are more. Keep it to resume from here later.
+    pub next: Option<Url>,
+}
+
+type NextPage<'a> = Box<Fn(&Url, &Headers, &Value) -> Option<Url> + 'a>;
+
+/// Items from every page of a paginated JSON API, see Request::paginate and
+/// Request::paginate_with. Pages are only fetched once the items from the previous
+/// one run out.
+pub struct Pages<'a, T> {
+    request: Request<'a>,
+    next_page: NextPage<'a>,
+    items_field: Option<String>,
+    more: bool,
+    fetched: usize,
+    items: VecDeque<T>,
+}
+
+impl<'a, T: Deserialize> Pages<'a, T> {
+    /// Pages linked with `rel="next"` Link headers.
+    pub fn new(request: Request<'a>) -> Pages<'a, T> {
+        Pages::with_next_page(request,
+                              Box::new(|url: &Url, headers: &Headers, _: &Value| {
+                                  link_with_rel(headers, "next").and_then(|l| url.join(&l).ok())
+                              }))
+    }
+
+    /// Pages linked by a cursor (or offset, or page number) `next_cursor` finds in
+    /// each page, which `apply_cursor` turns into the next page's url.
+    /// There are no more pages once next_cursor returns None.
+    pub fn with_cursor<N, A>(request: Request<'a>, next_cursor: N, apply_cursor: A) -> Pages<'a, T>
+        where N: Fn(&Headers, &Value) -> Option<String> + 'a,
+              A: Fn(&Url, &str) -> Url + 'a
+    {
+        Pages::with_next_page(request,
+                              Box::new(move |url: &Url, headers: &Headers, body: &Value| {
+                                  next_cursor(headers, body).map(|c| apply_cursor(url, &c))
+                              }))
+    }
+
+    fn with_next_page(request: Request<'a>, next_page: NextPage<'a>) -> Pages<'a, T> {
+        Pages {
+            request: request,
+            next_page: next_page,
+            items_field: None,
+            more: true,
+            fetched: 0,
+            items: VecDeque::new(),
+        }
+    }
+
+    /// Take items from this field of each page, for pages that aren't bare arrays,
+    /// like `{"data": [...], "next_cursor": "abc"}`.
+    pub fn items_field(mut self, field: &str) -> Pages<'a, T> {
+        self.items_field = Some(field.to_string());
+        self
+    }
+
+    /// Every item of every page, fetching at most `max_pages` pages. Fails with
+    /// CursError::TooManyPages if there are more, rather than going on forever
+    /// when an API's next links go round in circles.
+    pub fn collect_all(mut self, max_pages: usize) -> CursResult<Vec<T>> {
+        let mut all = vec![];
+        loop {
+            all.extend(self.items.drain(..));
+            if !self.more {
+                return Ok(all);
+            }
+            if self.fetched >= max_pages {
+                return Err(CursError::TooManyPages(max_pages));
+            }
+            try!(self.fetch());
+        }
+    }
+
+    /// Go through whole pages instead of items, so an error on one page doesn't
+    /// lose the ones before it. See ByPage.
+    pub fn by_page(self) -> ByPage<'a, T> {
+        ByPage {
+            pages: self,
+            failed: false,
+        }
+    }
+
+    fn fetch(&mut self) -> CursResult<()> {
+        match self.fetch_page() {
+            Ok(page) => {
+                self.items.extend(page.items);
+                Ok(())
+            }
+            Err(error) => {
+                self.more = false;
+                Err(error)
+            }
+        }
+    }
+
+    // Leaves the request alone when it fails, so the same page can be tried again.
+    fn fetch_page(&mut self) -> CursResult<Page<T>> {
+        let mut response = try!(self.request.send());
+        match response.status {
+            StatusCode::Ok | StatusCode::Created | StatusCode::Accepted => {}
+            _ => return Err(CursError::Status(response)),
+        }
+        let mut body = String::new();
+        try!(response.read_to_string(&mut body));
+        let mut page: Value = try!(serde_json::from_str(&body));
+        let next = (self.next_page)(&response.url, &response.headers, &page);
+        // Whatever credentials the request carries are for this API, not wherever a
+        // page points to. Pages that aren't over http weren't sent any.
+        let sent_over_http = response.url.scheme == "http" || response.url.scheme == "https";
+        if let Some(ref next) = next {
+            if sent_over_http && !same_origin(&response.url, next) {
+                let reason = format!("The next page is on another origin: {}", next);
+                return Err(CursError::InvalidResponse(response, reason));
+            }
+        }
+        let items = match self.items_field {
+            Some(ref field) => {
+                match page {
+                    Value::Object(ref mut fields) => fields.remove(field).unwrap_or(Value::Null),
+                    _ => Value::Null,
+                }
+            }
+            None => page,
+        };
+        let items: Vec<T> = try!(serde_json::from_value(items));
+        self.fetched += 1;
+        self.more = next.is_some();
+        if let Some(ref next) = next {
+            self.request.url = Cow::Owned(next.serialize());
+            // The next page's url already carries the query the params made, when
+            // they went in the query rather than the body.
+            let request = &mut self.request;
+            if request.no_body || request.method == Method::Get || request.method == Method::Head {
+                request.params.clear();
+            }
+        }
+        Ok(Page {
+            items: items,
+            url: response.url.clone(),
+            next: next,
+        })
+    }
+}
+
+/// Pages one at a time, fetched as they're asked for. Iteration stops after an
+/// error, with the pages before it already handed over. The failed page can be
+/// tried again with retry, or from somewhere else later with its url, see next_url.
+pub struct ByPage<'a, T> {
+    pages: Pages<'a, T>,
+    failed: bool,
+}
+
+impl<'a, T: Deserialize> ByPage<'a, T> {
+    /// The url of the page that comes next (or that just failed), if there's one.
+    pub fn next_url(&self) -> Option<String> {
+        if self.pages.more {
+            self.pages.request.prepare().ok().map(|prepared| prepared.url)
+        } else {
+            None
+        }
+    }
+
+    /// Go on after an error, trying the page that failed again.
+    pub fn retry(&mut self) {
+        self.failed = false;
+    }
+}
+
+impl<'a, T: Deserialize> Iterator for ByPage<'a, T> {
+    type Item = CursResult<Page<T>>;
+
+    fn next(&mut self) -> Option<CursResult<Page<T>>> {
+        if self.failed || !self.pages.more {
+            return None;
+        }
+        let page = self.pages.fetch_page();
+        self.failed = page.is_err();
+        Some(page)
+    }
+}
+
+impl<'a, T: Deserialize> Iterator for Pages<'a, T> {
+    type Item = CursResult<T>;
+
+    /// Stops after the first error.
+    fn next(&mut self) -> Option<CursResult<T>> {
+        loop {
+            if let Some(item) = self.items.pop_front() {
+                return Some(Ok(item));
+            }
+            if !self.more {
+                return None;
+            }
+            if let Err(error) = self.fetch() {
+                return Some(Err(error));
+            }
+        }
+    }
+}
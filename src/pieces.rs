@@ -0,0 +1,97 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+use std::path::PathBuf;
+
+#[cfg(all(feature = "mmap", unix))]
+use mmap::MappedFile;
+
+/// A stretch of a body put together from several places.
+pub enum Piece {
+    Bytes(Cursor<Vec<u8>>),
+    /// This many bytes of a file, opened once its turn comes so only one is open
+    /// at a time.
+    File(PathBuf, Option<File>, u64),
+    Zeros(u64),
+    #[cfg(all(feature = "mmap", unix))]
+    Mapped(Cursor<MappedFile>),
+}
+
+impl Piece {
+    pub fn bytes<B: Into<Vec<u8>>>(bytes: B) -> Piece {
+        Piece::Bytes(Cursor::new(bytes.into()))
+    }
+
+    pub fn len(&self) -> u64 {
+        match *self {
+            Piece::Bytes(ref bytes) => bytes.get_ref().len() as u64 - bytes.position(),
+            Piece::File(_, _, left) | Piece::Zeros(left) => left,
+            #[cfg(all(feature = "mmap", unix))]
+            Piece::Mapped(ref mapped) => mapped.get_ref().len() as u64 - mapped.position(),
+        }
+    }
+}
+
+/// Reads the pieces one after the other. Files that turn out shorter than they
+/// were are an error, a body's length may have been promised already.
+pub struct Pieces(pub VecDeque<Piece>);
+
+impl Pieces {
+    pub fn len(&self) -> u64 {
+        self.0.iter().map(|piece| piece.len()).sum::<u64>()
+    }
+}
+
+fn up_to(buf: &[u8], left: u64) -> usize {
+    if (buf.len() as u64) < left {
+        buf.len()
+    } else {
+        left as usize
+    }
+}
+
+impl Read for Pieces {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let read = match self.0.front_mut() {
+                Some(&mut Piece::Bytes(ref mut bytes)) => try!(bytes.read(buf)),
+                Some(&mut Piece::File(ref path, ref mut file, ref mut left)) => {
+                    if *left == 0 {
+                        0
+                    } else {
+                        if file.is_none() {
+                            *file = Some(try!(File::open(path)));
+                        }
+                        let wanted = up_to(buf, *left);
+                        let read = try!(file.as_mut().unwrap().read(&mut buf[..wanted]));
+                        if read == 0 {
+                            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                                      format!("{} shrank while sending it",
+                                                              path.display())));
+                        }
+                        *left -= read as u64;
+                        read
+                    }
+                }
+                Some(&mut Piece::Zeros(ref mut left)) => {
+                    let zeros = up_to(buf, *left);
+                    for byte in &mut buf[..zeros] {
+                        *byte = 0;
+                    }
+                    *left -= zeros as u64;
+                    zeros
+                }
+                #[cfg(all(feature = "mmap", unix))]
+                Some(&mut Piece::Mapped(ref mut mapped)) => try!(mapped.read(buf)),
+                None => return Ok(0),
+            };
+            if read > 0 {
+                return Ok(read);
+            }
+            self.0.pop_front();
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::io::{self, Read};
+use std::sync::Arc;
+
+/// How far along a multipart upload is, and the part going out right now.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartProgress {
+    /// Counting from 0, params first, then files, then uploads from memory.
+    pub index: usize,
+    /// The form field's name.
+    pub name: String,
+    /// None for plain params.
+    pub filename: Option<String>,
+    /// Bytes of this part's contents sent so far, out of `total`.
+    pub sent: u64,
+    pub total: u64,
+    /// The same for the whole body, boundaries and part headers included.
+    pub body_sent: u64,
+    pub body_total: u64,
+}
+
+impl PartProgress {
+    /// Of this part alone, from 0 to 100. Empty parts are done as soon as they start.
+    pub fn percent(&self) -> f64 {
+        if self.total == 0 {
+            100.0
+        } else {
+            self.sent as f64 * 100.0 / self.total as f64
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.sent == self.total
+    }
+}
+
+/// Told about every part of a streamed multipart body while it's sent, see
+/// Request::upload_progress. Any `Fn(&PartProgress)` will do.
+pub trait UploadProgress {
+    fn progress(&self, progress: &PartProgress);
+}
+
+impl<F: Fn(&PartProgress)> UploadProgress for F {
+    fn progress(&self, progress: &PartProgress) {
+        self(progress)
+    }
+}
+
+/// Where a part's contents are in a body.
+#[derive(Clone, Debug)]
+pub struct PartSpan {
+    pub name: String,
+    pub filename: Option<String>,
+    pub start: u64,
+    pub len: u64,
+}
+
+/// Counts what's read from a body and reports it part by part. Every part gets
+/// at least its last event, with all of it sent, even when a single read goes
+/// past several of them.
+pub struct ProgressReader<R> {
+    inner: R,
+    parts: Vec<PartSpan>,
+    current: usize,
+    sent: u64,
+    total: u64,
+    listener: Arc<UploadProgress + Send + Sync>,
+}
+
+impl<R: Read> ProgressReader<R> {
+    pub fn new(inner: R,
+               parts: Vec<PartSpan>,
+               total: u64,
+               listener: Arc<UploadProgress + Send + Sync>)
+               -> ProgressReader<R> {
+        ProgressReader {
+            inner: inner,
+            parts: parts,
+            current: 0,
+            sent: 0,
+            total: total,
+            listener: listener,
+        }
+    }
+
+    fn report(&self, index: usize, sent: u64) {
+        let part = &self.parts[index];
+        self.listener.progress(&PartProgress {
+            index: index,
+            name: part.name.clone(),
+            filename: part.filename.clone(),
+            sent: sent,
+            total: part.len,
+            body_sent: self.sent,
+            body_total: self.total,
+        });
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = try!(self.inner.read(buf));
+        self.sent += read as u64;
+        while self.current < self.parts.len() {
+            let (start, len) = (self.parts[self.current].start, self.parts[self.current].len);
+            if self.sent >= start + len {
+                let current = self.current;
+                self.report(current, len);
+                self.current += 1;
+            } else {
+                if self.sent > start {
+                    let current = self.current;
+                    self.report(current, self.sent - start);
+                }
+                break;
+            }
+        }
+        Ok(read)
+    }
+}
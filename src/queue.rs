@@ -0,0 +1,102 @@
+use std::sync::{Condvar, Mutex};
+
+/// How urgent a request is when its Client is already sending as many as it may.
+/// Higher priorities go first, requests with the same priority go in order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Background,
+    Normal,
+    Interactive,
+}
+
+impl Default for Priority {
+    fn default() -> Priority {
+        Priority::Normal
+    }
+}
+
+/// How busy a Client with a concurrency limit is right now.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct QueueDepth {
+    pub running: usize,
+    pub interactive: usize,
+    pub normal: usize,
+    pub background: usize,
+}
+
+impl QueueDepth {
+    /// Requests waiting for their turn, whatever their priority.
+    pub fn waiting(&self) -> usize {
+        self.interactive + self.normal + self.background
+    }
+}
+
+#[derive(Default)]
+struct GateState {
+    running: usize,
+    waiting: Vec<(Priority, u64)>,
+    next_ticket: u64,
+}
+
+impl GateState {
+    // Highest priority first, then whoever came first.
+    fn next_in_line(&self) -> Option<(Priority, u64)> {
+        self.waiting.iter().cloned().max_by_key(|&(priority, ticket)| (priority, !ticket))
+    }
+}
+
+/// Lets at most `limit` requests through at once, the rest wait by priority.
+pub struct Gate {
+    limit: usize,
+    state: Mutex<GateState>,
+    freed: Condvar,
+}
+
+/// A turn to send. The next request in line goes when this is dropped.
+pub struct Permit<'a> {
+    gate: &'a Gate,
+}
+
+impl Gate {
+    pub fn new(limit: usize) -> Gate {
+        Gate {
+            limit: limit,
+            state: Mutex::new(GateState::default()),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Blocks until it's this priority's turn.
+    pub fn enter(&self, priority: Priority) -> Permit {
+        let mut state = self.state.lock().unwrap();
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.waiting.push((priority, ticket));
+        while state.running >= self.limit || state.next_in_line() != Some((priority, ticket)) {
+            state = self.freed.wait(state).unwrap();
+        }
+        state.waiting.retain(|&(_, t)| t != ticket);
+        state.running += 1;
+        Permit { gate: self }
+    }
+
+    pub fn depth(&self) -> QueueDepth {
+        let state = self.state.lock().unwrap();
+        let count = |priority| state.waiting.iter().filter(|&&(p, _)| p == priority).count();
+        QueueDepth {
+            running: state.running,
+            interactive: count(Priority::Interactive),
+            normal: count(Priority::Normal),
+            background: count(Priority::Background),
+        }
+    }
+}
+
+impl<'a> Drop for Permit<'a> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.gate.state.lock() {
+            state.running -= 1;
+        }
+        self.gate.freed.notify_all();
+    }
+}
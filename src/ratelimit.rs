@@ -0,0 +1,143 @@
+use std::cmp;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::client::response::Response;
+use hyper::header::Headers;
+
+use super::{CursError, CursResult};
+use clock::{self, Clock, SystemClock};
+
+struct Bucket {
+    tokens: f64,
+    updated: Duration,
+}
+
+/// A token bucket: requests take a token each, tokens come back at `rate` per
+/// second and at most `burst` of them pile up. Requests wait when there's none left.
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    per_host: bool,
+    buckets: Mutex<BTreeMap<String, Bucket>>,
+    clock: Arc<Clock + Send + Sync>,
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9
+}
+
+impl RateLimiter {
+    /// One bucket shared by every request. Fails with CursError::Config unless the
+    /// rate is above zero, at a rate of zero nothing would ever go.
+    pub fn new(rate: f64, burst: u32) -> CursResult<RateLimiter> {
+        if !(rate > 0.0 && rate.is_finite()) {
+            return Err(CursError::Config(format!("Can't limit requests to {} a second", rate)));
+        }
+        Ok(RateLimiter {
+            rate: rate,
+            burst: cmp::max(burst, 1) as f64,
+            per_host: false,
+            buckets: Mutex::new(BTreeMap::new()),
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    /// Tell time and wait with this clock instead of the system's.
+    pub fn with_clock(self, clock: Arc<Clock + Send + Sync>) -> RateLimiter {
+        RateLimiter { clock: clock, ..self }
+    }
+
+    /// A bucket for each host, so one API's quota doesn't hold back another's.
+    pub fn per_host(rate: f64, burst: u32) -> CursResult<RateLimiter> {
+        RateLimiter::new(rate, burst).map(|limiter| RateLimiter { per_host: true, ..limiter })
+    }
+
+    /// Blocks until a request to this host can go.
+    pub fn wait(&self, host: &str) {
+        let key = if self.per_host { host } else { "" };
+        loop {
+            let missing = {
+                let mut buckets = self.buckets.lock().unwrap();
+                let now = self.clock.now();
+                let burst = self.burst;
+                let bucket = buckets.entry(key.to_string()).or_insert_with(|| {
+                    Bucket {
+                        tokens: burst,
+                        updated: now,
+                    }
+                });
+                let refill = seconds(clock::since(&*self.clock, bucket.updated)) * self.rate;
+                bucket.tokens = (bucket.tokens + refill).min(self.burst);
+                bucket.updated = now;
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return;
+                }
+                1.0 - bucket.tokens
+            };
+            let wait = missing / self.rate;
+            self.clock.sleep(Duration::new(wait as u64, (wait.fract() * 1e9) as u32));
+        }
+    }
+}
+
+/// What a server says about its rate limit, from `X-RateLimit-Limit`,
+/// `X-RateLimit-Remaining` and `X-RateLimit-Reset`, or the `RateLimit-*`
+/// headers from the IETF draft.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RateLimit {
+    pub limit: Option<u64>,
+    pub remaining: Option<u64>,
+    /// Time until the window resets. Reset headers can hold seconds or a unix
+    /// timestamp, anything past a billion seconds is taken as a timestamp.
+    pub reset: Option<Duration>,
+}
+
+fn header_number(headers: &Headers, name: &str) -> Option<u64> {
+    [format!("X-RateLimit-{}", name), format!("RateLimit-{}", name)]
+        .iter()
+        .filter_map(|name| headers.get_raw(name).and_then(|v| v.first()))
+        .filter_map(|value| {
+            // The draft allows a quota policy after the number, like "100, 100;w=60".
+            let value = String::from_utf8_lossy(value);
+            value.split(|c| c == ',' || c == ';').next().and_then(|n| n.trim().parse().ok())
+        })
+        .next()
+}
+
+impl RateLimit {
+    /// None when there are no rate limit headers at all. Reset timestamps are
+    /// counted down from `clock`.
+    pub fn from_headers(headers: &Headers, clock: &Clock) -> Option<RateLimit> {
+        let reset = header_number(headers, "Reset").map(|reset| {
+            if reset > 1_000_000_000 {
+                Duration::from_secs(reset.saturating_sub(clock.now().as_secs()))
+            } else {
+                Duration::from_secs(reset)
+            }
+        });
+        let rate_limit = RateLimit {
+            limit: header_number(headers, "Limit"),
+            remaining: header_number(headers, "Remaining"),
+            reset: reset,
+        };
+        if rate_limit == RateLimit::default() {
+            None
+        } else {
+            Some(rate_limit)
+        }
+    }
+}
+
+/// Lets you ask a Response about the rate limit it reports.
+pub trait RateLimited {
+    fn rate_limit(&self) -> Option<RateLimit>;
+}
+
+impl RateLimited for Response {
+    fn rate_limit(&self) -> Option<RateLimit> {
+        RateLimit::from_headers(&self.headers, &SystemClock)
+    }
+}
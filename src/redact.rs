@@ -0,0 +1,63 @@
+use std::fmt;
+
+use hyper::client::response::Response;
+use hyper::header::Headers;
+
+/// Headers whose values never show up in Debug output.
+pub const SENSITIVE_HEADERS: &'static [&'static str] = &["authorization",
+                                                         "proxy-authorization",
+                                                         "cookie",
+                                                         "set-cookie"];
+
+/// Whether this header or param's value should be hidden: it's one of the
+/// SENSITIVE_HEADERS or one of the `extra` names, compared case insensitively.
+pub fn is_sensitive(name: &str, extra: &[String]) -> bool {
+    let name = name.to_lowercase();
+    SENSITIVE_HEADERS.iter().any(|sensitive| *sensitive == name) ||
+    extra.iter().any(|sensitive| sensitive.to_lowercase() == name)
+}
+
+/// Debug formats headers with sensitive values replaced by `<redacted>`, so logging
+/// them doesn't leak credentials.
+pub struct RedactedHeaders<'a> {
+    pub headers: &'a Headers,
+    /// More names to hide, besides SENSITIVE_HEADERS.
+    pub extra: &'a [String],
+}
+
+impl<'a> RedactedHeaders<'a> {
+    pub fn new(headers: &'a Headers) -> RedactedHeaders<'a> {
+        RedactedHeaders {
+            headers: headers,
+            extra: &[],
+        }
+    }
+}
+
+impl<'a> fmt::Debug for RedactedHeaders<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let entries = self.headers.iter().map(|header| {
+            let value = if is_sensitive(header.name(), self.extra) {
+                "<redacted>".to_string()
+            } else {
+                header.value_string()
+            };
+            (header.name().to_string(), value)
+        });
+        f.debug_map().entries(entries).finish()
+    }
+}
+
+/// Debug formats a response's status, url and headers, with sensitive header
+/// values redacted. Bodies are left out, they haven't been read yet.
+pub struct RedactedResponse<'a>(pub &'a Response);
+
+impl<'a> fmt::Debug for RedactedResponse<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Response")
+         .field("status", &self.0.status)
+         .field("url", &self.0.url.serialize())
+         .field("headers", &RedactedHeaders::new(&self.0.headers))
+         .finish()
+    }
+}
@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+
+use super::{CursError, CursResult, DecodableResult};
+use client::Client;
+use openapi::path_segment;
+
+/// A type kept on a REST API as JSON, at the usual CRUD endpoints under its
+/// collection: `GET users`, `GET users/7`, `POST users`, `PUT users/7` and
+/// `DELETE users/7`. Paths are relative to the Client's base url. Implement it
+/// with the resource! macro.
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize)]
+/// struct User {
+///     id: Option<u64>,
+///     name: String,
+/// }
+/// resource!(User, "users", id);
+///
+/// let user = try!(User::find(&client, "7"));
+/// let renamed = User { name: "Ann".to_string(), ..user };
+/// try!(renamed.save(&client));
+/// ```
+pub trait Resource: Serialize + Deserialize + Sized {
+    /// Like `users`, without slashes around.
+    fn collection() -> &'static str;
+
+    /// None for those that haven't been created yet.
+    fn id(&self) -> Option<String>;
+
+    fn index(client: &Client) -> CursResult<Vec<Self>> {
+        client.get(Self::collection()).send().decode_success()
+    }
+
+    fn find(client: &Client, id: &str) -> CursResult<Self> {
+        client.get(&member_path::<Self>(id)).send().decode_success()
+    }
+
+    /// Creates it and returns it as the API has it now, with its id.
+    fn create(&self, client: &Client) -> CursResult<Self> {
+        client.post(Self::collection()).json(self).send().decode_success()
+    }
+
+    /// Replaces it with this and returns it as the API has it now. Fails with
+    /// CursError::Config when it has no id.
+    fn update(&self, client: &Client) -> CursResult<Self> {
+        let path = member_path::<Self>(&try!(saved_id(self)));
+        client.put(&path).json(self).send().decode_success()
+    }
+
+    /// Creates it when it has no id, and updates it when it does.
+    fn save(&self, client: &Client) -> CursResult<Self> {
+        match self.id() {
+            Some(_) => self.update(client),
+            None => self.create(client),
+        }
+    }
+
+    fn delete(&self, client: &Client) -> CursResult<()> {
+        let path = member_path::<Self>(&try!(saved_id(self)));
+        let response = try!(client.delete(&path).send());
+        if response.status.is_success() {
+            Ok(())
+        } else {
+            Err(CursError::Status(response))
+        }
+    }
+}
+
+fn member_path<R: Resource>(id: &str) -> String {
+    format!("{}/{}", R::collection(), path_segment(id))
+}
+
+fn saved_id<R: Resource>(resource: &R) -> CursResult<String> {
+    resource.id().ok_or_else(|| {
+        CursError::Config(format!("This {} has no id yet, create it first", R::collection()))
+    })
+}
+
+/// Ids resources can have, see the resource! macro.
+pub trait ResourceId {
+    fn resource_id(&self) -> Option<String>;
+}
+
+impl ResourceId for String {
+    fn resource_id(&self) -> Option<String> {
+        Some(self.clone())
+    }
+}
+
+impl ResourceId for u64 {
+    fn resource_id(&self) -> Option<String> {
+        Some(self.to_string())
+    }
+}
+
+impl ResourceId for i64 {
+    fn resource_id(&self) -> Option<String> {
+        Some(self.to_string())
+    }
+}
+
+impl<T: ResourceId> ResourceId for Option<T> {
+    fn resource_id(&self) -> Option<String> {
+        self.as_ref().and_then(|id| id.resource_id())
+    }
+}
+
+/// Implements Resource for a type, given its collection and the field with its
+/// id. The id can be a String, u64 or i64, or an Option of them for types that
+/// also stand for resources not created yet.
+#[macro_export]
+macro_rules! resource {
+    ($resource:ty, $collection:expr, $id:ident) => {
+        impl $crate::resource::Resource for $resource {
+            fn collection() -> &'static str {
+                $collection
+            }
+
+            fn id(&self) -> Option<String> {
+                $crate::resource::ResourceId::resource_id(&self.$id)
+            }
+        }
+    };
+}
+
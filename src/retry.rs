@@ -0,0 +1,202 @@
+use std::cmp;
+use std::io;
+use std::time::Duration;
+
+use hyper::error::Error as HyperError;
+use hyper::header::Headers;
+use hyper::method::Method;
+use rand::{self, Rng};
+use time;
+use hyper::status::StatusCode;
+
+use super::CursError;
+use clock::Clock;
+
+/// What happened on an attempt that may be worth retrying.
+pub enum Outcome<'a> {
+    /// The request never got a response.
+    Error(&'a CursError),
+    /// The server answered with this status.
+    Status(StatusCode),
+}
+
+/// Everything a RetryPolicy gets to decide on.
+pub struct Attempt<'a> {
+    /// 1 for the first try, 2 for the first retry and so on.
+    pub number: u32,
+    pub method: &'a Method,
+    pub url: &'a str,
+    pub outcome: Outcome<'a>,
+    /// Time since the first attempt started.
+    pub elapsed: Duration,
+    /// How long the policy said to wait before this attempt, if it's a retry.
+    pub previous_delay: Option<Duration>,
+    /// How long the server asked to wait in its Retry-After header, if it did.
+    pub retry_after: Option<Duration>,
+}
+
+pub enum RetryDecision {
+    /// Wait this long and try again.
+    Retry(Duration),
+    Stop,
+}
+
+/// Decides whether a failed attempt gets retried, and when.
+/// Successful responses never get here, only network errors and statuses
+/// other than 1xx, 2xx and 3xx.
+pub trait RetryPolicy {
+    fn decide(&self, attempt: &Attempt) -> RetryDecision;
+}
+
+/// How much randomness goes into backoff delays, so a fleet of clients that failed
+/// together doesn't retry together. See
+/// https://www.awsarchitectureblog.com/2015/03/backoff.html for how they compare.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Jitter {
+    /// Wait exactly the exponential delay.
+    None,
+    /// Anything between nothing and the exponential delay.
+    Full,
+    /// Half the exponential delay, plus up to another half.
+    Equal,
+    /// Anything between `base` and three times the previous delay.
+    Decorrelated,
+}
+
+/// Retries network errors and 429, 502, 503 and 504 responses, waiting twice as
+/// long each time, starting at `base` and never more than `max`.
+/// Delays get full jitter unless told otherwise. When a 429 or 503 comes with a
+/// Retry-After header that wait is used instead, still capped at `max`.
+#[derive(Clone, Debug)]
+pub struct ExponentialBackoff {
+    pub max_attempts: u32,
+    pub base: Duration,
+    pub max: Duration,
+    pub jitter: Jitter,
+}
+
+impl Default for ExponentialBackoff {
+    fn default() -> ExponentialBackoff {
+        ExponentialBackoff {
+            max_attempts: 3,
+            base: Duration::from_millis(100),
+            max: Duration::from_secs(10),
+            jitter: Jitter::Full,
+        }
+    }
+}
+
+/// How long a Retry-After header asks to wait. It can hold seconds or an HTTP date,
+/// dates in the past (on `clock`) mean no wait at all.
+pub fn retry_after(headers: &Headers, clock: &Clock) -> Option<Duration> {
+    let value = match headers.get_raw("Retry-After").and_then(|v| v.first()) {
+        Some(value) => String::from_utf8_lossy(value).trim().to_string(),
+        None => return None,
+    };
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    time::strptime(&value, "%a, %d %b %Y %H:%M:%S GMT").ok().map(|date| {
+        let seconds = date.to_timespec().sec - clock.unix_time();
+        Duration::from_secs(cmp::max(seconds, 0) as u64)
+    })
+}
+
+/// Whether sending a request twice has the same effect as sending it once.
+/// Only these get retried, unless a request opts in or carries an Idempotency-Key.
+pub fn is_idempotent(method: &Method) -> bool {
+    match *method {
+        Method::Get | Method::Head | Method::Put | Method::Delete | Method::Options |
+        Method::Trace => true,
+        _ => false,
+    }
+}
+
+/// Whether the connection was reset or closed under the request, before a response
+/// came. A kept-alive connection the server dropped fails like this.
+pub fn is_connection_reset(error: &CursError) -> bool {
+    match *error {
+        CursError::Network(HyperError::Io(ref error)) => {
+            match error.kind() {
+                io::ErrorKind::ConnectionReset |
+                io::ErrorKind::ConnectionAborted |
+                io::ErrorKind::BrokenPipe => true,
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Whether a status usually means trying again later could work.
+pub fn is_transient(status: StatusCode) -> bool {
+    match status {
+        StatusCode::TooManyRequests |
+        StatusCode::BadGateway |
+        StatusCode::ServiceUnavailable |
+        StatusCode::GatewayTimeout => true,
+        _ => false,
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs().saturating_mul(1000) + (duration.subsec_nanos() / 1_000_000) as u64
+}
+
+impl ExponentialBackoff {
+    /// How long to wait after this attempt number, before any jitter.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u64.checked_shl(attempt.saturating_sub(1)).unwrap_or(u64::max_value());
+        let delay = Duration::from_millis(millis(self.base).saturating_mul(factor));
+        cmp::min(delay, self.max)
+    }
+
+    /// The delay for this attempt with jitter applied.
+    pub fn jittered_delay(&self, attempt: &Attempt) -> Duration {
+        let delay = millis(self.delay(attempt.number));
+        let jittered = match self.jitter {
+            Jitter::None => delay,
+            Jitter::Full => between(0, delay),
+            Jitter::Equal => delay / 2 + between(0, delay - delay / 2),
+            Jitter::Decorrelated => {
+                let base = millis(self.base);
+                let previous = attempt.previous_delay.map(millis).unwrap_or(base);
+                cmp::min(millis(self.max), between(base, previous.saturating_mul(3)))
+            }
+        };
+        Duration::from_millis(jittered)
+    }
+}
+
+fn between(low: u64, high: u64) -> u64 {
+    if high <= low {
+        low
+    } else {
+        rand::thread_rng().gen_range(low, high.saturating_add(1))
+    }
+}
+
+impl RetryPolicy for ExponentialBackoff {
+    fn decide(&self, attempt: &Attempt) -> RetryDecision {
+        if attempt.number >= self.max_attempts {
+            return RetryDecision::Stop;
+        }
+        let transient = match attempt.outcome {
+            Outcome::Error(&CursError::Network(_)) => true,
+            Outcome::Status(status) => is_transient(status),
+            _ => false,
+        };
+        let asked = match attempt.outcome {
+            Outcome::Status(StatusCode::TooManyRequests) |
+            Outcome::Status(StatusCode::ServiceUnavailable) => attempt.retry_after,
+            _ => None,
+        };
+        if let Some(wait) = asked {
+            RetryDecision::Retry(cmp::min(wait, self.max))
+        } else if transient {
+            RetryDecision::Retry(self.jittered_delay(attempt))
+        } else {
+            RetryDecision::Stop
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::fmt;
+use std::ops::Deref;
+use std::ptr;
+
+/// Overwrites these bytes with zeros, in a way the compiler can't optimize away
+/// even if they're about to be freed.
+pub fn zero(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+}
+
+/// A password, token or key that's zeroed when dropped, so it doesn't linger in
+/// freed memory of long running processes. It derefs to a str. Copies made from
+/// it, like the headers a request sends, are on their own.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(secret: String) -> Secret {
+        Secret(secret)
+    }
+}
+
+impl Deref for Secret {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Secret {
+    fn from(secret: String) -> Secret {
+        Secret(secret)
+    }
+}
+
+impl<'a> From<&'a str> for Secret {
+    fn from(secret: &'a str) -> Secret {
+        Secret(secret.to_string())
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Secret(<redacted>)")
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        // All zeros is still valid UTF-8.
+        zero(unsafe { self.0.as_mut_vec() });
+    }
+}
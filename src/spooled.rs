@@ -0,0 +1,141 @@
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::PathBuf;
+
+use hyper::header::Headers;
+use serde::Deserialize;
+use serde_json;
+use url::Url;
+use uuid::Uuid;
+
+use super::{CursResult, Response, StatusCode};
+
+enum Spool {
+    Memory(Cursor<Vec<u8>>),
+    // The path is only kept where open files can't be removed, see remove_early.
+    Disk(File, Option<PathBuf>),
+}
+
+/// A body kept in memory while it's small and in a temporary file once it's over a
+/// threshold, so even huge ones can be read and decoded as many times as needed
+/// without running out of memory. The file goes away with the SpooledBody.
+pub struct SpooledBody {
+    spool: Spool,
+    len: u64,
+}
+
+// On unix the file can be removed right away, so it's gone even if the process dies.
+#[cfg(unix)]
+fn remove_early(path: PathBuf) -> io::Result<Option<PathBuf>> {
+    try!(fs::remove_file(&path));
+    Ok(None)
+}
+
+#[cfg(not(unix))]
+fn remove_early(path: PathBuf) -> io::Result<Option<PathBuf>> {
+    Ok(Some(path))
+}
+
+impl SpooledBody {
+    /// Reads all of it. Past `threshold` bytes everything goes to a temporary file.
+    pub fn read_from<R: Read>(mut reader: R, threshold: usize) -> io::Result<SpooledBody> {
+        let mut start = vec![];
+        try!(reader.by_ref().take(threshold as u64 + 1).read_to_end(&mut start));
+        if start.len() <= threshold {
+            return Ok(SpooledBody {
+                len: start.len() as u64,
+                spool: Spool::Memory(Cursor::new(start)),
+            });
+        }
+        let path = env::temp_dir().join(format!("curs-spool-{}",
+                                                Uuid::new_v4().to_hyphenated_string()));
+        let mut file = try!(OpenOptions::new()
+                                .read(true)
+                                .write(true)
+                                .create(true)
+                                .truncate(true)
+                                .open(&path));
+        let path = try!(remove_early(path));
+        try!(file.write_all(&start));
+        let len = start.len() as u64 + try!(io::copy(&mut reader, &mut file));
+        try!(file.seek(SeekFrom::Start(0)));
+        Ok(SpooledBody {
+            spool: Spool::Disk(file, path),
+            len: len,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether it was big enough to go to a temporary file.
+    pub fn is_on_disk(&self) -> bool {
+        match self.spool {
+            Spool::Disk(..) => true,
+            Spool::Memory(_) => false,
+        }
+    }
+
+    /// Back to the start, to read it again.
+    pub fn rewind(&mut self) -> io::Result<()> {
+        self.seek(SeekFrom::Start(0)).map(|_| ())
+    }
+
+    /// The whole body as JSON, read from the start without loading it all first.
+    pub fn decode<T: Deserialize>(&mut self) -> CursResult<T> {
+        try!(self.rewind());
+        Ok(try!(serde_json::from_reader(self)))
+    }
+}
+
+impl Read for SpooledBody {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.spool {
+            Spool::Memory(ref mut bytes) => bytes.read(buf),
+            Spool::Disk(ref mut file, _) => file.read(buf),
+        }
+    }
+}
+
+impl Seek for SpooledBody {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        match self.spool {
+            Spool::Memory(ref mut bytes) => bytes.seek(position),
+            Spool::Disk(ref mut file, _) => file.seek(position),
+        }
+    }
+}
+
+impl Drop for SpooledBody {
+    fn drop(&mut self) {
+        let spool = mem::replace(&mut self.spool, Spool::Memory(Cursor::new(vec![])));
+        if let Spool::Disk(file, Some(path)) = spool {
+            // It has to be closed before it can be removed.
+            drop(file);
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// A response read whole into a SpooledBody, see Request::send_spooled.
+pub struct SpooledResponse {
+    pub status: StatusCode,
+    pub headers: Headers,
+    pub url: Url,
+    pub body: SpooledBody,
+}
+
+impl SpooledResponse {
+    pub fn new(mut response: Response, threshold: usize) -> CursResult<SpooledResponse> {
+        let body = try!(SpooledBody::read_from(&mut response, threshold));
+        Ok(SpooledResponse {
+            status: response.status,
+            headers: response.headers.clone(),
+            url: response.url.clone(),
+            body: body,
+        })
+    }
+}
@@ -0,0 +1,220 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use hyper::header::{ContentType, Headers};
+use serde::Serialize;
+use serde_json;
+use url;
+
+use super::{CursError, CursResult, PreparedRequest, Rendered, Request, Response};
+use body::BodySource;
+use client::Client;
+use openapi::path_segment;
+
+// Stands in for a `{name}` while the url is resolved, something that goes through
+// parsing untouched wherever it is, even in a host.
+fn placeholder(index: usize) -> String {
+    format!("curs-template-param-{}-", index)
+}
+
+/// A request frozen with Request::freeze, to send over and over with different
+/// path params, query values and bodies. Its url was resolved and checked, and
+/// its body and headers worked out, once when it was frozen. Sending one only
+/// fills in the blanks, which is cheaper in tight loops than cloning a Request.
+///
+/// ```no_run
+/// # use curs::Client;
+/// # use curs::hyper::header::Authorization;
+/// let client = Client::new();
+/// let mut request = client.get("https://api.example.com/orders/{id}");
+/// request.header(Authorization("Token abc".to_string()));
+/// let orders = request.freeze().unwrap();
+/// for id in &["7", "8", "9"] {
+///     orders.request().path_param("id", id).query("expand", "items").send().unwrap();
+/// }
+/// ```
+pub struct RequestTemplate<'a> {
+    request: Request<'a>,
+    // The final url cut at every `{name}`, so there's one more piece than names.
+    pieces: Vec<String>,
+    names: Vec<String>,
+    headers: Headers,
+    body: Option<Vec<u8>>,
+    body_source: Option<Arc<BodySource + Send + Sync>>,
+}
+
+impl<'a> RequestTemplate<'a> {
+    pub fn new(request: &Request<'a>) -> CursResult<RequestTemplate<'a>> {
+        let mut names = vec![];
+        let mut url = String::new();
+        let mut rest = &*request.url;
+        while let Some(start) = rest.find('{') {
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => break,
+            };
+            url.push_str(&rest[..start]);
+            url.push_str(&placeholder(names.len()));
+            names.push(rest[start + 1..end].to_string());
+            rest = &rest[end + 1..];
+        }
+        url.push_str(rest);
+
+        // Braces don't make it through Request::new's check in every part of a url.
+        let mut request = request.clone();
+        request.problems.retain(|problem| !problem.starts_with("Invalid url"));
+        request.url = Cow::Owned(url);
+        let request = request.checking_url();
+        let resolved = try!(request.resolve_url());
+        let (rendered_url, headers, body, body_source) = {
+            let Rendered { url, headers, body, body_source } = try!(request.render(resolved,
+                                                                                   None));
+            (url, headers, body.map(|body| body.into_owned()), body_source)
+        };
+
+        // Fragments aren't sent anyway.
+        let mut rest = rendered_url.split('#').next().unwrap_or("");
+        let mut pieces = vec![];
+        for index in 0..names.len() {
+            let marker = placeholder(index);
+            match rest.find(&*marker) {
+                Some(start) => {
+                    pieces.push(rest[..start].to_string());
+                    rest = &rest[start + marker.len()..];
+                }
+                None => {
+                    let problem = format!("The url lost its {{{}}} when resolved", names[index]);
+                    return Err(CursError::InvalidRequest(vec![problem]));
+                }
+            }
+        }
+        pieces.push(rest.to_string());
+        Ok(RequestTemplate {
+            request: request,
+            pieces: pieces,
+            names: names,
+            headers: headers,
+            body: body,
+            body_source: body_source,
+        })
+    }
+
+    /// A request to fill in and send. Every `{name}` in the url needs a path_param.
+    pub fn request<'t>(&'t self) -> TemplatedRequest<'t, 'a> {
+        TemplatedRequest {
+            template: self,
+            path_params: vec![],
+            query: vec![],
+            body: None,
+            content_type: None,
+        }
+    }
+
+    /// The names between braces in its url, in order.
+    pub fn path_params(&self) -> &[String] {
+        &self.names
+    }
+}
+
+/// One request made from a RequestTemplate.
+pub struct TemplatedRequest<'t, 'a: 't> {
+    template: &'t RequestTemplate<'a>,
+    path_params: Vec<(String, String)>,
+    query: Vec<(String, String)>,
+    body: Option<Vec<u8>>,
+    content_type: Option<ContentType>,
+}
+
+impl<'t, 'a> TemplatedRequest<'t, 'a> {
+    /// What goes where `{name}` is in the url, percent-encoded.
+    pub fn path_param(&mut self, name: &str, value: &str) -> &mut TemplatedRequest<'t, 'a> {
+        self.path_params.push((name.to_string(), path_segment(value)));
+        self
+    }
+
+    /// Add a param to the query, after any the template has, whatever the method.
+    pub fn query(&mut self, name: &str, value: &str) -> &mut TemplatedRequest<'t, 'a> {
+        self.query.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Send this body instead of the template's, with the template's Content-Type.
+    pub fn body<B: Into<Vec<u8>>>(&mut self, body: B) -> &mut TemplatedRequest<'t, 'a> {
+        self.body = Some(body.into());
+        self
+    }
+
+    /// Send this as a JSON body instead of the template's.
+    pub fn json<S: Serialize>(&mut self, thing: S) -> &mut TemplatedRequest<'t, 'a> {
+        self.content_type = Some(ContentType("application/json".parse().unwrap()));
+        self.body(serde_json::to_string(&thing).unwrap())
+    }
+
+    /// Like Request::prepare. Fails with CursError::InvalidRequest when a `{name}`
+    /// in the url has no path_param.
+    pub fn prepare(&self) -> CursResult<PreparedRequest> {
+        let template = self.template;
+        let request = &template.request;
+        let mut url = template.pieces[0].clone();
+        for (name, piece) in template.names.iter().zip(&template.pieces[1..]) {
+            match self.path_params.iter().rev().find(|&&(ref param, _)| param == name) {
+                Some(&(_, ref value)) => url.push_str(value),
+                None => {
+                    let problem = format!("No path_param for {{{}}} in the url", name);
+                    return Err(CursError::InvalidRequest(vec![problem]));
+                }
+            }
+            url.push_str(piece);
+        }
+        if !self.query.is_empty() {
+            let separator = if url.contains('?') { '&' } else { '?' };
+            url.push(separator);
+            url.push_str(&url::form_urlencoded::serialize(&self.query));
+        }
+
+        let mut headers = template.headers.clone();
+        if let Some(ref content_type) = self.content_type {
+            headers.set(content_type.clone());
+        }
+        let body = match self.body {
+            Some(ref body) => Some(Cow::Borrowed(&body[..])),
+            None => template.body.as_ref().map(|body| Cow::Borrowed(&body[..])),
+        };
+        // The signature has a timestamp in it, receivers would take a frozen one for
+        // a replay.
+        if let Some(signer) = request.webhook_signer {
+            let signature = signer.sign(body.as_ref().map(|b| &**b).unwrap_or(&[]));
+            headers.set_raw(signer.header().to_string(), vec![signature.into_bytes()]);
+        }
+        let body_source = match body {
+            Some(_) => None,
+            None => template.body_source.clone(),
+        };
+        Ok(PreparedRequest {
+            method: request.method.clone(),
+            url: url,
+            headers: headers,
+            body: body,
+            body_source: body_source,
+            proxy: request.proxy.clone(),
+            sni_hostname: request.sni_hostname.clone(),
+            verify_hostname: request.verify_hostname.clone(),
+            priority: request.priority,
+            retry_non_idempotent: request.retry_non_idempotent,
+            cache_policy: request.cache_policy,
+            audit_tags: request.audit_tags.clone(),
+            webhook_signer: request.webhook_signer.cloned(),
+        })
+    }
+
+    /// Send it with the template's Client, checked like Request::send does.
+    pub fn send(&self) -> CursResult<Response> {
+        let prepared = try!(self.prepare());
+        let request = &self.template.request;
+        let response = try!(match request.client {
+            Some(client) => client.send_prepared(prepared),
+            None => Client::new().send_prepared(prepared),
+        });
+        request.check_sent(response)
+    }
+}
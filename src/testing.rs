@@ -0,0 +1,296 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use hyper::method::Method;
+use hyper::status::StatusCode;
+
+/// What a StubServer expects and answers, set up in StubServer::run.
+#[derive(Clone, Debug)]
+pub struct Stub {
+    method: Option<Method>,
+    path: Option<String>,
+    headers: Vec<(String, String)>,
+    bodies: Vec<String>,
+    status: StatusCode,
+    response_headers: Vec<(String, String)>,
+    response_body: Vec<u8>,
+}
+
+impl Stub {
+    fn new() -> Stub {
+        Stub {
+            method: None,
+            path: None,
+            headers: vec![],
+            bodies: vec![],
+            status: StatusCode::Ok,
+            response_headers: vec![],
+            response_body: vec![],
+        }
+    }
+
+    pub fn got_method(&mut self, method: Method) -> &mut Stub {
+        self.method = Some(method);
+        self
+    }
+
+    /// The path, query included, exactly. Ending it with `*` makes it a prefix.
+    pub fn got_path(&mut self, path: &str) -> &mut Stub {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    /// A header whose value contains this. Names are case insensitive.
+    pub fn got_header(&mut self, name: &str, contains: &str) -> &mut Stub {
+        self.headers.push((name.to_lowercase(), contains.to_string()));
+        self
+    }
+
+    /// A body that contains this. Chunked bodies are put together first.
+    pub fn got_body(&mut self, contains: &str) -> &mut Stub {
+        self.bodies.push(contains.to_string());
+        self
+    }
+
+    /// 200 OK unless told otherwise.
+    pub fn send_status(&mut self, status: StatusCode) -> &mut Stub {
+        self.status = status;
+        self
+    }
+
+    pub fn send_header(&mut self, name: &str, value: &str) -> &mut Stub {
+        self.response_headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn send_body(&mut self, body: &str) -> &mut Stub {
+        self.response_body = body.as_bytes().to_vec();
+        self
+    }
+
+    // What didn't match, if anything.
+    fn check(&self, request: &StubRequest) -> Vec<String> {
+        let mut failures = vec![];
+        if let Some(ref method) = self.method {
+            if *method != request.method {
+                failures.push(format!("Expected a {} but got a {}", method, request.method));
+            }
+        }
+        if let Some(ref path) = self.path {
+            let matches = if path.ends_with('*') {
+                request.path.starts_with(path.trim_right_matches('*'))
+            } else {
+                request.path == *path
+            };
+            if !matches {
+                failures.push(format!("Expected {} but got {}", path, request.path));
+            }
+        }
+        for &(ref name, ref contains) in &self.headers {
+            match request.header(name) {
+                Some(value) if value.contains(&**contains) => {}
+                Some(value) => {
+                    failures.push(format!("Expected {} to contain {:?} but it was {:?}",
+                                          name,
+                                          contains,
+                                          value))
+                }
+                None => failures.push(format!("Expected a {} header", name)),
+            }
+        }
+        let body = String::from_utf8_lossy(&request.body);
+        for contains in &self.bodies {
+            if !body.contains(&**contains) {
+                failures.push(format!("Expected the body to contain {:?}", contains));
+            }
+        }
+        failures
+    }
+}
+
+/// A request a StubServer got.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StubRequest {
+    pub method: Method,
+    pub path: String,
+    /// Names in lowercase, in the order they came.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl StubRequest {
+    /// The first value of this header. Names are case insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_lowercase();
+        self.headers.iter().find(|&&(ref n, _)| *n == name).map(|&(_, ref v)| &**v)
+    }
+}
+
+/// A small HTTP server for tests, that checks the requests it gets and answers
+/// them as told, for testing code that uses curs.
+/// It serves requests one at a time on a thread of its own until the process
+/// ends. Those that don't match get a 500 saying why, and assert_satisfied tells
+/// about them.
+///
+/// ```no_run
+/// # use curs::{Method, Request, StatusCode};
+/// # use curs::testing::StubServer;
+/// let server = StubServer::run(|stub| {
+///     stub.got_method(Method::Post);
+///     stub.got_path("/orders");
+///     stub.got_body("amount=10");
+///     stub.send_status(StatusCode::Created);
+///     stub.send_body(r#"{"id":1}"#);
+/// });
+/// let url = format!("{}/orders", server.url());
+/// let response = Request::new(Method::Post, &url).params(vec![("amount", "10")]).send().unwrap();
+/// assert_eq!(response.status, StatusCode::Created);
+/// server.assert_satisfied();
+/// ```
+pub struct StubServer {
+    url: String,
+    requests: Arc<Mutex<Vec<StubRequest>>>,
+    failures: Arc<Mutex<Vec<String>>>,
+}
+
+impl StubServer {
+    /// Starts listening on a free port on localhost before returning.
+    pub fn run<F: FnOnce(&mut Stub)>(configure: F) -> StubServer {
+        let mut stub = Stub::new();
+        configure(&mut stub);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let failures = Arc::new(Mutex::new(vec![]));
+        let (seen, failed) = (requests.clone(), failures.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if let Ok(stream) = stream {
+                    if let Err(error) = serve(&stub, stream, &seen, &failed) {
+                        failed.lock().unwrap().push(format!("Couldn't serve: {}", error));
+                    }
+                }
+            }
+        });
+        StubServer {
+            url: url,
+            requests: requests,
+            failures: failures,
+        }
+    }
+
+    /// Like `http://127.0.0.1:34567`, without a trailing slash.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Every request served so far.
+    pub fn requests(&self) -> Vec<StubRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Panics with everything that didn't match, or if nothing came at all.
+    pub fn assert_satisfied(&self) {
+        let failures = self.failures.lock().unwrap();
+        if !failures.is_empty() {
+            panic!("The stub server got unexpected requests:\n{}", failures.join("\n"));
+        }
+        if self.requests.lock().unwrap().is_empty() {
+            panic!("The stub server got no requests");
+        }
+    }
+}
+
+fn invalid(why: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, why.to_string())
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut line = String::new();
+    try!(reader.read_line(&mut line));
+    Ok(line.trim_right_matches(|c| c == '\r' || c == '\n').to_string())
+}
+
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<StubRequest> {
+    let request_line = try!(read_line(reader));
+    let mut parts = request_line.split(' ');
+    let method = try!(parts.next()
+                           .and_then(|m| Method::from_str(m).ok())
+                           .ok_or_else(|| invalid("No method")));
+    let path = try!(parts.next().ok_or_else(|| invalid("No path"))).to_string();
+    let mut headers = vec![];
+    loop {
+        let line = try!(read_line(reader));
+        if line.is_empty() {
+            break;
+        }
+        let mut header = line.splitn(2, ':');
+        match (header.next(), header.next()) {
+            (Some(name), Some(value)) => {
+                headers.push((name.trim().to_lowercase(), value.trim().to_string()))
+            }
+            _ => return Err(invalid("Malformed header")),
+        }
+    }
+    let mut request = StubRequest {
+        method: method,
+        path: path,
+        headers: headers,
+        body: vec![],
+    };
+    let chunked = request.header("transfer-encoding")
+                         .map_or(false, |value| value.to_lowercase().contains("chunked"));
+    if chunked {
+        loop {
+            let line = try!(read_line(reader));
+            let size = line.split(';').next().unwrap_or("").trim();
+            let size = try!(usize::from_str_radix(size, 16)
+                                .map_err(|_| invalid("Malformed chunk size")));
+            if size == 0 {
+                try!(read_line(reader));
+                break;
+            }
+            let mut chunk = vec![0; size];
+            try!(reader.read_exact(&mut chunk));
+            request.body.extend(chunk);
+            try!(read_line(reader));
+        }
+    } else if let Some(length) = request.header("content-length").and_then(|l| l.parse().ok()) {
+        let mut body = vec![0; length];
+        try!(reader.read_exact(&mut body));
+        request.body = body;
+    }
+    Ok(request)
+}
+
+fn serve(stub: &Stub,
+         stream: TcpStream,
+         seen: &Mutex<Vec<StubRequest>>,
+         failed: &Mutex<Vec<String>>)
+         -> io::Result<()> {
+    let mut reader = BufReader::new(try!(stream.try_clone()));
+    let request = try!(read_request(&mut reader));
+    let failures = stub.check(&request);
+    seen.lock().unwrap().push(request);
+    let mut stream = stream;
+    if !failures.is_empty() {
+        let body = failures.join("\n");
+        failed.lock().unwrap().extend(failures);
+        return write!(stream,
+                      "HTTP/1.1 500 Internal Server Error\r\nContent-Length: {}\r\n\
+                       Connection: close\r\n\r\n{}",
+                      body.len(),
+                      body);
+    }
+    try!(write!(stream, "HTTP/1.1 {}\r\n", stub.status));
+    for &(ref name, ref value) in &stub.response_headers {
+        try!(write!(stream, "{}: {}\r\n", name, value));
+    }
+    try!(write!(stream,
+                "Content-Length: {}\r\nConnection: close\r\n\r\n",
+                stub.response_body.len()));
+    stream.write_all(&stub.response_body)
+}
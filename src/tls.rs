@@ -0,0 +1,149 @@
+use std::sync::Arc;
+
+use hyper::net::{HttpStream, Openssl, Ssl};
+use openssl::nid::Nid;
+use openssl::ssl::{Ssl as OpensslSsl, SslContext, SslContextOptions, SslMethod, SslStream};
+use openssl::ssl::{SSL_OP_NO_SSLV2, SSL_OP_NO_SSLV3, SSL_OP_NO_TLSV1, SSL_OP_NO_TLSV1_1};
+use openssl::ssl::SSL_VERIFY_PEER;
+use openssl::ssl::error::SslError;
+use openssl::x509::X509;
+
+/// TLS protocol versions, oldest first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Tls {
+    V1,
+    V1_1,
+    V1_2,
+}
+
+/// How TLS connections get negotiated. The defaults are whatever openssl allows.
+#[derive(Clone, Debug, Default)]
+pub struct TlsSettings {
+    /// Refuse to negotiate anything older than this. SSLv2 and SSLv3 go away too.
+    pub min_version: Option<Tls>,
+    /// An openssl cipher list, like "ECDHE+AESGCM:!aNULL".
+    pub ciphers: Option<String>,
+    /// ALPN protocols to offer, most preferred first, like `["http/1.1"]`.
+    /// Only sent when curs is built with the alpn feature. Keep in mind curs speaks
+    /// HTTP/1.1, so offering just that one is how to steer clear of broken h2 servers.
+    pub alpn_protocols: Vec<String>,
+    /// Send this name in the SNI extension instead of the host being connected to.
+    pub sni_hostname: Option<String>,
+    /// Check the server certificate against the system CAs, and that it is valid for
+    /// this name. Handy along sni_hostname, when the host isn't the name to expect.
+    pub verify_hostname: Option<String>,
+    /// Use this openssl context as is, for anything the other settings don't cover.
+    /// min_version, ciphers and alpn_protocols are then up to whoever built it.
+    pub context: Option<Openssl>,
+}
+
+impl TlsSettings {
+    /// An openssl context following these settings.
+    /// Fails when openssl doesn't understand the cipher list.
+    pub fn openssl(&self) -> Result<Openssl, SslError> {
+        if let Some(ref custom) = self.context {
+            return Ok(custom.clone());
+        }
+        let mut context = try!(SslContext::new(SslMethod::Sslv23));
+        if let Some(version) = self.min_version {
+            context.set_options(disabled_protocols(version));
+        }
+        if let Some(ref ciphers) = self.ciphers {
+            try!(context.set_cipher_list(ciphers));
+        }
+        if self.verify_hostname.is_some() {
+            try!(context.set_default_verify_paths());
+        }
+        self.offer_alpn(&mut context);
+        Ok(Openssl { context: Arc::new(context) })
+    }
+
+    /// What https connections get wrapped with: openssl() plus the SNI and
+    /// verification names.
+    pub fn ssl(&self) -> Result<CursSsl, SslError> {
+        Ok(CursSsl {
+            openssl: try!(self.openssl()),
+            sni_hostname: self.sni_hostname.clone(),
+            verify_hostname: self.verify_hostname.clone(),
+        })
+    }
+
+    #[cfg(feature = "alpn")]
+    fn offer_alpn(&self, context: &mut SslContext) {
+        if !self.alpn_protocols.is_empty() {
+            let protocols: Vec<&[u8]> = self.alpn_protocols.iter().map(|p| p.as_bytes()).collect();
+            context.set_alpn_protocols(&protocols);
+        }
+    }
+
+    #[cfg(not(feature = "alpn"))]
+    fn offer_alpn(&self, _context: &mut SslContext) {}
+}
+
+fn disabled_protocols(min_version: Tls) -> SslContextOptions {
+    let mut options = SSL_OP_NO_SSLV2 | SSL_OP_NO_SSLV3;
+    if min_version > Tls::V1 {
+        options = options | SSL_OP_NO_TLSV1;
+    }
+    if min_version > Tls::V1_1 {
+        options = options | SSL_OP_NO_TLSV1_1;
+    }
+    options
+}
+
+/// Whether a certificate name like `*.example.com` covers this host.
+/// Wildcards only stand for the leftmost label.
+pub fn name_matches(pattern: &str, host: &str) -> bool {
+    let (pattern, host) = (pattern.to_lowercase(), host.to_lowercase());
+    if pattern.starts_with("*.") {
+        match host.find('.') {
+            Some(dot) => dot > 0 && host[dot..] == pattern[1..],
+            None => false,
+        }
+    } else {
+        pattern == host
+    }
+}
+
+fn certificate_matches(certificate: &X509, host: &str) -> bool {
+    if let Some(names) = certificate.subject_alt_names() {
+        return names.iter().filter_map(|n| n.dnsname()).any(|n| name_matches(n, host));
+    }
+    certificate.subject_name()
+               .text_by_nid(Nid::CN)
+               .map(|cn| name_matches(&cn, host))
+               .unwrap_or(false)
+}
+
+/// Wraps https connections using openssl like hyper's Openssl does, but can send a
+/// different SNI name and verify the server certificate against a given name.
+#[derive(Clone)]
+pub struct CursSsl {
+    openssl: Openssl,
+    sni_hostname: Option<String>,
+    verify_hostname: Option<String>,
+}
+
+impl Ssl for CursSsl {
+    type Stream = SslStream<HttpStream>;
+
+    fn wrap_client(&self, stream: HttpStream, host: &str) -> ::hyper::Result<Self::Stream> {
+        let mut ssl = try!(OpensslSsl::new(&self.openssl.context));
+        try!(ssl.set_hostname(self.sni_hostname.as_ref().map(|s| &**s).unwrap_or(host)));
+        if let Some(expected) = self.verify_hostname.clone() {
+            // Intermediate certificates only need to chain up, the name is on the leaf.
+            ssl.set_verify_callback(SSL_VERIFY_PEER, move |chain_ok, context| {
+                chain_ok &&
+                (context.error_depth() > 0 ||
+                 context.get_current_cert()
+                        .map(|certificate| certificate_matches(&certificate, &expected))
+                        .unwrap_or(false))
+            });
+        }
+        SslStream::connect(ssl, stream).map_err(From::from)
+    }
+
+    fn wrap_server(&self, stream: HttpStream) -> ::hyper::Result<Self::Stream> {
+        self.openssl.wrap_server(stream)
+    }
+}
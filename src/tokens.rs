@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde_json::{self, Value};
+
+use super::{replace_file, CursError, CursResult};
+use clock::{Clock, SystemClock};
+use secret::Secret;
+
+/// An access token, and what's needed to renew it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token {
+    pub access_token: Secret,
+    pub refresh_token: Option<Secret>,
+    /// Unix timestamp, in seconds.
+    pub expires_at: Option<i64>,
+}
+
+impl Token {
+    pub fn is_expired(&self) -> bool {
+        self.is_expired_at(SystemClock.unix_time())
+    }
+
+    /// Whether it's expired at this unix timestamp, for checking against a Clock.
+    pub fn is_expired_at(&self, now: i64) -> bool {
+        self.expires_at.map(|at| at <= now).unwrap_or(false)
+    }
+
+    fn to_value(&self) -> Value {
+        let mut map = BTreeMap::new();
+        map.insert("access_token".to_string(), Value::String(self.access_token.to_string()));
+        if let Some(ref refresh) = self.refresh_token {
+            map.insert("refresh_token".to_string(), Value::String(refresh.to_string()));
+        }
+        if let Some(at) = self.expires_at {
+            map.insert("expires_at".to_string(), Value::I64(at));
+        }
+        Value::Object(map)
+    }
+
+    fn from_value(value: &Value) -> Option<Token> {
+        value.find("access_token").and_then(|a| a.as_str()).map(|access| {
+            Token {
+                access_token: Secret::from(access),
+                refresh_token: value.find("refresh_token")
+                                    .and_then(|r| r.as_str())
+                                    .map(Secret::from),
+                expires_at: value.find("expires_at").and_then(|e| e.as_i64()),
+            }
+        })
+    }
+}
+
+/// Where tokens are kept between requests, and maybe between runs.
+/// Keys are up to you, an account name or a client id work well.
+pub trait TokenStore {
+    fn load(&self, key: &str) -> CursResult<Option<Token>>;
+    fn save(&self, key: &str, token: &Token) -> CursResult<()>;
+    fn remove(&self, key: &str) -> CursResult<()>;
+}
+
+/// Keeps tokens for as long as the process lives.
+#[derive(Debug, Default)]
+pub struct MemoryTokenStore {
+    tokens: Mutex<BTreeMap<String, Token>>,
+}
+
+impl MemoryTokenStore {
+    pub fn new() -> MemoryTokenStore {
+        MemoryTokenStore::default()
+    }
+}
+
+impl TokenStore for MemoryTokenStore {
+    fn load(&self, key: &str) -> CursResult<Option<Token>> {
+        Ok(self.tokens.lock().unwrap().get(key).cloned())
+    }
+
+    fn save(&self, key: &str, token: &Token) -> CursResult<()> {
+        self.tokens.lock().unwrap().insert(key.to_string(), token.clone());
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> CursResult<()> {
+        self.tokens.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+/// Keeps tokens in a JSON file so they survive restarts.
+/// On unix the file is only readable by its owner. It's replaced whole on every
+/// save, so a crash halfway through never loses the tokens already in it.
+#[derive(Debug)]
+pub struct FileTokenStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileTokenStore {
+    pub fn new(path: &Path) -> FileTokenStore {
+        FileTokenStore {
+            path: path.to_path_buf(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn read_all(&self) -> CursResult<BTreeMap<String, Value>> {
+        if !self.path.exists() {
+            return Ok(BTreeMap::new());
+        }
+        let mut contents = String::new();
+        try!(try!(File::open(&self.path)).read_to_string(&mut contents));
+        match try!(serde_json::from_str::<Value>(&contents)) {
+            Value::Object(tokens) => Ok(tokens),
+            _ => Err(CursError::Config(format!("{} is not a token file", self.path.display()))),
+        }
+    }
+
+    fn write_all(&self, tokens: BTreeMap<String, Value>) -> CursResult<()> {
+        let contents = try!(serde_json::to_string(&Value::Object(tokens)));
+        let mut options = OpenOptions::new();
+        restrict_to_owner(&mut options);
+        try!(replace_file(&self.path, contents.as_bytes(), options));
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn restrict_to_owner(options: &mut OpenOptions) {
+    use std::os::unix::fs::OpenOptionsExt;
+    options.mode(0o600);
+}
+
+#[cfg(not(unix))]
+fn restrict_to_owner(_: &mut OpenOptions) {}
+
+impl TokenStore for FileTokenStore {
+    fn load(&self, key: &str) -> CursResult<Option<Token>> {
+        let _guard = self.lock.lock().unwrap();
+        Ok(try!(self.read_all()).get(key).and_then(Token::from_value))
+    }
+
+    fn save(&self, key: &str, token: &Token) -> CursResult<()> {
+        let _guard = self.lock.lock().unwrap();
+        let mut tokens = try!(self.read_all());
+        tokens.insert(key.to_string(), token.to_value());
+        self.write_all(tokens)
+    }
+
+    fn remove(&self, key: &str) -> CursResult<()> {
+        let _guard = self.lock.lock().unwrap();
+        let mut tokens = try!(self.read_all());
+        tokens.remove(key);
+        self.write_all(tokens)
+    }
+}
+
+/// Keeps tokens in the operating system's keyring (the macOS Keychain, the Secret
+/// Service on Linux, the Credential Vault on Windows), one entry per key under
+/// `service`. Needs the os-keyring feature.
+#[cfg(feature = "os-keyring")]
+#[derive(Debug)]
+pub struct KeyringTokenStore {
+    service: String,
+}
+
+#[cfg(feature = "os-keyring")]
+impl KeyringTokenStore {
+    pub fn new(service: &str) -> KeyringTokenStore {
+        KeyringTokenStore { service: service.to_string() }
+    }
+}
+
+#[cfg(feature = "os-keyring")]
+fn keyring_error(error: ::keyring::KeyringError) -> CursError {
+    CursError::Config(format!("Can't use the keyring: {}", error))
+}
+
+#[cfg(feature = "os-keyring")]
+impl TokenStore for KeyringTokenStore {
+    fn load(&self, key: &str) -> CursResult<Option<Token>> {
+        use keyring::{Keyring, KeyringError};
+        match Keyring::new(&self.service, key).get_password() {
+            Ok(stored) => {
+                let value = try!(serde_json::from_str::<Value>(&stored));
+                Ok(Token::from_value(&value))
+            }
+            Err(KeyringError::NoPasswordFound) => Ok(None),
+            Err(error) => Err(keyring_error(error)),
+        }
+    }
+
+    fn save(&self, key: &str, token: &Token) -> CursResult<()> {
+        let stored = try!(serde_json::to_string(&token.to_value()));
+        ::keyring::Keyring::new(&self.service, key).set_password(&stored).map_err(keyring_error)
+    }
+
+    fn remove(&self, key: &str) -> CursResult<()> {
+        use keyring::{Keyring, KeyringError};
+        match Keyring::new(&self.service, key).delete_password() {
+            Ok(()) | Err(KeyringError::NoPasswordFound) => Ok(()),
+            Err(error) => Err(keyring_error(error)),
+        }
+    }
+}
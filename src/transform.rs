@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+
+use hyper::header::{ContentType, Headers};
+use hyper::mime::SubLevel;
+use serde_json::{self, Value};
+
+use super::CursResult;
+
+/// Rewrites request bodies after they're serialized and response bodies before
+/// they're decoded, like encrypting fields or wrapping everything in an envelope,
+/// so the code making requests doesn't need to know. See Client::body_transformer.
+/// Bodies made while they're sent, see body::BodySource, are left alone.
+pub trait BodyTransformer {
+    /// The body about to be sent. Headers like Content-Type can be changed to match.
+    fn outgoing(&self, body: Vec<u8>, headers: &mut Headers) -> CursResult<Vec<u8>>;
+
+    /// The body of a response, with its headers, before anyone reads it.
+    fn incoming(&self, body: Vec<u8>, headers: &Headers) -> CursResult<Vec<u8>>;
+
+    /// Whether incoming would change a response with these headers. Responses no
+    /// transformer would change are handed over as they come, without reading them
+    /// into memory first.
+    fn rewrites_incoming(&self, _headers: &Headers) -> bool {
+        true
+    }
+}
+
+fn is_json(headers: &Headers) -> bool {
+    match headers.get::<ContentType>() {
+        Some(&ContentType(ref mime)) => {
+            match mime.1 {
+                SubLevel::Json => true,
+                SubLevel::Ext(ref ext) => ext.ends_with("+json"),
+                _ => false,
+            }
+        }
+        None => false,
+    }
+}
+
+/// For APIs that wrap JSON responses in an object, like `{"data": ..., "meta": ...}`,
+/// leaving just what's in this field so it decodes into the inner type. Responses
+/// without the field, like most errors, pass through as they are. See
+/// Client::unwrap_field and Request::unwrap_field, and decode::JsonEnvelope to
+/// keep the meta too.
+#[derive(Clone, Debug)]
+pub struct Unwrap(pub String);
+
+impl BodyTransformer for Unwrap {
+    fn outgoing(&self, body: Vec<u8>, _: &mut Headers) -> CursResult<Vec<u8>> {
+        Ok(body)
+    }
+
+    fn incoming(&self, body: Vec<u8>, headers: &Headers) -> CursResult<Vec<u8>> {
+        if !is_json(headers) || body.is_empty() {
+            return Ok(body);
+        }
+        match try!(serde_json::from_slice::<Value>(&body)) {
+            Value::Object(mut fields) => {
+                match fields.remove(&self.0) {
+                    Some(inner) => Ok(try!(serde_json::to_vec(&inner))),
+                    None => Ok(body),
+                }
+            }
+            _ => Ok(body),
+        }
+    }
+
+    fn rewrites_incoming(&self, headers: &Headers) -> bool {
+        is_json(headers)
+    }
+}
+
+/// For APIs that wrap JSON bodies in an object both ways, like `{"data": {...}}`.
+/// Other bodies, and JSON responses without the field, like most errors, pass
+/// through as they are.
+#[derive(Clone, Debug)]
+pub struct Envelope(pub String);
+
+impl BodyTransformer for Envelope {
+    fn outgoing(&self, body: Vec<u8>, headers: &mut Headers) -> CursResult<Vec<u8>> {
+        if !is_json(headers) {
+            return Ok(body);
+        }
+        let mut envelope = BTreeMap::new();
+        envelope.insert(self.0.clone(), try!(serde_json::from_slice::<Value>(&body)));
+        Ok(try!(serde_json::to_vec(&Value::Object(envelope))))
+    }
+
+    fn incoming(&self, body: Vec<u8>, headers: &Headers) -> CursResult<Vec<u8>> {
+        Unwrap(self.0.clone()).incoming(body, headers)
+    }
+
+    fn rewrites_incoming(&self, headers: &Headers) -> bool {
+        is_json(headers)
+    }
+}
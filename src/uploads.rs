@@ -0,0 +1,105 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+
+use hyper::method::Method;
+use hyper::mime::Mime;
+
+use super::{CursResult, FileUpload, Response};
+use client::Client;
+
+/// One file for send_many, sent on its own as a multipart field.
+#[derive(Clone, Debug)]
+pub struct Upload {
+    pub method: Method,
+    pub url: String,
+    /// The form field's name.
+    pub name: String,
+    pub path: PathBuf,
+    /// Guessed from the extension when None.
+    pub mime: Option<Mime>,
+}
+
+impl Upload {
+    /// A POST of this file as the `name` field.
+    pub fn new(url: &str, name: &str, path: &Path) -> Upload {
+        Upload {
+            method: Method::Post,
+            url: url.to_string(),
+            name: name.to_string(),
+            path: path.to_path_buf(),
+            mime: None,
+        }
+    }
+
+    fn send(&self, client: &Client) -> CursResult<Response> {
+        let file = FileUpload {
+            name: self.name.clone(),
+            path: &self.path,
+            mime: self.mime.clone(),
+        };
+        client.request(self.method.clone(), &self.url)
+            .files(vec![file])
+            .stream_multipart()
+            .send()
+    }
+}
+
+/// Sends every upload as a request of its own with this Client, `concurrency` of
+/// them at a time, like syncing a folder of media. Each thread sends with a
+/// Client::try_clone, so default headers, signers, tokens and the rest apply.
+/// Files are streamed from disk, so big ones don't pile up in memory. A failed
+/// upload doesn't stop the others, the results come in the same order as the
+/// uploads.
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use curs::Client;
+/// # use curs::uploads::{self, Upload};
+/// let client = Client::new();
+/// let photos = vec![Upload::new("http://example.com/photos", "photo", Path::new("a.jpg")),
+///                   Upload::new("http://example.com/photos", "photo", Path::new("b.jpg"))];
+/// for result in uploads::send_many(&client, photos, 4).unwrap() {
+///     if result.is_err() { println!("Upload failed") }
+/// }
+/// ```
+pub fn send_many(client: &Client,
+                 uploads: Vec<Upload>,
+                 concurrency: usize)
+                 -> CursResult<Vec<CursResult<Response>>> {
+    let count = uploads.len();
+    let clients = try!((0..concurrency.max(1).min(count))
+                           .map(|_| client.try_clone())
+                           .collect::<CursResult<Vec<Client>>>());
+    let pending = Arc::new(Mutex::new(uploads.into_iter().enumerate().collect::<VecDeque<_>>()));
+    let (sender, receiver) = mpsc::channel();
+    let workers: Vec<_> = clients.into_iter()
+                                 .map(|client| {
+                                     let pending = pending.clone();
+                                     let sender = sender.clone();
+                                     thread::spawn(move || {
+                                         loop {
+                                             let next = pending.lock().unwrap().pop_front();
+                                             match next {
+                                                 Some((index, upload)) => {
+                                                     let result = upload.send(&client);
+                                                     let _ = sender.send((index, result));
+                                                 }
+                                                 None => return,
+                                             }
+                                         }
+                                     })
+                                 })
+                                 .collect();
+    drop(sender);
+
+    let mut results: Vec<Option<CursResult<Response>>> = (0..count).map(|_| None).collect();
+    for (index, result) in receiver {
+        results[index] = Some(result);
+    }
+    for worker in workers {
+        let _ = worker.join();
+    }
+    Ok(results.into_iter().map(|result| result.unwrap()).collect())
+}
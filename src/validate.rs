@@ -0,0 +1,49 @@
+use hyper::header::ContentType;
+use hyper::mime::{Mime, SubLevel, TopLevel};
+
+use super::Response;
+
+/// Looks at every response a Client gets before the caller does. Turning one down
+/// makes the request fail with CursError::InvalidResponse, carrying the reason.
+/// Any `Fn(&Response) -> Result<(), String>` is one.
+pub trait ResponseValidator {
+    fn validate(&self, response: &Response) -> Result<(), String>;
+}
+
+impl<F> ResponseValidator for F
+    where F: Fn(&Response) -> Result<(), String>
+{
+    fn validate(&self, response: &Response) -> Result<(), String> {
+        self(response)
+    }
+}
+
+/// Turns down responses without this header, like an `X-Request-Id` every
+/// response from your API is supposed to carry.
+#[derive(Clone, Debug)]
+pub struct RequireHeader(pub String);
+
+impl ResponseValidator for RequireHeader {
+    fn validate(&self, response: &Response) -> Result<(), String> {
+        match response.headers.get_raw(&self.0) {
+            Some(_) => Ok(()),
+            None => Err(format!("No {} header in the response", self.0)),
+        }
+    }
+}
+
+/// Turns down HTML responses, which from a JSON API usually mean a captive
+/// portal, a proxy's error page or a misrouted request.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RejectHtml;
+
+impl ResponseValidator for RejectHtml {
+    fn validate(&self, response: &Response) -> Result<(), String> {
+        match response.headers.get::<ContentType>() {
+            Some(&ContentType(Mime(TopLevel::Text, SubLevel::Html, _))) => {
+                Err(format!("Got an HTML page from {}", response.url))
+            }
+            _ => Ok(()),
+        }
+    }
+}
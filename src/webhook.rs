@@ -0,0 +1,62 @@
+use openssl::crypto::hmac::hmac;
+use rustc_serialize::hex::ToHex;
+use time;
+
+pub use openssl::crypto::hash::Type as HashType;
+
+use secret;
+
+/// Signs outgoing webhook calls, Stripe style: an HMAC of "{timestamp}.{body}"
+/// goes in a header as "t={timestamp},v1={hex signature}".
+/// Defaults to SHA256 and the X-Webhook-Signature header.
+#[derive(Clone)]
+pub struct WebhookSigner {
+    key: Vec<u8>,
+    algorithm: HashType,
+    header: String,
+}
+
+impl WebhookSigner {
+    pub fn new(key: &[u8]) -> WebhookSigner {
+        WebhookSigner {
+            key: key.to_vec(),
+            algorithm: HashType::SHA256,
+            header: "X-Webhook-Signature".to_string(),
+        }
+    }
+
+    pub fn algorithm(&mut self, algorithm: HashType) -> &mut WebhookSigner {
+        self.algorithm = algorithm;
+        self
+    }
+
+    pub fn header_name(&mut self, name: &str) -> &mut WebhookSigner {
+        self.header = name.to_string();
+        self
+    }
+
+    /// The name of the header the signature goes in.
+    pub fn header(&self) -> &str {
+        &self.header
+    }
+
+    /// The header value for the given body, signed at the given unix timestamp.
+    /// Receivers can use it to verify what they got.
+    pub fn signature(&self, timestamp: i64, body: &[u8]) -> String {
+        let mut payload = format!("{}.", timestamp).into_bytes();
+        payload.extend(body);
+        let digest = hmac(self.algorithm, &self.key, &payload);
+        format!("t={},v1={}", timestamp, digest.to_hex())
+    }
+
+    /// The header value for the given body, signed right now.
+    pub fn sign(&self, body: &[u8]) -> String {
+        self.signature(time::get_time().sec, body)
+    }
+}
+
+impl Drop for WebhookSigner {
+    fn drop(&mut self) {
+        secret::zero(&mut self.key);
+    }
+}
@@ -0,0 +1,25 @@
+// Generated by curs::openapi, edit the spec instead.
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Pet {
+    #[serde(rename = "birthDate")]
+    pub birth_date: Option<String>,
+    pub name: String,
+    pub tags: Option<Vec<String>>,
+}
+
+/// Finds a pet.
+pub fn get_pet(client: &::curs::Client, pet_id: &str, fields: &str) -> ::curs::CursResult<Pet> {
+    let url = format!("pets/{}", ::curs::openapi::path_segment(pet_id));
+    let url = ::curs::openapi::with_query(&url, &[("fields", Some(fields))]);
+    let request = client.request(::curs::Method::Get, &url);
+    ::curs::DecodableResult::decode_success(request.send())
+}
+
+pub fn update_pet(client: &::curs::Client, pet_id: &str, fields: Option<&str>, body: &Pet) -> ::curs::CursResult<::curs::Response> {
+    let url = format!("pets/{}", ::curs::openapi::path_segment(pet_id));
+    let url = ::curs::openapi::with_query(&url, &[("fields", fields)]);
+    let mut request = client.request(::curs::Method::Put, &url);
+    request.json(body);
+    request.send()
+}
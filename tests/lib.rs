@@ -4,16 +4,47 @@
 use std::{thread, time};
 
 
+#[macro_use]
 extern crate curs;
-extern crate http_stub;
+extern crate openssl;
 extern crate serde;
 
 use std::env;
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+use std::sync::{mpsc, Arc, Mutex};
 use curs::hyper::header::{UserAgent, ContentType};
 use curs::hyper::method::Method;
-use curs::{Request, DecodableResult, CursResult, CursError, FileUpload};
-use http_stub::HttpStub;
-use http_stub as hs;
+use curs::{Request, BytesUpload, Cached, DecodableResult, CursResult, CursError, FileUpload, Client,
+           HalResource, ODataQuery, SpooledBody, WebhookSigner};
+use curs::{charset, har, idn, odata, openapi, pagination, BackgroundQueue, ClientPool, Priority,
+           QueueDepth};
+use curs::proxy::{NoProxy, ProxySettings, parse_proxy_url};
+use curs::ratelimit::{RateLimit, RateLimiter};
+use curs::tls::{self, Tls};
+use curs::retry::{self, Attempt, ExponentialBackoff, Jitter, Outcome, RetryDecision, RetryPolicy};
+use curs::decode::{Decoder, JsonEnvelope, JsonField, LenientJson};
+use curs::dns::{self, Resolver};
+use curs::encode::BodyEncoder;
+use curs::endpoint::{self, Endpoint};
+use curs::clock::{Clock, ManualClock};
+use curs::call::{Call, MapRequest, Retry, call_fn};
+use curs::cache::{CachePolicy, CacheStore, CachedResponse, DiskCacheStore, Freshness,
+                  MemoryCacheStore};
+use curs::auth::{Challenge, CredentialProvider, Credentials};
+use curs::tokens::{FileTokenStore, MemoryTokenStore, Token, TokenStore};
+use curs::transform::Envelope;
+use curs::validate::{RejectHtml, RequireHeader};
+use curs::archive::TarDirectory;
+use curs::audit::AuditRecord;
+use curs::body::{BodyProducer, BodySource};
+use curs::language::Localized;
+use curs::loadgen::{self, LoadPlan};
+use curs::progress::PartProgress;
+use curs::resource::Resource;
+use curs::uploads::{self, Upload};
+use curs::hyper::header::{Authorization, Cookie, CookiePair, Headers};
+use curs::testing::StubServer;
 
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
 struct DummyJson {
@@ -22,21 +53,20 @@ struct DummyJson {
 
 #[test]
 fn successful_multipart() {
-    let url = HttpStub::run(|mut stub| {
+    let server = StubServer::run(|stub| {
         stub.got_path("/some_post");
-        stub.got_method(hs::Method::Post);
+        stub.got_method(Method::Post);
         stub.got_header("content-type", "multipart/form-data; boundary");
         stub.got_header("user-agent", "morcilla-firefox");
-        stub.got_body(r"Content-Type: image/png");
+        stub.got_body("Content-Type: image/png");
         stub.got_body(r#"name="shim.png"; filename="test.png""#);
-        stub.got_body(r#"Content-Disposition: form-data; name="two"\r\nvalue_two\r\n"#);
+        stub.got_body("Content-Disposition: form-data; name=\"two\"\r\nvalue_two\r\n");
 
-        stub.send_status(hs::StatusCode::Ok);
-        stub.send_header(hs::header::ContentType(hs::Mime(hs::TopLevel::Application,
-                                                          hs::SubLevel::Json,
-                                                          vec![])));
+        stub.send_status(curs::StatusCode::Ok);
+        stub.send_header("Content-Type", "application/json");
         stub.send_body(r#"{"foo":"got files"}"#);
     });
+    let url = server.url().to_string();
     print!("{}", url);
     let ten_millis = time::Duration::from_millis(10);
     thread::sleep(ten_millis);
@@ -59,13 +89,14 @@ fn successful_multipart() {
 
 #[test]
 fn successful_json_get() {
-    let url = HttpStub::run(|stub| {
-        stub.got_path(r"/a_get\?one=value_one&two=value_two");
+    let server = StubServer::run(|stub| {
+        stub.got_path("/a_get?one=value_one&two=value_two");
         stub.got_body("");
-        stub.got_method(hs::Method::Get);
+        stub.got_method(Method::Get);
         stub.got_header("user-agent", "morcilla-firefox");
         stub.send_body(r#"{"foo":"bar"}"#);
     });
+    let url = server.url().to_string();
     print!("{}", url);
     let ten_millis = time::Duration::from_millis(10);
     thread::sleep(ten_millis);
@@ -81,12 +112,13 @@ fn successful_json_get() {
 
 #[test]
 fn successful_json_post() {
-    let url = HttpStub::run(|stub| {
+    let server = StubServer::run(|stub| {
         stub.got_path("/some_post");
-        stub.got_method(hs::Method::Post);
+        stub.got_method(Method::Post);
         stub.got_body("one=value_one&two=value_two");
         stub.send_body(r#"{"foo":"that"}"#);
     });
+    let url = server.url().to_string();
     let ten_millis = time::Duration::from_millis(10);
     thread::sleep(ten_millis);
 
@@ -100,13 +132,14 @@ fn successful_json_post() {
 
 #[test]
 fn successful_json_body_post() {
-    let url = HttpStub::run(|stub| {
+    let server = StubServer::run(|stub| {
         stub.got_path("/see_this_json");
-        stub.got_method(hs::Method::Post);
+        stub.got_method(Method::Post);
         stub.got_header("content-type", "application/json");
-        stub.got_body(r#"\{"foo":"this"\}"#);
+        stub.got_body(r#"{"foo":"this"}"#);
         stub.send_body(r#"{"foo":"that"}"#);
     });
+    let url = server.url().to_string();
     let ten_millis = time::Duration::from_millis(10);
     thread::sleep(ten_millis);
 
@@ -120,13 +153,14 @@ fn successful_json_body_post() {
 
 #[test]
 fn successful_raw_body_post() {
-    let url = HttpStub::run(|stub| {
+    let server = StubServer::run(|stub| {
         stub.got_path("/a_potato");
-        stub.got_method(hs::Method::Post);
+        stub.got_method(Method::Post);
         stub.got_header("content-type", "application/potato");
         stub.got_body("A potato's body");
         stub.send_body(r#"{"foo":"potato"}"#);
     });
+    let url = server.url().to_string();
     let ten_millis = time::Duration::from_millis(10);
     thread::sleep(ten_millis);
 
@@ -142,12 +176,13 @@ fn successful_raw_body_post() {
 
 #[test]
 fn errors_out_with_not_found() {
-    let url = HttpStub::run(|mut stub| {
+    let server = StubServer::run(|stub| {
         stub.got_body("");
-        stub.got_method(hs::Method::Get);
-        stub.send_status(hs::StatusCode::InternalServerError);
+        stub.got_method(Method::Get);
+        stub.send_status(curs::StatusCode::InternalServerError);
         stub.send_body("404 not found");
     });
+    let url = server.url().to_string();
     let ten_millis = time::Duration::from_millis(10);
     thread::sleep(ten_millis);
 
@@ -158,3 +193,3379 @@ fn errors_out_with_not_found() {
         _ => panic!("No status error"),
     }
 }
+
+#[test]
+fn follows_hal_links() {
+    let orders_server = StubServer::run(|stub| {
+        stub.got_path("/orders");
+        stub.got_method(Method::Get);
+        stub.send_body(r#"{"foo":"orders"}"#);
+    });
+    let orders_url = orders_server.url().to_string();
+    let root_server = StubServer::run(move |stub| {
+        stub.got_path("/");
+        stub.send_body(&*format!(r#"{{"_links":{{"orders":{{"href":"{}/orders"}}}}}}"#,
+                                 orders_url));
+    });
+    let root_url = root_server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let client = Client::new();
+    let root: HalResource = client.request(Method::Get, &*format!("{}/", root_url))
+                                  .send()
+                                  .decode_success()
+                                  .unwrap();
+    let response: DummyJson = root.follow(&client, "orders").decode_success().unwrap();
+    assert_eq!(response, DummyJson { foo: "orders".to_string() });
+
+    match root.follow(&client, "customers").unwrap_err() {
+        CursError::MissingLink(rel) => assert_eq!(rel, "customers"),
+        _ => panic!("No missing link error"),
+    }
+}
+
+#[test]
+fn successful_odata_query() {
+    let server = StubServer::run(|stub| {
+        stub.got_path(concat!("/People?%24filter=%28Name+eq+%27O%27%27Neil%27%29+and+",
+                              "%28Age+gt+30%29&%24select=Name%2CAge&%24top=5&%24orderby=Age+desc"));
+        stub.got_method(Method::Get);
+        stub.send_body(r#"{"foo":"people"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let mut query = ODataQuery::new();
+    query.filter(&*format!("Name eq {}", odata::literal("O'Neil")))
+         .filter("Age gt 30")
+         .select(&["Name", "Age"])
+         .top(5)
+         .order_by("Age desc");
+
+    let response: DummyJson = Request::new(Method::Get, &*format!("{}/People", url))
+                                  .params(query.params())
+                                  .send()
+                                  .decode_success()
+                                  .unwrap();
+    assert_eq!(response, DummyJson { foo: "people".to_string() });
+}
+
+#[test]
+fn signs_webhook_body() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/hooks");
+        stub.got_method(Method::Post);
+        stub.got_header("x-partner-signature", ",v1=");
+        stub.send_body(r#"{"foo":"signed"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let mut signer = WebhookSigner::new(b"whsec");
+    signer.header_name("X-Partner-Signature");
+    assert_eq!(signer.signature(1480000000, br#"{"foo":"event"}"#),
+               "t=1480000000,v1=c4280e4affb3b09503075511d77413e565d67ec53e001b7ddc412ad3008794a7");
+
+    let response: DummyJson = Request::new(Method::Post, &*format!("{}/hooks", url))
+                                  .json(DummyJson { foo: "event".to_string() })
+                                  .sign_webhook(&signer)
+                                  .send()
+                                  .decode_success()
+                                  .unwrap();
+    assert_eq!(response, DummyJson { foo: "signed".to_string() });
+}
+
+#[test]
+fn reuses_idempotency_key() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/payments");
+        stub.got_method(Method::Post);
+        stub.got_header("idempotency-key", "-");
+        stub.send_body(r#"{"foo":"paid"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let payments_url = format!("{}/payments", url);
+    let mut request = Request::new(Method::Post, &*payments_url);
+    request.params(vec![("amount", "10")]).idempotent();
+    let retry = request.clone();
+
+    let mut another = Request::new(Method::Post, &*payments_url);
+    another.params(vec![("amount", "10")]).idempotent();
+
+    for attempt in vec![&request, &retry, &another] {
+        let response: DummyJson = attempt.send().decode_success().unwrap();
+        assert_eq!(response, DummyJson { foo: "paid".to_string() });
+    }
+    let requests = server.requests();
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[0].header("idempotency-key"), requests[1].header("idempotency-key"));
+    assert!(requests[0].header("idempotency-key") != requests[2].header("idempotency-key"));
+}
+
+#[test]
+fn signs_prepared_requests() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/orders?market=btc");
+        stub.got_method(Method::Get);
+        stub.got_header("authorization", "/orders?market=btc 0");
+        stub.send_body(r#"{"foo":"orders"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let mut client = Client::new();
+    client.signer(|prepared| {
+        let signature = format!("{} {} {}",
+                                prepared.method,
+                                prepared.url,
+                                prepared.body.as_ref().map(|b| b.len()).unwrap_or(0));
+        prepared.headers.set(Authorization(signature));
+    });
+
+    let response: DummyJson = client.request(Method::Get, &*format!("{}/orders", url))
+                                    .params(vec![("market", "btc")])
+                                    .send()
+                                    .decode_success()
+                                    .unwrap();
+    assert_eq!(response, DummyJson { foo: "orders".to_string() });
+}
+
+#[test]
+fn uses_netrc_credentials() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/private");
+        // "robot:s3cret" in base64.
+        stub.got_header("authorization", "Basic cm9ib3Q6czNjcmV0");
+        stub.send_body(r#"{"foo":"private"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let netrc_path = env::temp_dir().join("curs_test_netrc");
+    let mut netrc_file = File::create(&netrc_path).unwrap();
+    netrc_file.write_all(b"machine example.com login nobody password nothing\n\
+                           machine 127.0.0.1\n  login robot\n  password s3cret\n\
+                           machine localhost login robot password s3cret\n")
+              .unwrap();
+
+    let mut client = Client::new();
+    client.netrc_file(&netrc_path);
+    let response: DummyJson = client.request(Method::Get, &*format!("{}/private", url))
+                                    .send()
+                                    .decode_success()
+                                    .unwrap();
+    assert_eq!(response, DummyJson { foo: "private".to_string() });
+}
+
+#[test]
+fn leaves_credentials_behind_on_redirects_to_other_origins() {
+    let elsewhere = StubServer::run(|stub| {
+        stub.got_path("/landing");
+        stub.send_body(r#"{"foo":"landed"}"#);
+    });
+    let landing = format!("{}/landing", elsewhere.url());
+    let server = StubServer::run(move |stub| {
+        stub.got_path("/private");
+        stub.got_header("authorization", "Basic cm9ib3Q6czNjcmV0");
+        stub.send_status(curs::StatusCode::Found);
+        stub.send_header("Location", &landing);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let netrc_path = env::temp_dir().join("curs_test_redirect_netrc");
+    let mut netrc_file = File::create(&netrc_path).unwrap();
+    netrc_file.write_all(b"machine 127.0.0.1 login robot password s3cret\n").unwrap();
+
+    let mut client = Client::new();
+    client.netrc_file(&netrc_path);
+    let response: DummyJson = client.request(Method::Get, &*format!("{}/private", url))
+                                    .header(Cookie(vec![CookiePair::new("session".to_string(),
+                                                                        "secret".to_string())]))
+                                    .send()
+                                    .decode_success()
+                                    .unwrap();
+    assert_eq!(response, DummyJson { foo: "landed".to_string() });
+    server.assert_satisfied();
+    elsewhere.assert_satisfied();
+
+    // Another port is another origin, even on the same host.
+    let landed = elsewhere.requests();
+    assert_eq!(landed.len(), 1);
+    assert_eq!(landed[0].header("authorization"), None);
+    assert_eq!(landed[0].header("cookie"), None);
+}
+
+#[test]
+fn does_not_sign_redirects_to_other_origins() {
+    let elsewhere = StubServer::run(|stub| {
+        stub.got_path("/landing");
+        stub.send_body(r#"{"foo":"landed"}"#);
+    });
+    let landing = format!("{}/landing", elsewhere.url());
+    let server = StubServer::run(move |stub| {
+        stub.got_path("/signed");
+        stub.got_header("x-signature", "GET");
+        stub.send_status(curs::StatusCode::Found);
+        stub.send_header("Location", &landing);
+    });
+
+    let mut client = Client::new();
+    client.signer(|prepared| {
+        let signature = format!("{} {}", prepared.method, prepared.url);
+        prepared.headers.set_raw("X-Signature", vec![signature.into_bytes()]);
+    });
+    let response: DummyJson = client.get(&*format!("{}/signed", server.url()))
+                                    .send()
+                                    .decode_success()
+                                    .unwrap();
+    assert_eq!(response, DummyJson { foo: "landed".to_string() });
+    server.assert_satisfied();
+    elsewhere.assert_satisfied();
+    assert_eq!(elsewhere.requests()[0].header("x-signature"), None);
+}
+
+#[test]
+fn matches_no_proxy_rules() {
+    let no_proxy = NoProxy::parse(".internal.example, *.corp.example,localhost:8080 \
+                                   10.0.0.0/8 [::1] fd00::/8");
+
+    assert!(no_proxy.matches("api.internal.example", 443));
+    assert!(no_proxy.matches("internal.example", 80));
+    assert!(no_proxy.matches("wiki.corp.example", 80));
+    assert!(!no_proxy.matches("notcorp.example", 80));
+    assert!(no_proxy.matches("localhost", 8080));
+    assert!(!no_proxy.matches("localhost", 3000));
+    assert!(no_proxy.matches("10.20.30.40", 80));
+    assert!(!no_proxy.matches("11.0.0.1", 80));
+    assert!(no_proxy.matches("[::1]", 80));
+    assert!(no_proxy.matches("fd12:3456::1", 80));
+    assert!(!no_proxy.matches("example.com", 80));
+
+    assert!(NoProxy::parse("*").matches("anything.example", 80));
+}
+
+// Cargo builds the binary next to the test's own, or a directory up from it.
+#[cfg(feature = "cli")]
+fn run_cli(url: &str) -> std::process::Output {
+    let test = env::current_exe().unwrap();
+    let dir = test.parent().unwrap();
+    let bin = dir.join("curs");
+    let bin = if bin.exists() { bin } else { dir.parent().unwrap().join("curs") };
+    std::process::Command::new(bin).arg(url).output().unwrap()
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn prints_bodies_raw_from_the_command_line() {
+    let binary = run_cli("data:application/octet-stream;base64,/wD+iQ==");
+    assert!(binary.status.success());
+    assert_eq!(binary.stdout, vec![0xFF, 0x00, 0xFE, 0x89]);
+
+    let json = run_cli(r#"data:application/json,{"foo":"bar"}"#);
+    assert_eq!(String::from_utf8(json.stdout).unwrap(), "{\n  \"foo\": \"bar\"\n}\n");
+    let text = run_cli(r#"data:text/plain,{"foo":"bar"}"#);
+    assert_eq!(String::from_utf8(text.stdout).unwrap(), r#"{"foo":"bar"}"#);
+}
+
+#[cfg(feature = "config")]
+#[test]
+fn loads_config_profiles() {
+    use curs::config::Config;
+
+    let config = Config::parse(r#"
+        base_url = "https://api.example.com/v1/"
+        timeout = 30
+
+        [headers]
+        User-Agent = "curs-tests"
+
+        [profiles.staging]
+        base_url = "https://staging.example.com/v1/"
+        proxy = "http://proxy.corp:3128"
+
+        [profiles.staging.headers]
+        X-Env = "staging"
+    "#)
+                     .unwrap();
+
+    let default = config.profile("default").unwrap();
+    assert_eq!(default.base_url, Some("https://api.example.com/v1/".to_string()));
+    assert_eq!(default.timeout, Some(30));
+    assert_eq!(default.proxy, None);
+
+    let staging = config.profile("staging").unwrap();
+    assert_eq!(staging.base_url, Some("https://staging.example.com/v1/".to_string()));
+    assert_eq!(staging.timeout, Some(30));
+    assert_eq!(staging.proxy, Some("http://proxy.corp:3128".to_string()));
+    assert_eq!(staging.headers,
+               vec![("User-Agent".to_string(), "curs-tests".to_string()),
+                    ("X-Env".to_string(), "staging".to_string())]);
+    assert!(staging.client().is_ok());
+
+    match config.profile("production").unwrap_err() {
+        CursError::Config(_) => {}
+        _ => panic!("No config error"),
+    }
+}
+
+struct RecordingProvider {
+    asked_for: Arc<Mutex<Vec<String>>>,
+}
+
+impl CredentialProvider for RecordingProvider {
+    fn credentials(&self, challenge: &Challenge) -> Option<Credentials> {
+        self.asked_for.lock().unwrap().push(challenge.url.to_string());
+        Some(Credentials {
+            username: "robot".to_string(),
+            password: Some("wrong".into()),
+        })
+    }
+}
+
+#[test]
+fn asks_credential_provider_on_unauthorized() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/private");
+        stub.send_status(curs::StatusCode::Unauthorized);
+        stub.send_body("Who are you?");
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let asked_for = Arc::new(Mutex::new(vec![]));
+    let mut client = Client::new();
+    client.credential_provider(RecordingProvider { asked_for: asked_for.clone() });
+
+    let result: CursResult<DummyJson> = client.request(Method::Get, &*format!("{}/private", url))
+                                              .send()
+                                              .decode_success();
+
+    // Retried just once, then the 401 is handed back.
+    match result.unwrap_err() {
+        CursError::Status(response) => assert_eq!(response.status, curs::StatusCode::Unauthorized),
+        _ => panic!("No status error"),
+    }
+    assert_eq!(asked_for.lock().unwrap().len(), 1);
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].header("authorization"), None);
+    // "robot:wrong" in base64.
+    assert_eq!(requests[1].header("authorization"), Some("Basic cm9ib3Q6d3Jvbmc="));
+}
+
+#[test]
+fn asks_credential_provider_for_proxies() {
+    // Wants credentials for the first CONNECT, then tunnels nowhere.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy = format!("http://{}", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for answer in &["407 Proxy Authentication Required\r\n\
+                         Proxy-Authenticate: Basic realm=\"corp\"\r\nContent-Length: 0",
+                        "200 Connection Established"] {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut byte = [0];
+            while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+                request.push(byte[0]);
+            }
+            sender.send(String::from_utf8(request).unwrap()).unwrap();
+            stream.write_all(format!("HTTP/1.1 {}\r\n\r\n", answer).as_bytes()).unwrap();
+        }
+    });
+
+    let asked_for = Arc::new(Mutex::new(vec![]));
+    let mut client = Client::new();
+    client.credential_provider(RecordingProvider { asked_for: asked_for.clone() });
+    client.proxy(ProxySettings {
+        http: None,
+        https: Some(curs::hyper::Url::parse(&proxy).unwrap()),
+        no_proxy: NoProxy::default(),
+    });
+    // The tunnel closes before any TLS goes through it.
+    assert!(client.get("https://example.com/private").send().is_err());
+
+    let first = receiver.recv().unwrap();
+    assert!(first.starts_with("CONNECT example.com:443 HTTP/1.1\r\n"));
+    assert!(!first.contains("Proxy-Authorization"));
+    let second = receiver.recv().unwrap();
+    assert!(second.contains("Proxy-Authorization: Basic cm9ib3Q6d3Jvbmc=\r\n"));
+    assert_eq!(*asked_for.lock().unwrap(), vec!["https://example.com:443".to_string()]);
+}
+
+#[test]
+fn keeps_tokens_in_a_file_and_sends_them() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/me");
+        stub.got_header("authorization", "Bearer access-one");
+        stub.send_body(r#"{"foo":"me"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let path = env::temp_dir().join("curs_test_tokens.json");
+    let token = Token {
+        access_token: "access-one".into(),
+        refresh_token: Some("refresh-one".into()),
+        expires_at: Some(4102444800),
+    };
+    FileTokenStore::new(&path).save("robot", &token).unwrap();
+    // Written next to it and renamed over it.
+    assert!(!env::temp_dir().join("curs_test_tokens.json.tmp").exists());
+    if cfg!(unix) {
+        assert_eq!(owner_only_mode(&path), Some(0o600));
+    }
+
+    // A new store on the same file, as if the process had restarted.
+    let store = FileTokenStore::new(&path);
+    assert_eq!(store.load("robot").unwrap(), Some(token));
+    assert_eq!(store.load("nobody").unwrap(), None);
+
+    let mut client = Client::new();
+    client.bearer_tokens(store, "robot");
+    let response: DummyJson = client.request(Method::Get, &*format!("{}/me", url))
+                                    .send()
+                                    .decode_success()
+                                    .unwrap();
+    assert_eq!(response, DummyJson { foo: "me".to_string() });
+}
+
+#[cfg(unix)]
+fn owner_only_mode(path: &std::path::Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path).ok().map(|metadata| metadata.permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+fn owner_only_mode(_: &std::path::Path) -> Option<u32> {
+    None
+}
+
+#[test]
+fn sends_bearer_tokens_only_to_their_origin() {
+    let elsewhere = StubServer::run(|stub| {
+        stub.got_path("/moved");
+        stub.send_body(r#"{"foo":"moved"}"#);
+    });
+    let moved = format!("{}/moved", elsewhere.url());
+    let server = StubServer::run(move |stub| {
+        stub.got_path("/me");
+        stub.got_header("authorization", "Bearer access-one");
+        stub.send_status(curs::StatusCode::MovedPermanently);
+        stub.send_header("Location", &moved);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let store = MemoryTokenStore::new();
+    let token = Token {
+        access_token: "access-one".into(),
+        refresh_token: None,
+        expires_at: None,
+    };
+    store.save("robot", &token).unwrap();
+    let mut client = Client::new();
+    client.bearer_tokens(store, "robot");
+    let response: DummyJson = client.request(Method::Get, &*format!("{}/me", url))
+                                    .send()
+                                    .decode_success()
+                                    .unwrap();
+    assert_eq!(response, DummyJson { foo: "moved".to_string() });
+    server.assert_satisfied();
+    assert_eq!(elsewhere.requests()[0].header("authorization"), None);
+}
+
+
+    let path = env::current_dir().unwrap().join("tests/fixtures/test.png");
+    let url = format!("file://{}", path.display());
+    match Request::new(Method::Get, &url).send() {
+        Err(CursError::Network(curs::hyper::error::Error::Io(error))) => {
+            assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied)
+        }
+        other => panic!("Expected file urls to be off by default, got {:?}", other),
+    }
+
+    let mut client = Client::new();
+    client.allow_file_urls(true);
+    let mut response = client.get(&url).send().unwrap();
+    assert_eq!(response.status, curs::StatusCode::Ok);
+    assert_eq!(response.headers.get::<ContentType>().unwrap().to_string(), "image/png");
+
+    let mut body = vec![];
+    response.read_to_end(&mut body).unwrap();
+    assert_eq!(body.len(), 95);
+}
+
+#[test]
+fn decodes_data_urls() {
+    let response: DummyJson = Request::new(Method::Get, "data:application/json;base64,\
+                                                        eyJmb28iOiJiYXNlNjQifQ==")
+                                  .send()
+                                  .decode_success()
+                                  .unwrap();
+    assert_eq!(response, DummyJson { foo: "base64".to_string() });
+
+    let mut response = Request::new(Method::Get, "data:,Hello%2C%20World!").send().unwrap();
+    let mut body = String::new();
+    response.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "Hello, World!");
+    assert!(response.headers.get::<ContentType>().unwrap().to_string().starts_with("text/plain"));
+}
+
+#[test]
+fn converts_international_domain_names() {
+    assert_eq!(idn::to_ascii("bücher.example").unwrap(), "xn--bcher-kva.example");
+    assert_eq!(idn::to_unicode("xn--bcher-kva.example"), "bücher.example");
+    assert_eq!(idn::to_unicode("plain.example"), "plain.example");
+
+    let url = curs::hyper::Url::parse("https://bücher.example/böoks?q=1").unwrap();
+    assert_eq!(url.domain(), Some("xn--bcher-kva.example"));
+    assert!(idn::display_url(&url).starts_with("https://bücher.example/"));
+
+    let no_proxy = NoProxy::parse(".bücher.example");
+    assert!(no_proxy.matches("shop.xn--bcher-kva.example", 443));
+}
+
+#[test]
+fn appends_params_to_existing_query() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/search?fixed=1&one=value_one");
+        stub.got_method(Method::Get);
+        stub.send_body(r#"{"foo":"found"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let response: DummyJson = Request::new(Method::Get, &*format!("{}/search?fixed=1#top", url))
+                                  .params(vec![("one", "value_one")])
+                                  .send()
+                                  .decode_success()
+                                  .unwrap();
+    assert_eq!(response, DummyJson { foo: "found".to_string() });
+}
+
+#[test]
+fn matches_ipv6_literals() {
+    let url = curs::hyper::Url::parse("http://[::1]:8080/path").unwrap();
+    assert_eq!(url.serialize_host(), Some("[::1]".to_string()));
+    assert_eq!(url.port(), Some(8080));
+    assert!(NoProxy::parse("::1").matches("[::1]", 8080));
+
+    // Hosts without IPv6 can't have a stub on [::1].
+    let listener = match std::net::TcpListener::bind("[::1]:0") {
+        Ok(listener) => listener,
+        Err(_) => return,
+    };
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0; 1024];
+        let read = stream.read(&mut request).unwrap();
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 13\r\n\
+                           Connection: close\r\n\r\n{\"foo\":\"six\"}")
+              .unwrap();
+        String::from_utf8_lossy(&request[..read]).into_owned()
+    });
+
+    let response: DummyJson = Request::new(Method::Get, &*format!("http://[::1]:{}/", port))
+                                  .params(vec![("v", "6")])
+                                  .send()
+                                  .decode_success()
+                                  .unwrap();
+    assert_eq!(response, DummyJson { foo: "six".to_string() });
+    let request = server.join().unwrap();
+    assert!(request.starts_with("GET /?v=6 HTTP/1.1\r\n"));
+    assert!(request.contains(&format!("Host: [::1]:{}\r\n", port)));
+}
+
+#[test]
+fn resolves_relative_urls_against_base() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/api/v2/users/1?expand=profile");
+        stub.got_method(Method::Get);
+        stub.send_body(r#"{"foo":"user"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let mut client = Client::new();
+    client.base_url(curs::hyper::Url::parse(&*format!("{}/api/v1/", url)).unwrap());
+    let response: DummyJson = client.get("../v2/users/1?expand=profile")
+                                    .send()
+                                    .decode_success()
+                                    .unwrap();
+    assert_eq!(response, DummyJson { foo: "user".to_string() });
+
+    match Client::new().get("users/1").send().unwrap_err() {
+        CursError::Url(_) => {}
+        _ => panic!("No url error"),
+    }
+}
+
+#[test]
+fn skips_client_proxy_per_request() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/direct");
+        stub.send_body(r#"{"foo":"direct"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    // Nothing listens there, going through this proxy would fail.
+    let nowhere = curs::hyper::Url::parse("http://127.0.0.1:9").unwrap();
+    let mut client = Client::new();
+    client.proxy(ProxySettings {
+        http: Some(nowhere.clone()),
+        https: Some(nowhere.clone()),
+        no_proxy: NoProxy::default(),
+    });
+
+    assert!(client.get(&*format!("{}/direct", url)).send().is_err());
+
+    let response: DummyJson = client.get(&*format!("{}/direct", url))
+                                    .no_proxy()
+                                    .send()
+                                    .decode_success()
+                                    .unwrap();
+    assert_eq!(response, DummyJson { foo: "direct".to_string() });
+
+    let unreachable = Request::new(Method::Get, &*format!("{}/direct", url))
+                          .via_proxy(nowhere)
+                          .send();
+    assert!(unreachable.is_err());
+}
+
+#[test]
+fn sends_http_requests_to_proxies_in_absolute_form() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy = format!("http://robot:p%40ss@{}", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for answer in &["200 OK\r\nContent-Length: 17\r\n\r\n{\"foo\":\"proxied\"}",
+                        "200 Connection Established\r\n\r\n"] {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut byte = [0];
+            while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+                request.push(byte[0]);
+            }
+            sender.send(String::from_utf8(request).unwrap()).unwrap();
+            stream.write_all(format!("HTTP/1.1 {}", answer).as_bytes()).unwrap();
+        }
+    });
+
+    let proxy = curs::hyper::Url::parse(&proxy).unwrap();
+    let mut client = Client::new();
+    client.proxy(ProxySettings {
+        http: Some(proxy.clone()),
+        https: Some(proxy),
+        no_proxy: NoProxy::default(),
+    });
+    let response: DummyJson = client.get("http://example.com/orders?market=btc")
+                                    .send()
+                                    .decode_success()
+                                    .unwrap();
+    assert_eq!(response, DummyJson { foo: "proxied".to_string() });
+    let forwarded = receiver.recv().unwrap();
+    assert!(forwarded.starts_with("GET http://example.com/orders?market=btc HTTP/1.1\r\n"));
+    // "robot:p@ss" in base64.
+    assert!(forwarded.contains("Proxy-Authorization: Basic cm9ib3Q6cEBzcw==\r\n"));
+    assert!(forwarded.contains("Host: example.com\r\n"));
+
+    // The tunnel closes before any TLS goes through it.
+    assert!(client.get("https://example.com/orders").send().is_err());
+    let tunneled = receiver.recv().unwrap();
+    assert!(tunneled.starts_with("CONNECT example.com:443 HTTP/1.1\r\n"));
+    assert!(tunneled.contains("Proxy-Authorization: Basic cm9ib3Q6cEBzcw==\r\n"));
+}
+
+#[test]
+fn answers_proxy_challenges_on_absolute_form_requests() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy = format!("http://robot:p%40ss@{}", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for &(answer, body) in &[("407 Proxy Authentication Required\r\n\
+                                  Proxy-Authenticate: Basic realm=\"corp\"\r\n\
+                                  Content-Length: 0",
+                                 ""),
+                                ("200 OK\r\nContent-Length: 17", r#"{"foo":"proxied"}"#)] {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut byte = [0];
+            while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+                request.push(byte[0]);
+            }
+            sender.send(String::from_utf8(request).unwrap()).unwrap();
+            let response = format!("HTTP/1.1 {}\r\nConnection: close\r\n\r\n{}", answer, body);
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+
+    let proxy = curs::hyper::Url::parse(&proxy).unwrap();
+    let mut client = Client::new();
+    client.credential_provider(RecordingProvider { asked_for: Arc::new(Mutex::new(vec![])) });
+    client.proxy(ProxySettings {
+        http: Some(proxy),
+        https: None,
+        no_proxy: NoProxy::default(),
+    });
+    let response: DummyJson = client.get("http://example.com/orders")
+                                    .send()
+                                    .decode_success()
+                                    .unwrap();
+    assert_eq!(response, DummyJson { foo: "proxied".to_string() });
+
+    let first = receiver.recv().unwrap();
+    assert!(first.contains("Proxy-Authorization: Basic cm9ib3Q6cEBzcw==\r\n"));
+    // Only what the provider gave, "robot:wrong" in base64.
+    let retried = receiver.recv().unwrap();
+    assert!(retried.starts_with("GET http://example.com/orders HTTP/1.1\r\n"));
+    assert_eq!(retried.matches("Proxy-Authorization").count(), 1);
+    assert!(retried.contains("Proxy-Authorization: Basic cm9ib3Q6d3Jvbmc=\r\n"));
+}
+
+#[test]
+fn keeps_timeouts_on_rebuilt_clients() {
+    // Takes connections and never answers them.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/stalled", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        let mut held = vec![];
+        for stream in listener.incoming() {
+            held.push(stream);
+        }
+    });
+
+    let mut client = Client::new();
+    client.read_timeout(Some(time::Duration::from_millis(200)));
+    client.proxy(ProxySettings::default());
+    let started = time::Instant::now();
+    assert!(client.get(&url).send().is_err());
+    assert!(client.sibling().unwrap().get(&url).send().is_err());
+    // A hyper client of its own, going direct.
+    assert!(client.get(&url).no_proxy().send().is_err());
+    // Others for TLS names of their own.
+    assert!(client.get(&url).sni_hostname("front.example.com").send().is_err());
+    assert!(client.get(&url).verify_hostname("api.example.com").send().is_err());
+    assert!(started.elapsed() < time::Duration::from_secs(5));
+}
+
+#[test]
+fn parses_socks_proxy_urls() {
+    let tor = parse_proxy_url("socks5h://127.0.0.1:9050").unwrap();
+    assert_eq!(tor.scheme, "socks5h");
+    assert_eq!(tor.serialize_host(), Some("127.0.0.1".to_string()));
+    assert_eq!(tor.port_or_default(), Some(9050));
+    assert_eq!(parse_proxy_url("socks5://proxy.corp").unwrap().port_or_default(),
+               Some(1080));
+}
+
+#[test]
+fn refuses_host_names_too_long_for_socks5() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy = format!("socks5h://{}", listener.local_addr().unwrap());
+    let proxied = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut received = vec![];
+        stream.read_to_end(&mut received).unwrap();
+        received
+    });
+
+    let mut client = Client::new();
+    client.proxy(ProxySettings {
+        http: Some(curs::hyper::Url::parse(&proxy).unwrap()),
+        https: None,
+        no_proxy: NoProxy::default(),
+    });
+    let host = vec!["a".repeat(50); 6].join(".");
+    match client.get(&*format!("http://{}/", host)).send() {
+        Err(CursError::Network(curs::hyper::error::Error::Io(error))) => {
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput)
+        }
+        other => panic!("Expected a {} byte host name to be refused, got {:?}", host.len(), other),
+    }
+    assert_eq!(proxied.join().unwrap(), Vec::<u8>::new());
+}
+
+#[test]
+fn restricts_tls_versions_and_ciphers() {
+    let mut client = Client::new();
+    assert!(client.min_tls_version(Tls::V1_2).is_ok());
+    assert!(client.ciphers("ECDHE+AESGCM:!aNULL").is_ok());
+    assert!(client.ciphers("NOT-A-REAL-CIPHER").is_err());
+}
+
+// The first TLS record a client set up like so sends, its ClientHello. There's
+// no certificate to answer with, so the request itself fails.
+fn client_hello<F: FnOnce(&mut Client)>(configure: F) -> Vec<u8> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("https://{}/", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut hello = [0; 4096];
+        let read = stream.read(&mut hello).unwrap();
+        hello[..read].to_vec()
+    });
+    let mut client = Client::new();
+    configure(&mut client);
+    assert!(client.get(&url).send().is_err());
+    server.join().unwrap()
+}
+
+fn contains_bytes(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+#[test]
+fn offers_alpn_protocols_with_the_alpn_feature() {
+    let plain = client_hello(|_| {});
+    assert!(!contains_bytes(&plain, b"\x0bcurs-test/1"));
+    let offering = client_hello(|client| {
+        client.tls(tls::TlsSettings {
+                  alpn_protocols: vec!["curs-test/1".to_string(), "http/1.1".to_string()],
+                  ..tls::TlsSettings::default()
+              })
+              .unwrap();
+    });
+    assert_eq!(contains_bytes(&offering, b"\x0bcurs-test/1\x08http/1.1"),
+               cfg!(feature = "alpn"));
+}
+
+// What a ClientHello offers, after its record and handshake headers, version,
+// random and session id.
+fn cipher_suites(hello: &[u8]) -> Vec<u16> {
+    let at = 5 + 4 + 2 + 32;
+    let at = at + 1 + hello[at] as usize;
+    let length = (hello[at] as usize) << 8 | hello[at + 1] as usize;
+    hello[at + 2..at + 2 + length]
+        .chunks(2)
+        .map(|pair| (pair[0] as u16) << 8 | pair[1] as u16)
+        .collect()
+}
+
+#[test]
+fn negotiates_with_ready_made_ssl_contexts() {
+    // TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256 and TLS_RSA_WITH_AES_128_CBC_SHA.
+    let (ecdhe, rsa) = (0xC02F, 0x002F);
+    assert!(cipher_suites(&client_hello(|_| {})).contains(&ecdhe));
+    let custom = cipher_suites(&client_hello(|client| {
+        let mut context = openssl::ssl::SslContext::new(openssl::ssl::SslMethod::Sslv23)
+                              .unwrap();
+        context.set_cipher_list("AES128-SHA").unwrap();
+        client.ssl_context(context);
+    }));
+    assert!(custom.contains(&rsa));
+    assert!(!custom.contains(&ecdhe));
+}
+
+#[test]
+fn matches_certificate_names() {
+    assert!(tls::name_matches("front.example.com", "FRONT.example.com"));
+    assert!(tls::name_matches("*.example.com", "api.example.com"));
+    assert!(!tls::name_matches("*.example.com", "example.com"));
+    assert!(!tls::name_matches("*.example.com", "a.b.example.com"));
+}
+
+#[test]
+fn sends_over_preconnected_connections() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/warm");
+        stub.send_body(r#"{"foo":"warm"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let mut client = Client::new();
+    client.base_url(curs::hyper::Url::parse(&url).unwrap());
+    client.preconnect("/").unwrap();
+    let response: DummyJson = client.get("/warm").send().decode_success().unwrap();
+    assert_eq!(response, DummyJson { foo: "warm".to_string() });
+}
+
+#[test]
+fn queues_requests_over_the_concurrency_limit() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/queued");
+        stub.send_body(r#"{"foo":"queued"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let mut client = Client::new();
+    client.max_concurrent(1);
+    let response: DummyJson = client.get(&*format!("{}/queued", url))
+                                    .priority(Priority::Interactive)
+                                    .send()
+                                    .decode_success()
+                                    .unwrap();
+    assert_eq!(response, DummyJson { foo: "queued".to_string() });
+    assert_eq!(client.queue_depth(), QueueDepth::default());
+}
+
+#[test]
+fn sends_queued_requests_by_priority() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (release, released) = mpsc::channel();
+    let server = thread::spawn(move || {
+        let mut paths = vec![];
+        for served in 0..4 {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let read = stream.read(&mut request).unwrap();
+            let request = String::from_utf8_lossy(&request[..read]).into_owned();
+            paths.push(request.split(' ').nth(1).unwrap_or("").to_string());
+            // The first one holds the only turn until the rest are queued.
+            if served == 0 {
+                released.recv().unwrap();
+            }
+            stream.write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+                  .unwrap();
+        }
+        paths
+    });
+
+    let mut client = Client::new();
+    client.max_concurrent(1);
+    let client = Arc::new(client);
+    let send = |path: &'static str, priority: Priority| {
+        let (client, url) = (client.clone(), url.clone());
+        thread::spawn(move || {
+            client.get(&*format!("{}{}", url, path)).priority(priority).send().unwrap();
+        })
+    };
+    let mut senders = vec![send("/first", Priority::Normal)];
+    while client.queue_depth().running == 0 {
+        thread::sleep(time::Duration::from_millis(1));
+    }
+    senders.push(send("/background", Priority::Background));
+    senders.push(send("/normal", Priority::Normal));
+    senders.push(send("/interactive", Priority::Interactive));
+    while client.queue_depth().waiting() < 3 {
+        thread::sleep(time::Duration::from_millis(1));
+    }
+    release.send(()).unwrap();
+    for sender in senders {
+        sender.join().unwrap();
+    }
+    assert_eq!(server.join().unwrap(),
+               vec!["/first", "/interactive", "/normal", "/background"]);
+    assert_eq!(client.queue_depth(), QueueDepth::default());
+}
+
+#[test]
+fn sends_queued_requests_in_the_background() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/events");
+        stub.got_method(Method::Post);
+        stub.send_status(curs::StatusCode::Accepted);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let (sender, receiver) = mpsc::channel();
+    let queue = BackgroundQueue::new(Client::new(), 2, 10);
+    let events_url = format!("{}/events", url);
+    assert!(queue.enqueue(move |client| client.post(&events_url).send(),
+                          move |result| sender.send(result.map(|r| r.status)).unwrap()));
+    queue.shutdown();
+    assert_eq!(receiver.recv().unwrap().unwrap(), curs::StatusCode::Accepted);
+}
+
+struct RecordingPolicy(Arc<Mutex<Vec<u32>>>);
+
+impl RetryPolicy for RecordingPolicy {
+    fn decide(&self, attempt: &Attempt) -> RetryDecision {
+        self.0.lock().unwrap().push(attempt.number);
+        match attempt.outcome {
+            Outcome::Status(curs::StatusCode::ServiceUnavailable) if attempt.number < 3 => {
+                RetryDecision::Retry(time::Duration::from_millis(1))
+            }
+            _ => RetryDecision::Stop,
+        }
+    }
+}
+
+#[test]
+fn asks_the_retry_policy_after_failures() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/flaky");
+        stub.send_status(curs::StatusCode::ServiceUnavailable);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let attempts = Arc::new(Mutex::new(vec![]));
+    let mut client = Client::new();
+    client.retry_policy(RecordingPolicy(attempts.clone()));
+    let response = client.get(&*format!("{}/flaky", url)).send().unwrap();
+    assert_eq!(response.status, curs::StatusCode::ServiceUnavailable);
+    assert_eq!(*attempts.lock().unwrap(), vec![1, 2, 3]);
+}
+
+#[test]
+fn only_retries_posts_that_opt_in() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/charges");
+        stub.got_method(Method::Post);
+        stub.send_status(curs::StatusCode::ServiceUnavailable);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let attempts = Arc::new(Mutex::new(vec![]));
+    let mut client = Client::new();
+    client.retry_policy(RecordingPolicy(attempts.clone()));
+    let charges_url = format!("{}/charges", url);
+    client.post(&charges_url).send().unwrap();
+    assert!(attempts.lock().unwrap().is_empty());
+
+    client.post(&charges_url).retry_non_idempotent().send().unwrap();
+    assert_eq!(*attempts.lock().unwrap(), vec![1, 2, 3]);
+}
+
+#[test]
+fn gives_up_when_the_retry_budget_runs_out() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/down");
+        stub.send_status(curs::StatusCode::ServiceUnavailable);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let mut client = Client::new();
+    client.retry_policy(ExponentialBackoff {
+              max_attempts: 10,
+              base: time::Duration::from_secs(1),
+              max: time::Duration::from_secs(10),
+              jitter: Jitter::None,
+          })
+          .max_retry_duration(time::Duration::from_millis(500));
+    match client.get(&*format!("{}/down", url)).send() {
+        Err(CursError::RetriesExhausted(1, ref last)) => {
+            match **last {
+                CursError::Status(ref response) => {
+                    assert_eq!(response.status, curs::StatusCode::ServiceUnavailable)
+                }
+                _ => panic!("Last attempt should have failed with a status"),
+            }
+        }
+        _ => panic!("Retries should have run out"),
+    }
+}
+
+#[test]
+fn keeps_jittered_backoff_in_bounds() {
+    let mut backoff = ExponentialBackoff::default();
+    assert_eq!(backoff.delay(1), time::Duration::from_millis(100));
+    assert_eq!(backoff.delay(3), time::Duration::from_millis(400));
+    assert_eq!(backoff.delay(40), time::Duration::from_secs(10));
+
+    let error = CursError::Config("down".to_string());
+    let method = Method::Get;
+    let attempt = Attempt {
+        number: 3,
+        method: &method,
+        url: "http://example.com",
+        outcome: Outcome::Error(&error),
+        elapsed: time::Duration::from_secs(1),
+        previous_delay: Some(time::Duration::from_millis(200)),
+        retry_after: None,
+    };
+    for &(jitter, low, high) in &[(Jitter::Full, 0, 400),
+                                  (Jitter::Equal, 200, 400),
+                                  (Jitter::Decorrelated, 100, 600)] {
+        backoff.jitter = jitter;
+        for _ in 0..20 {
+            let delay = backoff.jittered_delay(&attempt);
+            assert!(delay >= time::Duration::from_millis(low));
+            assert!(delay <= time::Duration::from_millis(high));
+        }
+    }
+}
+
+#[test]
+fn spaces_requests_over_the_rate_limit() {
+    let start = time::Duration::from_secs(1_500_000_000);
+    let clock = Arc::new(ManualClock::new(start));
+    let limiter = RateLimiter::per_host(20.0, 2).unwrap().with_clock(clock.clone());
+    for _ in 0..4 {
+        limiter.wait("api.example.com");
+    }
+    // Two go right away, the other two wait 50ms each, give or take rounding.
+    let waited = clock.now() - start;
+    assert!(waited >= time::Duration::from_millis(99) &&
+            waited <= time::Duration::from_millis(101),
+            "waited {:?}",
+            waited);
+    limiter.wait("other.example.com");
+    assert_eq!(clock.now() - start, waited);
+
+    for rate in &[0.0, -1.0, std::f64::NAN] {
+        match RateLimiter::new(*rate, 1) {
+            Err(CursError::Config(_)) => {}
+            _ => panic!("Expected a rate of {} to be refused", rate),
+        }
+    }
+    assert!(Client::new().rate_limit_per_host(0.0, 1).is_err());
+}
+
+#[test]
+fn parses_retry_after_headers() {
+    // Five minutes before the date below.
+    let clock = ManualClock::new(time::Duration::from_secs(1445412180));
+    let mut headers = Headers::new();
+    assert_eq!(retry::retry_after(&headers, &clock), None);
+    headers.set_raw("Retry-After", vec![b"120".to_vec()]);
+    assert_eq!(retry::retry_after(&headers, &clock), Some(time::Duration::from_secs(120)));
+    headers.set_raw("Retry-After", vec![b"Wed, 21 Oct 2015 07:28:00 GMT".to_vec()]);
+    assert_eq!(retry::retry_after(&headers, &clock), Some(time::Duration::from_secs(300)));
+    clock.advance(time::Duration::from_secs(600));
+    assert_eq!(retry::retry_after(&headers, &clock), Some(time::Duration::from_secs(0)));
+    headers.set_raw("Retry-After", vec![b"soon".to_vec()]);
+    assert_eq!(retry::retry_after(&headers, &clock), None);
+}
+
+#[test]
+fn retries_after_what_the_server_asks() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/busy", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        let responses: [&[u8]; 2] =
+            [b"HTTP/1.1 503 Service Unavailable\r\nRetry-After: 7\r\nContent-Length: 0\r\n\
+               Connection: close\r\n\r\n",
+             b"HTTP/1.1 200 OK\r\nContent-Length: 14\r\nConnection: close\r\n\r\n\
+               {\"foo\":\"idle\"}"];
+        for response in responses.iter() {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            stream.read(&mut request).unwrap();
+            stream.write_all(response).unwrap();
+        }
+    });
+
+    let start = time::Duration::from_secs(1_500_000_000);
+    let clock = Arc::new(ManualClock::new(start));
+    let mut client = Client::new();
+    client.clock(clock.clone());
+    client.retry_policy(ExponentialBackoff {
+        max_attempts: 3,
+        base: time::Duration::from_millis(100),
+        max: time::Duration::from_secs(60),
+        jitter: Jitter::None,
+    });
+    let response: DummyJson = client.get(&url).send().decode_success().unwrap();
+    assert_eq!(response, DummyJson { foo: "idle".to_string() });
+    server.join().unwrap();
+    assert_eq!(clock.now(), start + time::Duration::from_secs(7));
+}
+
+#[test]
+fn parses_rate_limit_headers() {
+    let clock = ManualClock::new(time::Duration::from_secs(1445412180));
+    let mut headers = Headers::new();
+    assert_eq!(RateLimit::from_headers(&headers, &clock), None);
+    headers.set_raw("X-RateLimit-Limit", vec![b"5000".to_vec()]);
+    headers.set_raw("X-RateLimit-Remaining", vec![b"4999".to_vec()]);
+    headers.set_raw("X-RateLimit-Reset", vec![b"1".to_vec()]);
+    assert_eq!(RateLimit::from_headers(&headers, &clock),
+               Some(RateLimit {
+                   limit: Some(5000),
+                   remaining: Some(4999),
+                   reset: Some(time::Duration::from_secs(1)),
+               }));
+
+    let mut draft = Headers::new();
+    draft.set_raw("RateLimit-Limit", vec![b"100, 100;w=60".to_vec()]);
+    draft.set_raw("RateLimit-Reset", vec![b"1445412480".to_vec()]);
+    let rate_limit = RateLimit::from_headers(&draft, &clock).unwrap();
+    assert_eq!(rate_limit.limit, Some(100));
+    assert_eq!(rate_limit.remaining, None);
+    assert_eq!(rate_limit.reset, Some(time::Duration::from_secs(300)));
+    clock.advance(time::Duration::from_secs(600));
+    let rate_limit = RateLimit::from_headers(&draft, &clock).unwrap();
+    assert_eq!(rate_limit.reset, Some(time::Duration::from_secs(0)));
+}
+
+#[test]
+fn finds_next_links() {
+    let mut headers = Headers::new();
+    assert_eq!(pagination::link_with_rel(&headers, "next"), None);
+    headers.set_raw("Link",
+                    vec![b"<https://api.example.com/items?page=1>; rel=\"prev first\", \
+                           <https://api.example.com/items?page=3>; rel=\"next\""
+                             .to_vec()]);
+    assert_eq!(pagination::link_with_rel(&headers, "next"),
+               Some("https://api.example.com/items?page=3".to_string()));
+    assert_eq!(pagination::link_with_rel(&headers, "first"),
+               Some("https://api.example.com/items?page=1".to_string()));
+    assert_eq!(pagination::link_with_rel(&headers, "last"), None);
+}
+
+#[test]
+fn paginates_until_there_is_no_next_link() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/items?per_page=2");
+        stub.send_body(r#"[{"foo":"one"},{"foo":"two"}]"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let items: Vec<DummyJson> = Request::new(Method::Get, &*format!("{}/items", url))
+                                    .params(vec![("per_page", "2")])
+                                    .paginate()
+                                    .collect::<CursResult<_>>()
+                                    .unwrap();
+    assert_eq!(items,
+               vec![DummyJson { foo: "one".to_string() }, DummyJson { foo: "two".to_string() }]);
+}
+
+#[test]
+fn follows_next_links_only_within_the_origin() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/items*");
+        stub.send_header("Link", r#"</items?page=2>; rel="next""#);
+        stub.send_body(r#"[{"foo":"one"}]"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let items_url = format!("{}/items", url);
+    let mut pages = Request::new(Method::Post, &*items_url)
+                        .params(vec![("per_page", "1")])
+                        .paginate::<DummyJson>()
+                        .by_page();
+    pages.next().unwrap().unwrap();
+    pages.next().unwrap().unwrap();
+    let requests = server.requests();
+    assert_eq!(requests[1].path, "/items?page=2");
+    assert_eq!(String::from_utf8_lossy(&requests[1].body), "per_page=1");
+
+    let server = StubServer::run(|stub| {
+        stub.got_path("/items");
+        stub.send_header("Link", r#"<http://example.invalid/items?page=2>; rel="next""#);
+        stub.send_body(r#"[{"foo":"one"}]"#);
+    });
+    let url = server.url().to_string();
+    thread::sleep(ten_millis);
+
+    let mut request = Request::new(Method::Get, &*format!("{}/items", url));
+    request.raw_header("Authorization", "Bearer secret");
+    match request.paginate::<DummyJson>().collect::<CursResult<Vec<_>>>() {
+        Err(CursError::InvalidResponse(_, _)) => {}
+        _ => panic!("Expected the other origin to be refused"),
+    }
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn paginates_with_cursors_in_the_body() {
+    let first_page = r#"data:application/json,{"data":[{"foo":"first"}],"next":"second"}"#;
+    let items: Vec<DummyJson> =
+        Request::new(Method::Get, first_page)
+            .paginate_with(|_, body| body.find("next").and_then(|n| n.as_str()).map(String::from),
+                           |_, cursor| {
+                               let page = format!(r#"{{"data":[{{"foo":"{}"}}],"next":null}}"#,
+                                                  cursor);
+                               curs::hyper::Url::parse(&format!("data:application/json,{}", page))
+                                   .unwrap()
+                           })
+            .items_field("data")
+            .collect::<CursResult<_>>()
+            .unwrap();
+    assert_eq!(items,
+               vec![DummyJson { foo: "first".to_string() },
+                    DummyJson { foo: "second".to_string() }]);
+
+    let url = curs::hyper::Url::parse("http://example.com/events?limit=10&cursor=a").unwrap();
+    assert_eq!(pagination::with_query_param(&url, "cursor", "b").serialize(),
+               "http://example.com/events?limit=10&cursor=b");
+}
+
+#[test]
+fn maps_not_modified_responses() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/polled");
+        stub.got_header("if-none-match", r#""v1""#);
+        stub.send_status(curs::StatusCode::NotModified);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let cached: Cached<DummyJson> = Request::new(Method::Get, &*format!("{}/polled", url))
+                                        .if_none_match(r#""v1""#)
+                                        .send()
+                                        .decode_cached()
+                                        .unwrap();
+    assert_eq!(cached, Cached::NotModified);
+}
+
+#[test]
+fn keeps_validated_responses_in_the_cache_store() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/cached");
+        stub.send_header("ETag", r#""v1""#);
+        stub.send_body(r#"{"foo":"cached"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let dir = env::temp_dir().join("curs_test_cache");
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut client = Client::new();
+    client.cache(DiskCacheStore::new(&dir));
+    let cached_url = format!("{}/cached", url);
+    let response: DummyJson = client.get(&cached_url).send().decode_success().unwrap();
+    assert_eq!(response, DummyJson { foo: "cached".to_string() });
+
+    let stored = DiskCacheStore::new(&dir).get(&cached_url).unwrap().unwrap();
+    assert_eq!(stored.header("etag"), Some(r#""v1""#));
+    assert_eq!(stored.body, br#"{"foo":"cached"}"#.to_vec());
+
+    let store = DiskCacheStore::new(&dir);
+    store.put("other", &stored).unwrap();
+    store.remove(&cached_url).unwrap();
+    assert_eq!(store.get(&cached_url).unwrap(), None);
+    assert_eq!(store.get("other").unwrap().map(|c| c.body), Some(stored.body.clone()));
+    assert!(!dir.join("index.json.tmp").exists());
+
+    // Whatever is wrong with the index, it's only a miss.
+    File::create(dir.join("index.json")).unwrap().write_all(b"{\"other\":").unwrap();
+    assert_eq!(store.get("other").unwrap(), None);
+}
+
+#[test]
+fn keys_the_cache_by_vary_and_credentials() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/*");
+        stub.send_header("Cache-Control", "max-age=60");
+        stub.send_header("Vary", "Accept-Language");
+        stub.send_body(r#"{"foo":"cached"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let mut client = Client::new();
+    client.cache(MemoryCacheStore::new());
+    let varied = format!("{}/varied", url);
+    for language in &["en", "es", "en", "es"] {
+        client.get(&varied).raw_header("Accept-Language", language).send().unwrap();
+    }
+    // Once for each language.
+    assert_eq!(server.requests().len(), 2);
+
+    let private = format!("{}/private", url);
+    for _ in 0..2 {
+        client.get(&private).header(Authorization("Token abc".to_string())).send().unwrap();
+    }
+    // Not public, so not kept.
+    assert_eq!(server.requests().len(), 4);
+}
+
+#[test]
+fn serves_stored_responses_when_offline() {
+    let now = time::SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap().as_secs() as i64;
+    let url = "http://127.0.0.1:1/offline";
+    let stored = CachedResponse {
+        status: 200,
+        headers: vec![("Cache-Control".to_string(),
+                       "max-age=60, stale-while-revalidate=30".to_string())],
+        body: br#"{"foo":"offline"}"#.to_vec(),
+        stored_at: now - 75,
+    };
+    assert_eq!(stored.freshness(), Freshness::StaleWhileRevalidate);
+    assert_eq!(CachedResponse { stored_at: now, ..stored.clone() }.freshness(),
+               Freshness::Fresh);
+    assert_eq!(CachedResponse { stored_at: 0, ..stored.clone() }.freshness(),
+               Freshness::Stale);
+
+    let store = MemoryCacheStore::new();
+    store.put(url, &CachedResponse { stored_at: 0, ..stored }).unwrap();
+    let mut client = Client::new();
+    client.cache(store);
+    let response: DummyJson = client.get(url)
+                                    .cache_policy(CachePolicy::OnlyIfCached)
+                                    .send()
+                                    .decode_success()
+                                    .unwrap();
+    assert_eq!(response, DummyJson { foo: "offline".to_string() });
+    let missing = client.get("http://127.0.0.1:1/missing")
+                        .cache_policy(CachePolicy::OnlyIfCached)
+                        .send()
+                        .unwrap();
+    assert_eq!(missing.status, curs::StatusCode::GatewayTimeout);
+
+    assert!(client.get(url).send().is_err());
+    client.cache_policy(CachePolicy::StaleIfError);
+    let response: DummyJson = client.get(url).send().decode_success().unwrap();
+    assert_eq!(response, DummyJson { foo: "offline".to_string() });
+}
+
+#[test]
+fn resumes_interrupted_downloads() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/big_file");
+        stub.got_header("range", "bytes=6-");
+        stub.got_header("if-range", r#""v1""#);
+        stub.send_status(curs::StatusCode::PartialContent);
+        stub.send_header("ETag", r#""v1""#);
+        stub.send_header("Content-Range", "bytes 6-10/11");
+        stub.send_body("world");
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let path = env::temp_dir().join("curs_test_download");
+    let etag_path = env::temp_dir().join("curs_test_download.etag");
+    File::create(&path).unwrap().write_all(b"hello ").unwrap();
+    File::create(&etag_path).unwrap().write_all(br#""v1""#).unwrap();
+
+    let length = Request::new(Method::Get, &format!("{}/big_file", url))
+                     .resume_download(&path)
+                     .unwrap();
+    assert_eq!(length, 11);
+    let mut contents = String::new();
+    File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "hello world");
+    let mut etag = String::new();
+    File::open(&etag_path).unwrap().read_to_string(&mut etag).unwrap();
+    assert_eq!(etag, r#""v1""#);
+}
+
+#[test]
+fn checks_unsatisfiable_ranges_against_the_local_file() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/changed_file", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        let mut requests = vec![];
+        let responses: [&[u8]; 2] =
+            [b"HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */3\r\n\
+               Content-Length: 0\r\nConnection: close\r\n\r\n",
+             b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\
+               Last-Modified: Wed, 21 Oct 2015 07:28:00 GMT\r\nConnection: close\r\n\r\nnew"];
+        for response in responses.iter() {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let read = stream.read(&mut request).unwrap();
+            requests.push(String::from_utf8_lossy(&request[..read]).to_lowercase());
+            stream.write_all(response).unwrap();
+        }
+        requests
+    });
+
+    let path = env::temp_dir().join("curs_test_changed_download");
+    let etag_path = env::temp_dir().join("curs_test_changed_download.etag");
+    File::create(&path).unwrap().write_all(b"old file").unwrap();
+    File::create(&etag_path).unwrap().write_all(br#""v1""#).unwrap();
+    assert_eq!(Request::new(Method::Get, &url).resume_download(&path).unwrap(), 3);
+    let mut contents = String::new();
+    File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "new");
+    let requests = server.join().unwrap();
+    assert!(requests[0].contains("range: bytes=8-"));
+    assert!(requests[0].contains("if-range: \"v1\""));
+    assert!(!requests[1].contains("range:"));
+
+    let server = StubServer::run(|stub| {
+        stub.got_path("/changed_file");
+        stub.got_header("range", "bytes=3-");
+        stub.got_header("if-range", "Wed, 21 Oct 2015 07:28:00 GMT");
+        stub.send_status(curs::StatusCode::RangeNotSatisfiable);
+        stub.send_header("Content-Range", "bytes */3");
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+    let complete = Request::new(Method::Get, &format!("{}/changed_file", url))
+                       .resume_download(&path)
+                       .unwrap();
+    assert_eq!(complete, 3);
+    server.assert_satisfied();
+}
+
+struct FormDecoder;
+
+impl Decoder for FormDecoder {
+    fn decode<T: serde::Deserialize>(&self, body: &[u8], _: &Headers) -> CursResult<T> {
+        let mut fields = std::collections::BTreeMap::new();
+        for pair in String::from_utf8_lossy(body).split('&') {
+            let mut parts = pair.splitn(2, '=');
+            if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                fields.insert(name.to_string(), curs::serde_json::Value::String(value.to_string()));
+            }
+        }
+        Ok(try!(curs::serde_json::from_value(curs::serde_json::Value::Object(fields))))
+    }
+}
+
+#[test]
+fn decodes_with_custom_decoders() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/form");
+        stub.send_body("foo=form");
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let response: DummyJson = Request::new(Method::Get, &format!("{}/form", url))
+                                  .send()
+                                  .decode_success_with(&FormDecoder)
+                                  .unwrap();
+    assert_eq!(response, DummyJson { foo: "form".to_string() });
+
+    let enveloped = r#"data:application/json,{"data":{"foo":"inside"}}"#;
+    let response: DummyJson = Request::new(Method::Get, enveloped)
+                                  .send()
+                                  .decode_success_with(&JsonField("data"))
+                                  .unwrap();
+    assert_eq!(response, DummyJson { foo: "inside".to_string() });
+    match Request::new(Method::Get, enveloped).send().decode_success_with::<DummyJson, _>(
+        &JsonField("missing")) {
+        Err(CursError::Decode(_)) => {}
+        _ => panic!("Expected a decode error"),
+    }
+}
+
+struct FormEncoder;
+
+impl BodyEncoder for FormEncoder {
+    fn content_type(&self) -> curs::hyper::mime::Mime {
+        "application/x-www-form-urlencoded".parse().unwrap()
+    }
+
+    fn encode<T: serde::Serialize>(&self, value: &T) -> CursResult<Vec<u8>> {
+        let fields = match curs::serde_json::to_value(value) {
+            curs::serde_json::Value::Object(fields) => fields,
+            _ => return Err(CursError::Config("Only objects make forms".to_string())),
+        };
+        let pairs: Vec<String> = fields.iter()
+                                       .map(|(name, value)| {
+                                           format!("{}={}", name, value.as_str().unwrap_or(""))
+                                       })
+                                       .collect();
+        Ok(pairs.join("&").into_bytes())
+    }
+}
+
+#[test]
+fn encodes_bodies_with_custom_encoders() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/encoded");
+        stub.got_method(Method::Post);
+        stub.got_header("content-type", "application/x-www-form-urlencoded");
+        stub.got_body("foo=encoded");
+        stub.send_body(r#"{"foo":"bar"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let body = DummyJson { foo: "encoded".to_string() };
+    let response: DummyJson = Request::new(Method::Post, &format!("{}/encoded", url))
+                                  .body_with(&FormEncoder, &body)
+                                  .unwrap()
+                                  .send()
+                                  .decode_success()
+                                  .unwrap();
+    assert_eq!(response, DummyJson { foo: "bar".to_string() });
+    assert!(Request::new(Method::Post, "http://example.com").body_with(&FormEncoder, &1).is_err());
+}
+
+#[test]
+fn sends_and_decodes_in_one_call() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/one_call");
+        stub.send_header("Content-Type", "application/json");
+        stub.send_body(r#"{"foo":"one call"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let response: DummyJson = Request::new(Method::Get, &format!("{}/one_call", url))
+                                  .send_and_decode()
+                                  .unwrap();
+    assert_eq!(response, DummyJson { foo: "one call".to_string() });
+    match Request::new(Method::Get, r#"data:text/html,{"foo":"html"}"#)
+              .send_and_decode::<DummyJson>() {
+        Err(CursError::Decode(_)) => {}
+        _ => panic!("Expected a decode error"),
+    }
+}
+
+#[test]
+fn memoizes_identical_requests() {
+    // Answers a single request, anything else would find nobody listening.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/memoized", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        drop(listener);
+        let mut request = [0; 1024];
+        stream.read(&mut request).unwrap();
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 16\r\nConnection: close\r\n\r\n\
+                           {\"foo\":\"memo\"}\r\n")
+              .unwrap();
+    });
+
+    let mut client = Client::new();
+    client.memoize(time::Duration::from_secs(60));
+    for _ in 0..3 {
+        let response: DummyJson = client.get(&url).send().decode_success().unwrap();
+        assert_eq!(response, DummyJson { foo: "memo".to_string() });
+    }
+    assert!(client.get(&url).params(vec![("other", "query")]).send().is_err());
+
+    let server = StubServer::run(|stub| {
+        stub.got_path("/orders");
+        stub.send_body(r#"{"foo":"ordered"}"#);
+    });
+    let orders = format!("{}/orders", server.url());
+    for _ in 0..2 {
+        client.request(Method::Post, &orders).params(vec![("amount", "10")]).send().unwrap();
+    }
+    // Each POST places an order.
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn memoizes_requests_per_user() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/me");
+        stub.send_body(r#"{"foo":"me"}"#);
+    });
+    let url = format!("{}/me", server.url());
+    let mut client = Client::new();
+    client.memoize(time::Duration::from_secs(60));
+    for user in &["alice", "bob", "alice"] {
+        client.get(&url)
+              .header(Authorization(format!("Bearer {}", user)))
+              .send()
+              .unwrap();
+    }
+    client.get(&url).send().unwrap();
+    let users: Vec<_> = server.requests()
+                              .iter()
+                              .map(|request| request.header("authorization").map(String::from))
+                              .collect();
+    assert_eq!(users,
+               vec![Some("Bearer alice".to_string()), Some("Bearer bob".to_string()), None]);
+}
+
+struct FixedResolver(std::net::IpAddr);
+
+impl Resolver for FixedResolver {
+    fn resolve(&self, _: &str) -> std::io::Result<Vec<std::net::IpAddr>> {
+        Ok(vec![self.0])
+    }
+}
+
+#[test]
+fn resolves_hosts_with_custom_resolvers() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/resolved");
+        stub.send_body(r#"{"foo":"resolved"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let port = url.rsplit(':').next().unwrap();
+    let mut client = Client::new();
+    client.resolver(FixedResolver("127.0.0.1".parse().unwrap()));
+    let response: DummyJson = client.get(&format!("http://nowhere.invalid:{}/resolved", port))
+                                    .send()
+                                    .decode_success()
+                                    .unwrap();
+    assert_eq!(response, DummyJson { foo: "resolved".to_string() });
+}
+
+#[test]
+fn reads_dns_over_https_answers() {
+    let query = dns::query_message("example.com", 1);
+    assert_eq!(query,
+               b"\x00\x00\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\
+                 \x07example\x03com\x00\x00\x01\x00\x01"
+                   .to_vec());
+
+    let mut answer = query.clone();
+    answer[2] = 0x81;
+    answer[3] = 0x80;
+    answer[7] = 2;
+    // A CNAME pointing back at the question's name, then the A record.
+    answer.extend(b"\xc0\x0c\x00\x05\x00\x01\x00\x00\x00\x3c\x00\x02\xc0\x0c");
+    answer.extend(b"\xc0\x0c\x00\x01\x00\x01\x00\x00\x00\x3c\x00\x04\x5d\xb8\xd8\x22");
+    assert_eq!(dns::answer_addresses(&answer).unwrap(),
+               vec!["93.184.216.34".parse::<std::net::IpAddr>().unwrap()]);
+
+    // The A lookup, then the AAAA one, twice over.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}/dns-query", listener.local_addr().unwrap());
+    let found = answer.clone();
+    let server = thread::spawn(move || {
+        for served in 0..4 {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            stream.read(&mut request).unwrap();
+            if served == 0 {
+                let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\
+                                    Connection: close\r\n\r\n",
+                                   found.len());
+                stream.write_all(head.as_bytes()).unwrap();
+                stream.write_all(&found).unwrap();
+            } else {
+                stream.write_all(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\
+                                   Connection: close\r\n\r\n")
+                      .unwrap();
+            }
+        }
+    });
+    let resolver = dns::DohResolver::new(&endpoint);
+    assert_eq!(resolver.resolve("example.com").unwrap(),
+               vec!["93.184.216.34".parse::<std::net::IpAddr>().unwrap()]);
+    assert!(resolver.resolve("example.com").is_err());
+    server.join().unwrap();
+
+    answer[3] = 0x83;
+    assert!(dns::answer_addresses(&answer).is_err());
+}
+
+#[test]
+fn fails_on_unexpected_statuses() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/expected");
+        stub.send_status(curs::StatusCode::Ok);
+        stub.send_body(r#"{"foo":"bar"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let expected_url = format!("{}/expected", url);
+    let mut request = Request::new(Method::Post, &expected_url);
+    request.expect_status(curs::StatusCode::Created);
+    match request.send() {
+        Err(CursError::UnexpectedStatus(response)) => {
+            assert_eq!(response.status, curs::StatusCode::Ok)
+        }
+        _ => panic!("Expected an unexpected status"),
+    }
+    request.expect_status(curs::StatusCode::Ok);
+    assert!(request.send().is_ok());
+}
+
+#[test]
+fn validates_every_response() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/validated");
+        stub.send_header("Content-Type", "text/html");
+        stub.send_body("<html></html>");
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+    let validated_url = format!("{}/validated", url);
+
+    let mut client = Client::new();
+    client.validator(|response: &curs::Response| {
+        if response.status == curs::StatusCode::Ok {
+            Ok(())
+        } else {
+            Err("Not OK".to_string())
+        }
+    });
+    assert!(client.get(&validated_url).send().is_ok());
+
+    client.validator(RejectHtml);
+    match client.get(&validated_url).send() {
+        Err(CursError::InvalidResponse(_, reason)) => assert!(reason.contains("HTML")),
+        _ => panic!("Expected an invalid response"),
+    }
+
+    let mut client = Client::new();
+    client.validator(RequireHeader("X-Request-Id".to_string()));
+    match client.get(&validated_url).send() {
+        Err(CursError::InvalidResponse(_, reason)) => {
+            assert_eq!(reason, "No X-Request-Id header in the response")
+        }
+        _ => panic!("Expected an invalid response"),
+    }
+}
+
+#[test]
+fn tells_time_with_the_given_clock() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/slow_to_recover");
+        stub.send_status(curs::StatusCode::ServiceUnavailable);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let start = time::Duration::from_secs(1_000_000_000);
+    let clock = Arc::new(ManualClock::new(start));
+    let mut client = Client::new();
+    client.clock(clock.clone());
+    client.retry_policy(ExponentialBackoff {
+        max_attempts: 3,
+        base: time::Duration::from_secs(10),
+        max: time::Duration::from_secs(60),
+        jitter: Jitter::None,
+    });
+    let started = time::Instant::now();
+    let response = client.get(&format!("{}/slow_to_recover", url)).send().unwrap();
+    assert_eq!(response.status, curs::StatusCode::ServiceUnavailable);
+    assert!(started.elapsed() < time::Duration::from_secs(5));
+    assert_eq!(clock.now(), start + time::Duration::from_secs(30));
+
+    let limiter = RateLimiter::new(0.5, 1).unwrap().with_clock(clock.clone());
+    limiter.wait("example.com");
+    limiter.wait("example.com");
+    assert_eq!(clock.now(), start + time::Duration::from_secs(32));
+
+    let token = Token {
+        access_token: "abc".into(),
+        refresh_token: None,
+        expires_at: Some(clock.unix_time() + 60),
+    };
+    assert!(!token.is_expired_at(clock.unix_time()));
+    clock.advance(time::Duration::from_secs(60));
+    assert!(token.is_expired_at(clock.unix_time()));
+}
+
+#[test]
+fn escapes_multipart_names_and_filenames() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/escaped_upload");
+        stub.got_body("name=\"evil%22%0D%0AX-Injected: yes\"\r\nvalue\r\n");
+        stub.got_body("name=\"file\"; filename=\"evil%22%0D%0AX-Injected: yes.txt\"\r\n");
+        stub.send_body(r#"{"foo":"escaped"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let path = env::temp_dir().join("evil\"\r\nX-Injected: yes.txt");
+    File::create(&path).unwrap().write_all(b"contents").unwrap();
+    let file = FileUpload {
+        name: "file".to_string(),
+        mime: None,
+        path: &path,
+    };
+    let response: DummyJson = Request::new(Method::Post, &format!("{}/escaped_upload", url))
+                                  .params(vec![("evil\"\r\nX-Injected: yes", "value")])
+                                  .files(vec![file])
+                                  .send()
+                                  .decode_success()
+                                  .unwrap();
+    assert_eq!(response, DummyJson { foo: "escaped".to_string() });
+}
+
+#[test]
+fn rejects_line_breaks_in_headers() {
+    let mut request = Request::new(Method::Get, "http://127.0.0.1:1/smuggled");
+    request.raw_header("X-Search", "shoes\r\nX-Injected: yes");
+    match request.send() {
+        Err(CursError::InvalidRequest(_)) => {}
+        _ => panic!("Expected invalid request"),
+    }
+
+    let mut request = Request::new(Method::Get, "http://127.0.0.1:1/smuggled");
+    request.raw_header("X-Injected: yes\r\nX-Search", "shoes");
+    match request.send() {
+        Err(CursError::InvalidRequest(_)) => {}
+        _ => panic!("Expected invalid request"),
+    }
+
+    let mut client = Client::new();
+    client.signer(|prepared| prepared.headers.set_raw("X-Signed", vec![b"a\r\nb: c".to_vec()]));
+    match client.get("http://127.0.0.1:1/smuggled").send() {
+        Err(CursError::InvalidInput(_)) => {}
+        _ => panic!("Expected invalid input"),
+    }
+}
+
+#[test]
+fn redacts_secrets_in_debug_output() {
+    let mut request = Request::new(Method::Post, "http://example.com/login");
+    request.header(Authorization("Bearer secret-token".to_string()))
+           .raw_header("X-Api-Key", "secret-key")
+           .params(vec![("user", "alice"), ("password", "secret-password")])
+           .redact("x-api-key")
+           .redact("password");
+    let debugged = format!("{:?}", request);
+    assert!(!debugged.contains("secret"));
+    assert!(debugged.contains("alice"));
+    assert!(debugged.contains("<redacted>"));
+
+    let server = StubServer::run(|stub| {
+        stub.got_path("/session");
+        stub.send_status(curs::StatusCode::InternalServerError);
+        stub.send_header("Set-Cookie", "session=secret-session");
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let error = Request::new(Method::Get, &format!("{}/session", url))
+                    .send()
+                    .decode_success::<DummyJson>()
+                    .unwrap_err();
+    let debugged = format!("{:?}", error);
+    assert!(debugged.contains("Status"));
+    assert!(!debugged.contains("secret-session"));
+}
+
+#[test]
+fn keeps_credentials_in_secrets() {
+    let secret = curs::secret::Secret::from("s3cret");
+    assert_eq!(&*secret, "s3cret");
+    assert_eq!(format!("{:?}", secret), "Secret(<redacted>)");
+
+    let mut bytes = b"s3cret".to_vec();
+    curs::secret::zero(&mut bytes);
+    assert_eq!(bytes, vec![0; 6]);
+}
+
+#[test]
+fn audits_every_request() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/transfers");
+        stub.got_method(Method::Post);
+        stub.send_status(curs::StatusCode::Created);
+        stub.send_body("ok");
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let records = Arc::new(Mutex::new(vec![]));
+    let recorded = records.clone();
+    let mut client = Client::new();
+    client.clock(Arc::new(ManualClock::new(time::Duration::from_secs(1_000_000_000))));
+    client.audit(move |record: &AuditRecord| recorded.lock().unwrap().push(record.clone()));
+    client.post(&format!("{}/transfers?token=secret", url))
+          .override_body("amount=10".to_string())
+          .audit_tag("user", "alice")
+          .send()
+          .unwrap();
+
+    let records = records.lock().unwrap();
+    assert_eq!(records.len(), 1);
+    let record = &records[0];
+    assert_eq!(record.method, "POST");
+    assert_eq!(record.host, "127.0.0.1");
+    assert_eq!(record.path, "/transfers");
+    assert_eq!(record.status, Some(201));
+    assert_eq!(record.request_bytes, 9);
+    assert_eq!(record.response_bytes, Some(2));
+    assert_eq!(record.tags, vec![("user".to_string(), "alice".to_string())]);
+    let json = record.to_json();
+    assert_eq!(json.find("started_at_ms").and_then(|t| t.as_u64()), Some(1_000_000_000_000));
+    assert_eq!(json.find("tags").and_then(|t| t.find("user")).and_then(|u| u.as_str()),
+               Some("alice"));
+}
+
+#[test]
+fn replays_har_entries() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/orders*");
+        stub.got_method(Method::Post);
+        stub.got_header("x-request-id", "abc");
+        stub.got_body(r#"{"amount":10}"#);
+        stub.send_status(curs::StatusCode::Created);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let har = br#"{"log": {"entries": [{"request": {
+        "method": "POST",
+        "url": "https://production.example.com/orders?id=1",
+        "headers": [
+            {"name": ":authority", "value": "production.example.com"},
+            {"name": "Host", "value": "production.example.com"},
+            {"name": "X-Request-Id", "value": "abc"}
+        ],
+        "postData": {"mimeType": "application/json", "text": "{\"amount\":10}"}
+    }}]}}"#;
+    let entries = har::parse(har).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].headers, vec![("X-Request-Id".to_string(), "abc".to_string())]);
+
+    let client = Client::new();
+    let base = curs::hyper::Url::parse(&url).unwrap();
+    let responses = har::replay(&client, &entries, Some(&base));
+    assert_eq!(responses[0].as_ref().unwrap().status, curs::StatusCode::Created);
+}
+
+#[test]
+fn renders_canonical_requests() {
+    let mut request = Request::new(Method::Get, "http://example.com/search?b=2");
+    request.params(vec![("a", "1")])
+           .raw_header("X-Api-Key", "key")
+           .header(UserAgent("curs".to_string()));
+    assert_eq!(request.to_canonical_string().unwrap(),
+               "GET http://example.com/search?b=2&a=1\nuser-agent: curs\nx-api-key: key\n\n");
+
+    let path = env::temp_dir().join("curs_canonical.txt");
+    File::create(&path).unwrap().write_all(b"contents").unwrap();
+    let file = FileUpload {
+        name: "file".to_string(),
+        mime: Some("text/plain".parse().unwrap()),
+        path: &path,
+    };
+    let mut upload = Request::new(Method::Post, "http://example.com/upload");
+    upload.params(vec![("note", "hi")]).files(vec![file]);
+    let canonical = upload.to_canonical_string().unwrap();
+    assert_eq!(canonical, upload.to_canonical_string().unwrap());
+    assert!(canonical.starts_with("POST http://example.com/upload\n\
+                                   content-type: multipart/form-data; \
+                                   boundary=curs-canonical-boundary\n\n"));
+    assert!(canonical.contains("name=\"note\"\r\nhi\r\n"));
+    assert!(canonical.ends_with("contents\r\n\r\n\r\n--curs-canonical-boundary--"));
+
+    let signer = WebhookSigner::new(b"secret");
+    let mut signed = Request::new(Method::Post, "http://example.com/hooks");
+    signed.json(DummyJson { foo: "paid".to_string() }).sign_webhook(&signer);
+    assert_eq!(signed.to_canonical_string().unwrap(),
+               "POST http://example.com/hooks\ncontent-type: application/json\n\n\
+                {\"foo\":\"paid\"}");
+}
+
+#[test]
+fn prepares_requests_without_sending_them() {
+    let mut client = Client::new();
+    client.base_url(curs::hyper::Url::parse("http://127.0.0.1:1/api/").unwrap());
+    let mut request = client.post("orders");
+    request.params(vec![("market", "btc")]);
+    let prepared = request.prepare().unwrap();
+    assert_eq!(prepared.method, Method::Post);
+    assert_eq!(prepared.url, "http://127.0.0.1:1/api/orders");
+    assert_eq!(prepared.headers.get::<ContentType>().map(|c| c.to_string()),
+               Some("application/x-www-form-urlencoded".to_string()));
+    assert_eq!(prepared.body.as_ref().map(|b| b.to_vec()), Some(b"market=btc".to_vec()));
+
+    assert_eq!(prepared.url(), "http://127.0.0.1:1/api/orders");
+    assert_eq!(prepared.header("content-type"),
+               Some("application/x-www-form-urlencoded".to_string()));
+    assert_eq!(prepared.body(), Some(&b"market=btc"[..]));
+    assert_eq!(prepared.body_len(), 10);
+}
+
+#[test]
+fn collects_every_problem_before_sending() {
+    let missing = env::temp_dir().join("curs_missing_upload.txt");
+    let file = FileUpload {
+        name: "file".to_string(),
+        mime: None,
+        path: &missing,
+    };
+    let mut request = Request::new(Method::Post, "http://[::1/upload");
+    request.raw_header("X-Bad\nName", "value")
+           .raw_header("X-Fine", "value")
+           .files(vec![file]);
+    assert_eq!(request.problems().len(), 3);
+    match request.send() {
+        Err(CursError::InvalidRequest(problems)) => assert_eq!(problems.len(), 3),
+        _ => panic!("Expected invalid request"),
+    }
+
+    assert!(Request::new(Method::Get, "/relative/to/the/base").problems().is_empty());
+}
+
+#[test]
+fn keeps_bodies_of_buffered_responses() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/broken");
+        stub.send_status(curs::StatusCode::UnprocessableEntity);
+        stub.send_body("not json");
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let mut response = Request::new(Method::Get, &format!("{}/broken", url))
+                           .send_buffered()
+                           .unwrap();
+    assert_eq!(response.status(), curs::StatusCode::UnprocessableEntity);
+    assert!(response.decode::<DummyJson>().is_err());
+    assert_eq!(response.text().unwrap(), "not json");
+    let mut streamed = String::new();
+    response.into_reader().read_to_string(&mut streamed).unwrap();
+    assert_eq!(streamed, "not json");
+}
+
+#[test]
+fn fails_on_changed_preconditions() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/accounts/1");
+        stub.got_method(Method::Put);
+        stub.got_header("if-match", r#""v1""#);
+        stub.send_status(curs::StatusCode::PreconditionFailed);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let read: Cached<DummyJson> = Cached::Fresh {
+        body: DummyJson { foo: "account".to_string() },
+        etag: Some("\"v1\"".to_string()),
+        last_modified: None,
+    };
+    let result = Request::new(Method::Put, &format!("{}/accounts/1", url))
+                     .if_match(read.etag().unwrap())
+                     .json(&DummyJson { foo: "changed".to_string() })
+                     .send();
+    match result {
+        Err(CursError::PreconditionFailed(response)) => {
+            assert_eq!(response.status, curs::StatusCode::PreconditionFailed)
+        }
+        _ => panic!("Expected a failed precondition"),
+    }
+}
+
+#[test]
+fn sets_cache_control_directives() {
+    let now = time::SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap().as_secs() as i64;
+    let url = "http://127.0.0.1:1/rates";
+    let store = MemoryCacheStore::new();
+    store.put(url,
+             &CachedResponse {
+                 status: 200,
+                 headers: vec![("Cache-Control".to_string(), "max-age=60".to_string())],
+                 body: br#"{"foo":"rates"}"#.to_vec(),
+                 stored_at: now - 30,
+             })
+         .unwrap();
+    let mut client = Client::new();
+    client.cache(store);
+
+    let mut request = client.get(url);
+    request.no_cache().max_age(time::Duration::from_secs(10));
+    assert_eq!(request.prepare().unwrap().header("Cache-Control"),
+               Some("no-cache, max-age=10".to_string()));
+
+    assert!(client.get(url).send().is_ok());
+    assert!(client.get(url).only_if_cached().max_age(time::Duration::from_secs(10)).send().is_ok());
+    assert!(client.get(url).max_age(time::Duration::from_secs(10)).send().is_err());
+    assert!(client.get(url).no_cache().send().is_err());
+    let missing = client.get("http://127.0.0.1:1/missing").only_if_cached().send().unwrap();
+    assert_eq!(missing.status, curs::StatusCode::GatewayTimeout);
+}
+
+#[test]
+fn collects_all_pages_up_to_a_limit() {
+    let page = |cursor: &str, next: &str| {
+        let body = format!(r#"{{"data":[{{"foo":"{}"}}],"next":{}}}"#, cursor, next);
+        curs::hyper::Url::parse(&format!("data:application/json,{}", body)).unwrap()
+    };
+    let first_page = page("first", r#""second""#).serialize();
+    let items: Vec<DummyJson> =
+        Request::new(Method::Get, &first_page)
+            .paginate_with(|_, body| body.find("next").and_then(|n| n.as_str()).map(String::from),
+                           |_, cursor| page(cursor, "null"))
+            .items_field("data")
+            .collect_all(10)
+            .unwrap();
+    assert_eq!(items.len(), 2);
+
+    let endless: CursResult<Vec<DummyJson>> =
+        Request::new(Method::Get, &first_page)
+            .paginate_with(|_, _| Some("again".to_string()), |_, cursor| page(cursor, "null"))
+            .items_field("data")
+            .collect_all(3);
+    match endless {
+        Err(CursError::TooManyPages(3)) => {}
+        _ => panic!("Expected too many pages"),
+    }
+}
+
+#[test]
+fn keeps_pages_fetched_before_an_error() {
+    let first_page = concat!(r#"data:application/json,"#,
+                             r#"{"data":[{"foo":"first"}],"next":"http://127.0.0.1:1/2"}"#);
+    let next = |_: &Headers, body: &curs::serde_json::Value| {
+        body.find("next").and_then(|n| n.as_str()).map(String::from)
+    };
+    let mut pages = Request::new(Method::Get, first_page)
+                        .paginate_with(next, |_, next| curs::hyper::Url::parse(next).unwrap())
+                        .items_field("data")
+                        .by_page();
+    let first: pagination::Page<DummyJson> = pages.next().unwrap().unwrap();
+    assert_eq!(first.items, vec![DummyJson { foo: "first".to_string() }]);
+    assert_eq!(first.next.map(|n| n.serialize()), Some("http://127.0.0.1:1/2".to_string()));
+    assert!(pages.next().unwrap().is_err());
+    assert!(pages.next().is_none());
+    assert_eq!(pages.next_url(), Some("http://127.0.0.1:1/2".to_string()));
+    pages.retry();
+    assert!(pages.next().unwrap().is_err());
+}
+
+#[test]
+fn resumes_dropped_downloads_right_away() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/flaky_file", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0; 1024];
+        stream.read(&mut request).unwrap();
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 11\r\nETag: \"v1\"\r\n\
+                           Connection: close\r\n\r\nhello")
+              .unwrap();
+        drop(stream);
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0; 1024];
+        let read = stream.read(&mut request).unwrap();
+        let request = String::from_utf8_lossy(&request[..read]).to_lowercase();
+        stream.write_all(b"HTTP/1.1 206 Partial Content\r\nContent-Length: 6\r\nETag: \"v1\"\r\n\
+                           Content-Range: bytes 5-10/11\r\nConnection: close\r\n\r\n world")
+              .unwrap();
+        request
+    });
+
+    let path = env::temp_dir().join("curs_test_flaky_download");
+    let _ = std::fs::remove_file(&path);
+    let length = Request::new(Method::Get, &url).download_with_resume(&path, 3).unwrap();
+    assert_eq!(length, 11);
+    let mut contents = String::new();
+    File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "hello world");
+    let resumed = server.join().unwrap();
+    assert!(resumed.contains("range: bytes=5-"));
+    assert!(resumed.contains("if-range: \"v1\""));
+}
+
+#[test]
+fn uploads_from_pipes() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/piped");
+        stub.got_body("name=\"log\"; filename=\"build.log\"\r\nContent-Type: text/plain");
+        stub.got_body("\r\n\r\nline one\nline two\r\n");
+        stub.send_body(r#"{"foo":"piped"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let pipe = std::io::Cursor::new(b"line one\nline two".to_vec());
+    let upload = BytesUpload::from_reader("log", "build.log", None, pipe).unwrap();
+    let response: DummyJson = Request::new(Method::Post, &format!("{}/piped", url))
+                                  .bytes_uploads(vec![upload])
+                                  .send()
+                                  .decode_success()
+                                  .unwrap();
+    assert_eq!(response, DummyJson { foo: "piped".to_string() });
+
+    let mut request = Request::new(Method::Post, "http://example.com/raw");
+    request.body_from_reader(std::io::Cursor::new(b"raw".to_vec())).unwrap();
+    assert_eq!(request.prepare().unwrap().body(), Some(&b"raw"[..]));
+}
+
+#[test]
+fn uploads_directories_as_tar_archives() {
+    let dir = env::temp_dir().join("curs_tar_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("assets")).unwrap();
+    File::create(dir.join("index.html")).unwrap().write_all(b"<h1>hi</h1>").unwrap();
+    File::create(dir.join("assets").join("app.js")).unwrap().write_all(b"run()").unwrap();
+
+    let mut opened = TarDirectory::new(&dir).open().unwrap();
+    let mut archive = vec![];
+    opened.reader.read_to_end(&mut archive).unwrap();
+    // assets/, assets/app.js and index.html with a block of contents each, and the end.
+    assert_eq!(archive.len(), 512 * 7);
+    assert_eq!(opened.length, Some(archive.len() as u64));
+    assert_eq!(&archive[..7], b"assets/");
+    assert_eq!(&archive[257..262], b"ustar");
+    assert_eq!(&archive[512..525], b"assets/app.js");
+    assert_eq!(&archive[1024..1029], b"run()");
+    assert_eq!(&archive[1536..1546], b"index.html");
+
+    let server = StubServer::run(|stub| {
+        stub.got_path("/deploys");
+        stub.got_header("content-type", "application/x-tar");
+        stub.got_header("content-length", "3584");
+        stub.send_body(r#"{"foo":"deployed"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let response: DummyJson = Request::new(Method::Post, &format!("{}/deploys", url))
+                                  .tar_directory(&dir)
+                                  .send()
+                                  .decode_success()
+                                  .unwrap();
+    assert_eq!(response, DummyJson { foo: "deployed".to_string() });
+}
+
+#[test]
+fn streams_multipart_bodies_with_a_length() {
+    let path = env::temp_dir().join("curs_streamed_upload.txt");
+    File::create(&path).unwrap().write_all(b"streamed contents").unwrap();
+    let upload = |stream: bool| {
+        let file = FileUpload {
+            name: "file".to_string(),
+            mime: None,
+            path: &path,
+        };
+        let mut request = Request::new(Method::Post, "http://example.com/upload");
+        request.params(vec![("note", "hi")]).files(vec![file]);
+        if stream {
+            request.stream_multipart();
+        }
+        request.to_canonical_string().unwrap()
+    };
+    assert_eq!(upload(true), upload(false));
+
+    let server = StubServer::run(|stub| {
+        stub.got_path("/streamed");
+        stub.got_header("content-length", "");
+        stub.got_body("filename=\"curs_streamed_upload.txt\"\r\nContent-Type: text/plain");
+        stub.got_body("streamed contents");
+        stub.send_body(r#"{"foo":"streamed"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let file = FileUpload {
+        name: "file".to_string(),
+        mime: None,
+        path: &path,
+    };
+    let mut request = Request::new(Method::Post, &format!("{}/streamed", url));
+    request.files(vec![file]).stream_multipart();
+    let prepared = request.prepare().unwrap();
+    assert!(prepared.body.is_none());
+    let mut opened = prepared.body_source.unwrap().open().unwrap();
+    let mut body = vec![];
+    opened.reader.read_to_end(&mut body).unwrap();
+    assert_eq!(opened.length, Some(body.len() as u64));
+    let response: DummyJson = request.send().decode_success().unwrap();
+    assert_eq!(response, DummyJson { foo: "streamed".to_string() });
+}
+
+#[test]
+fn reports_progress_per_part() {
+    let path = env::temp_dir().join("curs_progress_upload.txt");
+    File::create(&path).unwrap().write_all(b"0123456789").unwrap();
+    let file = FileUpload {
+        name: "first".to_string(),
+        mime: None,
+        path: &path,
+    };
+    let upload = BytesUpload::from_reader("second", "b.txt", None, &b"abcd"[..]).unwrap();
+    let events = Arc::new(Mutex::new(vec![]));
+    let seen = events.clone();
+    let mut request = Request::new(Method::Post, "http://example.com/upload");
+    request.params(vec![("note", "hi")])
+           .files(vec![file])
+           .bytes_uploads(vec![upload])
+           .upload_progress(move |progress: &PartProgress| {
+               seen.lock().unwrap().push(progress.clone())
+           });
+    let prepared = request.prepare().unwrap();
+    let mut opened = prepared.body_source.unwrap().open().unwrap();
+    let mut body = vec![];
+    opened.reader.read_to_end(&mut body).unwrap();
+
+    let events = events.lock().unwrap();
+    let done: Vec<(usize, &str, Option<&str>, u64)> =
+        events.iter()
+              .filter(|event| event.is_done())
+              .map(|event| {
+                  (event.index, &*event.name, event.filename.as_ref().map(|f| &**f), event.total)
+              })
+              .collect();
+    assert_eq!(done,
+               vec![(0, "note", None, 2),
+                    (1, "first", Some("curs_progress_upload.txt"), 10),
+                    (2, "second", Some("b.txt"), 4)]);
+    assert!(events.iter().all(|event| event.percent() <= 100.0));
+    let last = events.last().unwrap();
+    assert_eq!(last.body_total, body.len() as u64);
+}
+
+#[test]
+fn sends_many_uploads_concurrently() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/media");
+        stub.got_header("X-Tenant", "acme");
+        stub.got_body(r#"name="clip"; filename="curs_media_clip.txt""#);
+        stub.got_body("clip contents");
+        stub.send_status(curs::StatusCode::Created);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let path = env::temp_dir().join("curs_media_clip.txt");
+    File::create(&path).unwrap().write_all(b"clip contents").unwrap();
+    let missing = env::temp_dir().join("curs_media_missing.txt");
+    let _ = std::fs::remove_file(&missing);
+    let media = format!("{}/media", url);
+    let mut client = Client::new();
+    client.signer(|request| request.headers.set_raw("X-Tenant", vec![b"acme".to_vec()]));
+    let results = uploads::send_many(&client,
+                                     vec![Upload::new(&media, "clip", &missing),
+                                          Upload::new(&media, "clip", &path)],
+                                     2)
+                      .unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results[0].is_err());
+    assert_eq!(results[1].as_ref().unwrap().status, curs::StatusCode::Created);
+}
+
+struct Countdown(Vec<&'static str>);
+
+impl BodyProducer for Countdown {
+    fn next_chunk(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        Ok(self.0.pop().map(|chunk| chunk.as_bytes().to_vec()))
+    }
+}
+
+#[test]
+fn sends_bodies_from_producers() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/events");
+        stub.got_header("transfer-encoding", "chunked");
+        stub.got_body("3,2,1");
+        stub.send_body(r#"{"foo":"produced"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let started = Arc::new(Mutex::new(0));
+    let counter = started.clone();
+    let response: DummyJson = Request::new(Method::Post, &format!("{}/events", url))
+                                  .body_producer(move || {
+                                      *counter.lock().unwrap() += 1;
+                                      Countdown(vec!["1", "", "2,", "3,"])
+                                  })
+                                  .send()
+                                  .decode_success()
+                                  .unwrap();
+    assert_eq!(response, DummyJson { foo: "produced".to_string() });
+    assert_eq!(*started.lock().unwrap(), 1);
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn maps_files_into_memory() {
+    let path = env::temp_dir().join("curs_mapped_upload.txt");
+    File::create(&path).unwrap().write_all(b"mapped contents").unwrap();
+
+    let mut raw = Request::new(Method::Post, "http://example.com/raw");
+    raw.mmap_body(&path);
+    let mut opened = raw.prepare().unwrap().body_source.unwrap().open().unwrap();
+    let mut body = vec![];
+    opened.reader.read_to_end(&mut body).unwrap();
+    assert_eq!(body, b"mapped contents");
+    assert_eq!(opened.length, Some(15));
+
+    let upload = |mmap: bool| {
+        let file = FileUpload {
+            name: "file".to_string(),
+            mime: None,
+            path: &path,
+        };
+        let mut request = Request::new(Method::Post, "http://example.com/upload");
+        request.files(vec![file]);
+        if mmap {
+            request.mmap_files();
+        }
+        request.to_canonical_string().unwrap()
+    };
+    assert_eq!(upload(true), upload(false));
+}
+
+#[test]
+fn sends_borrowed_bytes_bodies() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/bytes");
+        stub.got_body("borrowed bytes");
+        stub.send_body(r#"{"foo":"bytes"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let body = b"borrowed bytes".to_vec();
+    let response: DummyJson = Request::new(Method::Post, &format!("{}/bytes", url))
+                                  .bytes_body(&body[..])
+                                  .send()
+                                  .decode_success()
+                                  .unwrap();
+    assert_eq!(response, DummyJson { foo: "bytes".to_string() });
+
+    let mut owned = Request::new(Method::Put, "http://example.com/bytes");
+    owned.bytes_body(b"owned".to_vec());
+    assert_eq!(owned.prepare().unwrap().body(), Some(&b"owned"[..]));
+}
+
+#[test]
+fn spools_big_bodies_to_disk() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/export");
+        stub.send_body(r#"{"foo":"a big export"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let mut response = Request::new(Method::Get, &format!("{}/export", url))
+                           .send_spooled(8)
+                           .unwrap();
+    assert_eq!(response.status, curs::StatusCode::Ok);
+    assert!(response.body.is_on_disk());
+    assert_eq!(response.body.len(), 22);
+    let decoded: DummyJson = response.body.decode().unwrap();
+    assert_eq!(decoded, DummyJson { foo: "a big export".to_string() });
+    let decoded_again: DummyJson = response.body.decode().unwrap();
+    assert_eq!(decoded, decoded_again);
+
+    let small = SpooledBody::read_from(&b"tiny"[..], 8).unwrap();
+    assert!(!small.is_on_disk());
+    assert_eq!(small.len(), 4);
+}
+
+#[test]
+fn benchmarks_requests() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/health", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        for served in 0..4 {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            stream.read(&mut request).unwrap();
+            let response: &[u8] = if served == 3 {
+                b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\
+                  Connection: close\r\n\r\n"
+            } else {
+                b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok"
+            };
+            stream.write_all(response).unwrap();
+        }
+    });
+
+    let benchmark = Request::new(Method::Get, &url).benchmark(4, 2).unwrap();
+    server.join().unwrap();
+    assert_eq!(benchmark.requests, 4);
+    assert_eq!(benchmark.errors, 0);
+    assert_eq!(benchmark.statuses.get(&200), Some(&3));
+    assert_eq!(benchmark.statuses.get(&503), Some(&1));
+    assert!(benchmark.min <= benchmark.p50 && benchmark.p50 <= benchmark.p99);
+    assert!(benchmark.p99 <= benchmark.max);
+    assert!(benchmark.throughput() > 0.0);
+}
+
+#[test]
+fn generates_load_at_a_rate() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/orders", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        let mut signed = 0;
+        for _ in 0..4 {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let read = stream.read(&mut request).unwrap();
+            if String::from_utf8_lossy(&request[..read]).contains("X-Signed: yes") {
+                signed += 1;
+            }
+            stream.write_all(b"HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\
+                               Connection: close\r\n\r\n")
+                  .unwrap();
+        }
+        signed
+    });
+
+    let plan = LoadPlan {
+        rate: 20,
+        duration: time::Duration::from_millis(200),
+        concurrency: 2,
+    };
+    let mut seen = vec![];
+    let mut client = Client::new();
+    client.signer(|prepared| prepared.headers.set_raw("X-Signed", vec![b"yes".to_vec()]));
+    let request = client.post(&url);
+    let summary = loadgen::run(&request, &plan, |result| {
+                      seen.push((result.sequence, result.scheduled, result.status))
+                  });
+    let summary = summary.unwrap();
+    assert_eq!(server.join().unwrap(), 4);
+    seen.sort_by_key(|&(sequence, _, _)| sequence);
+    assert_eq!(seen.iter().map(|&(sequence, _, _)| sequence).collect::<Vec<_>>(),
+               vec![0, 1, 2, 3]);
+    assert_eq!(seen[3].1, time::Duration::from_millis(150));
+    assert!(seen.iter().all(|&(_, _, status)| status == Some(201)));
+    assert_eq!(summary.requests, 4);
+    assert!(summary.elapsed >= time::Duration::from_millis(150));
+
+    let endless = LoadPlan {
+        rate: u32::max_value(),
+        duration: time::Duration::from_secs(u64::max_value()),
+        concurrency: 1,
+    };
+    match loadgen::run(&request, &endless, |_| {}) {
+        Err(CursError::Config(_)) => {}
+        _ => panic!("Expected too many requests"),
+    }
+}
+
+#[test]
+fn stubs_servers_for_tests() {
+    let server = StubServer::run(|stub| {
+        stub.got_method(Method::Put)
+            .got_path("/items/*")
+            .got_header("Content-Type", "application/json")
+            .got_body(r#""foo":"stubbed""#)
+            .send_status(curs::StatusCode::Accepted)
+            .send_header("X-Request-Id", "42")
+            .send_body(r#"{"foo":"accepted"}"#);
+    });
+    let url = format!("{}/items/1", server.url());
+    let response: DummyJson = Request::new(Method::Put, &url)
+                                  .json(DummyJson { foo: "stubbed".to_string() })
+                                  .send()
+                                  .decode_success()
+                                  .unwrap();
+    assert_eq!(response, DummyJson { foo: "accepted".to_string() });
+    server.assert_satisfied();
+    assert_eq!(server.requests()[0].path, "/items/1");
+
+    let wrong = Request::new(Method::Get, &url).send().unwrap();
+    assert_eq!(wrong.status, curs::StatusCode::InternalServerError);
+}
+
+// What openapi::generate makes of fixtures/petstore.json, compiled as it is.
+mod petstore {
+    include!("fixtures/petstore.rs");
+}
+
+#[test]
+fn generates_clients_from_openapi_specs() {
+    let code = openapi::generate(include_str!("fixtures/petstore.json")).unwrap();
+    assert_eq!(code, include_str!("fixtures/petstore.rs"));
+    assert_eq!(openapi::path_segment("a b/c"), "a%20b%2Fc");
+    assert_eq!(openapi::with_query("pets", &[("a", Some("1")), ("b", None)]), "pets?a=1");
+
+    let server = StubServer::run(|stub| {
+        stub.got_path("/pets/rex*");
+        stub.send_body(r#"{"name":"Rex","tags":["good"]}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let mut client = Client::new();
+    client.base_url(curs::hyper::Url::parse(&format!("{}/", url)).unwrap());
+    let pet = petstore::get_pet(&client, "rex", "name").unwrap();
+    assert_eq!(pet,
+               petstore::Pet {
+                   birth_date: None,
+                   name: "Rex".to_string(),
+                   tags: Some(vec!["good".to_string()]),
+               });
+    petstore::update_pet(&client, "rex", None, &pet).unwrap();
+    let requests = server.requests();
+    assert_eq!(requests[0].path, "/pets/rex?fields=name");
+    assert_eq!(requests[1].method, Method::Put);
+    assert_eq!(requests[1].path, "/pets/rex");
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+struct User {
+    id: Option<u64>,
+    name: String,
+}
+resource!(User, "users", id);
+
+#[test]
+fn maps_resources_to_crud_endpoints() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/api/users/7");
+        stub.got_method(Method::Get);
+        stub.send_body(r#"{"id":7,"name":"Ann"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let mut client = Client::new();
+    client.base_url(curs::hyper::Url::parse(&format!("{}/api/", url)).unwrap());
+    let user = User::find(&client, "7").unwrap();
+    assert_eq!(user, User { id: Some(7), name: "Ann".to_string() });
+    assert_eq!(user.id(), Some("7".to_string()));
+
+    let unsaved = User { id: None, name: "Bob".to_string() };
+    match unsaved.update(&client) {
+        Err(CursError::Config(_)) => {}
+        other => panic!("Expected a Config error, got {:?}", other.map(|_| ())),
+    }
+}
+
+struct RenameItem;
+
+impl Endpoint for RenameItem {
+    type Input = (u64, String);
+    type Output = DummyJson;
+
+    fn method() -> Method {
+        Method::Patch
+    }
+
+    fn path(input: &(u64, String)) -> String {
+        format!("items/{}", input.0)
+    }
+
+    fn query(_: &(u64, String)) -> Vec<(String, String)> {
+        vec![("notify".to_string(), "false".to_string())]
+    }
+
+    fn body(input: &(u64, String)) -> Option<curs::serde_json::Value> {
+        Some(curs::serde_json::to_value(DummyJson { foo: input.1.clone() }))
+    }
+}
+
+#[test]
+fn executes_declared_endpoints() {
+    assert_eq!(endpoint::path_and_query::<RenameItem>(&(3, "x".to_string())),
+               "items/3?notify=false");
+
+    let server = StubServer::run(|stub| {
+        stub.got_path("/items/3?notify=false");
+        stub.got_method(Method::Patch);
+        stub.got_body(r#"{"foo":"renamed"}"#);
+        stub.send_body(r#"{"foo":"renamed"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let mut client = Client::new();
+    client.base_url(curs::hyper::Url::parse(&format!("{}/", url)).unwrap());
+    let renamed = client.execute::<RenameItem>((3, "renamed".to_string())).unwrap();
+    assert_eq!(renamed, DummyJson { foo: "renamed".to_string() });
+}
+
+#[test]
+fn composes_calls() {
+    let attempts = Mutex::new(vec![]);
+    let flaky = call_fn(|request: Request| {
+        let mut attempts = attempts.lock().unwrap();
+        attempts.push(request.prepare().unwrap().header("X-Layer"));
+        if attempts.len() < 3 {
+            let refused = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+            return Err(CursError::Network(curs::hyper::error::Error::Io(refused)));
+        }
+        Request::new(Method::Get, "data:,done").send()
+    });
+    let layered = Retry::new(MapRequest::new(&flaky, |request: &mut Request| {
+                                 request.raw_header("X-Layer", "mapped");
+                             }),
+                             3);
+    let mut response = layered.call(Request::new(Method::Get, "http://example.com/jobs"))
+                              .unwrap();
+    let mut body = String::new();
+    response.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "done");
+    assert_eq!(*attempts.lock().unwrap(), vec![Some("mapped".to_string()); 3]);
+
+    attempts.lock().unwrap().clear();
+    assert!(layered.call(Request::new(Method::Post, "http://example.com/jobs")).is_err());
+    assert_eq!(attempts.lock().unwrap().len(), 1);
+    attempts.lock().unwrap().clear();
+    let mut keyed = Request::new(Method::Post, "http://example.com/jobs");
+    keyed.idempotent();
+    assert!(layered.call(keyed).is_ok());
+    assert_eq!(attempts.lock().unwrap().len(), 3);
+    attempts.lock().unwrap().clear();
+    let mut opted_in = Request::new(Method::Post, "http://example.com/jobs");
+    opted_in.retry_non_idempotent();
+    assert!(layered.call(opted_in).is_ok());
+    assert_eq!(attempts.lock().unwrap().len(), 3);
+
+    let server = StubServer::run(|stub| {
+        stub.got_path("/jobs");
+        stub.send_body(r#"{"foo":"called"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let mut client = Client::new();
+    client.base_url(curs::hyper::Url::parse(&url).unwrap());
+    let response: DummyJson = client.call(Request::new(Method::Get, "/jobs"))
+                                    .decode_success()
+                                    .unwrap();
+    assert_eq!(response, DummyJson { foo: "called".to_string() });
+}
+
+#[test]
+fn transforms_bodies_both_ways() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/enveloped");
+        stub.got_body(r#"{"data":{"foo":"this"}}"#);
+        stub.send_header("Content-Type", "application/json");
+        stub.send_body(r#"{"data":{"foo":"that"}}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let mut client = Client::new();
+    client.body_transformer(Envelope("data".to_string()));
+    let response: DummyJson = client.post(&format!("{}/enveloped", url))
+                                    .json(DummyJson { foo: "this".to_string() })
+                                    .send()
+                                    .decode_success()
+                                    .unwrap();
+    assert_eq!(response, DummyJson { foo: "that".to_string() });
+}
+
+#[test]
+fn signs_webhooks_after_transforming_them() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/hooks");
+        stub.send_body(r#"{"foo":"signed"}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let signer = WebhookSigner::new(b"whsec");
+    let mut client = Client::new();
+    client.body_transformer(Envelope("data".to_string()));
+    client.post(&format!("{}/hooks", url))
+          .json(DummyJson { foo: "event".to_string() })
+          .sign_webhook(&signer)
+          .send()
+          .unwrap();
+
+    // Signed as it arrived, envelope and all.
+    let received = &server.requests()[0];
+    assert_eq!(received.body, br#"{"data":{"foo":"event"}}"#.to_vec());
+    let signature = received.header("X-Webhook-Signature").unwrap();
+    let timestamp = signature[2..signature.find(',').unwrap()].parse().unwrap();
+    assert_eq!(signature, signer.signature(timestamp, &received.body));
+}
+
+#[test]
+fn unwraps_json_envelopes() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/wrapped");
+        stub.send_header("Content-Type", "application/json");
+        stub.send_body(r#"{"data":{"foo":"inner"},"meta":{"foo":"page 1"}}"#);
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+    let wrapped_url = format!("{}/wrapped", url);
+
+    let response: DummyJson = Request::new(Method::Get, &wrapped_url)
+                                  .unwrap_field("data")
+                                  .send()
+                                  .decode_success()
+                                  .unwrap();
+    assert_eq!(response, DummyJson { foo: "inner".to_string() });
+
+    let mut client = Client::new();
+    client.unwrap_field("data");
+    let response: DummyJson = client.get(&wrapped_url).send().decode_success().unwrap();
+    assert_eq!(response, DummyJson { foo: "inner".to_string() });
+
+    let envelope = JsonEnvelope("data", "meta");
+    let (data, meta): (DummyJson, Option<DummyJson>) =
+        Request::new(Method::Get, &wrapped_url).send().decode_success_with(&envelope).unwrap();
+    assert_eq!(data, DummyJson { foo: "inner".to_string() });
+    assert_eq!(meta, Some(DummyJson { foo: "page 1".to_string() }));
+}
+
+#[test]
+fn tells_where_json_bodies_went_wrong() {
+    let drifted = r#"data:application/json,{"id":7,%0A"foo":12}"#;
+    match Request::new(Method::Get, drifted).send().decode_success::<DummyJson>() {
+        Err(CursError::JsonBody(error)) => {
+            assert_eq!(error.line, 2);
+            assert!(error.snippet.contains(r#""foo":12"#));
+            assert!(format!("{}", error).contains("line 2"));
+        }
+        other => panic!("Expected a JSON body error, got {:?}", other),
+    }
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct Quote {
+    #[serde(deserialize_with = "curs::decode::number_or_string")]
+    price: f64,
+    volume: Option<f64>,
+    sides: Vec<String>,
+}
+
+#[test]
+fn decodes_sloppy_json_leniently() {
+    let sloppy = r#"data:application/json,{"price":"12.5","volume":NaN,"sides":["buy",],}"#;
+    let quote: Quote = Request::new(Method::Get, sloppy)
+                           .send()
+                           .decode_success_with(&LenientJson)
+                           .unwrap();
+    assert_eq!(quote,
+               Quote {
+                   price: 12.5,
+                   volume: None,
+                   sides: vec!["buy".to_string()],
+               });
+    assert!(Request::new(Method::Get, sloppy).send().decode_success::<Quote>().is_err());
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct Order {
+    price: f64,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct Orders {
+    orders: Vec<Order>,
+}
+
+#[test]
+fn tells_the_path_to_fields_that_failed_to_decode() {
+    let drifted = concat!(r#"data:application/json,{"orders":"#,
+                          r#"[{"price":1.5},{"price":2},{"price":"cheap"}]}"#);
+    match Request::new(Method::Get, drifted).send().decode_success::<Orders>() {
+        Err(CursError::JsonBody(error)) => {
+            assert_eq!(error.path, "orders[2].price");
+            assert!(format!("{}", error).contains("at orders[2].price"));
+        }
+        other => panic!("Expected a JSON body error, got {:?}", other),
+    }
+}
+
+#[test]
+fn sends_parts_in_base64() {
+    let upload = BytesUpload {
+        name: "attachment".to_string(),
+        filename: "hello.bin".to_string(),
+        mime: Some("application/octet-stream".parse().unwrap()),
+        bytes: b"hello".to_vec(),
+    };
+    let mut request = Request::new(Method::Post, "http://example.com/gateway");
+    request.bytes_uploads(vec![upload]).base64_part("attachment");
+    let canonical = request.to_canonical_string().unwrap();
+    assert!(canonical.contains("Content-Type: application/octet-stream\r\n\
+                                Content-Transfer-Encoding: base64\r\n\r\naGVsbG8=\r\n"));
+
+    request.stream_multipart();
+    let streamed = request.to_canonical_string().unwrap();
+    assert_eq!(streamed, canonical);
+}
+
+#[test]
+fn forces_multipart_without_files() {
+    let mut request = Request::new(Method::Post, "http://example.com/form");
+    request.params(vec![("note", "hi")]);
+    assert!(request.to_canonical_string()
+                   .unwrap()
+                   .contains("content-type: application/x-www-form-urlencoded"));
+
+    request.force_multipart();
+    let canonical = request.to_canonical_string().unwrap();
+    assert!(canonical.contains("content-type: multipart/form-data; \
+                                boundary=curs-canonical-boundary\n"));
+    assert!(canonical.contains("name=\"note\"\r\nhi\r\n"));
+}
+
+#[test]
+fn sends_bodyless_requests_with_a_zero_length() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/empty?ids=7");
+        stub.got_method(Method::Put);
+        stub.got_header("content-length", "0");
+        stub.send_body("ok");
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+    let empty_url = format!("{}/empty", url);
+
+    let prepared = Request::new(Method::Post, &empty_url).prepare().unwrap();
+    assert!(prepared.body.is_none());
+    assert!(prepared.headers.get::<ContentType>().is_none());
+
+    let response = Request::new(Method::Put, &empty_url)
+                       .params(vec![("ids", "7")])
+                       .no_body()
+                       .send()
+                       .unwrap();
+    assert_eq!(response.status, curs::StatusCode::Ok);
+}
+
+#[test]
+fn negotiates_languages() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/localized", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0; 1024];
+        let read = stream.read(&mut request).unwrap();
+        sender.send(String::from_utf8_lossy(&request[..read]).into_owned()).unwrap();
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Language: es-AR, es\r\n\
+                           Content-Length: 4\r\nConnection: close\r\n\r\nhola")
+              .unwrap();
+    });
+
+    let response = Request::new(Method::Get, &url)
+                       .accept_language(&["es-AR", "es", "en;q=0.5"])
+                       .send()
+                       .unwrap();
+    assert!(receiver.recv().unwrap().contains("Accept-Language: es-AR, es;q=0.9, en;q=0.5\r\n"));
+    assert_eq!(response.content_language(), vec!["es-AR".to_string(), "es".to_string()]);
+
+    match Request::new(Method::Get, &url).accept_language(&["en;q=2"]).send() {
+        Err(CursError::InvalidRequest(problems)) => assert!(problems[0].contains("weight")),
+        _ => panic!("Expected an invalid request"),
+    }
+}
+
+#[test]
+fn sends_preconditions_and_merge_patches() {
+    let mut patch = std::collections::BTreeMap::new();
+    patch.insert("foo", Some("patched"));
+    patch.insert("bar", None);
+    let mut request = Request::new(Method::Patch, "http://example.com/items/1");
+    request.merge_patch(&patch).if_unmodified_since("Wed, 21 Oct 2015 07:28:00 GMT");
+    let canonical = request.to_canonical_string().unwrap();
+    assert!(canonical.contains("content-type: application/merge-patch+json\n"));
+    assert!(canonical.contains("if-unmodified-since: Wed, 21 Oct 2015 07:28:00 GMT\n"));
+    assert!(canonical.ends_with("\n\n{\"bar\":null,\"foo\":\"patched\"}"));
+
+    let mut resume = Request::new(Method::Get, "http://example.com/big.iso");
+    resume.range(100, None).if_range("\"v1\"");
+    let canonical = resume.to_canonical_string().unwrap();
+    assert!(canonical.contains("if-range: \"v1\"\n"));
+    assert!(canonical.contains("range: bytes=100-\n"));
+}
+
+#[test]
+fn pools_clients_per_tenant() {
+    let made = Arc::new(Mutex::new(vec![]));
+    let clock = Arc::new(ManualClock::new(time::Duration::from_secs(1_000_000_000)));
+    let log = made.clone();
+    let pool = ClientPool::new(2, move |tenant: &str| {
+                   log.lock().unwrap().push(tenant.to_string());
+                   Ok(Client::new())
+               })
+                   .idle_timeout(time::Duration::from_secs(60))
+                   .with_clock(clock.clone());
+
+    let acme = pool.get("acme").unwrap();
+    assert!(Arc::ptr_eq(&acme, &pool.get("acme").unwrap()));
+    pool.get("globex").unwrap();
+    pool.get("acme").unwrap();
+    pool.get("initech").unwrap();
+    assert_eq!(pool.len(), 2);
+    pool.get("acme").unwrap();
+    pool.get("globex").unwrap();
+    assert_eq!(*made.lock().unwrap(), vec!["acme", "globex", "initech", "globex"]);
+
+    clock.advance(time::Duration::from_secs(61));
+    assert_eq!(pool.len(), 0);
+    pool.evict("acme");
+    pool.get("acme").unwrap();
+    assert_eq!(made.lock().unwrap().len(), 5);
+}
+
+#[test]
+fn blocks_private_addresses() {
+    let server = StubServer::run(|stub| {
+        stub.send_body("internal");
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let mut client = Client::new();
+    assert!(client.get(&url).send().is_ok());
+    client.block_private_addresses();
+    match client.get(&url).send() {
+        Err(CursError::Network(curs::hyper::error::Error::Io(error))) => {
+            assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied)
+        }
+        other => panic!("Expected the connection to be refused, got {:?}", other),
+    }
+
+    let path = env::current_dir().unwrap().join("tests/fixtures/test.png");
+    for url in &[format!("file://{}", path.display()), "data:,secret".to_string()] {
+        match client.get(url).send() {
+            Err(CursError::Network(curs::hyper::error::Error::Io(error))) => {
+                assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied)
+            }
+            other => panic!("Expected {} to be refused, got {:?}", url, other),
+        }
+    }
+
+    let mut client = Client::new();
+    client.resolver(dns::PublicOnly::new(FixedResolver("10.1.2.3".parse().unwrap())));
+    assert!(client.refuses_private_addresses());
+    assert!(client.get("http://intranet.example.com/").send().is_err());
+
+    for private in &["10.0.0.1", "172.16.5.4", "192.168.1.1", "127.0.0.1", "169.254.169.254",
+                     "100.64.0.1", "0.0.0.0", "::1", "fe80::1", "fd00::1", "::ffff:10.0.0.1"] {
+        assert!(!dns::is_public(&private.parse().unwrap()), "{} is private", private);
+    }
+    for public in &["93.184.216.34", "2606:2800:220:1:248:1893:25c8:1946"] {
+        assert!(dns::is_public(&public.parse().unwrap()), "{} is public", public);
+    }
+}
+
+// Answers with a different address every time, like a DNS rebinding attack.
+struct Rebinding(Mutex<u8>);
+
+impl Resolver for Rebinding {
+    fn resolve(&self, _: &str) -> std::io::Result<Vec<std::net::IpAddr>> {
+        let mut lookups = self.0.lock().unwrap();
+        *lookups += 1;
+        Ok(vec![std::net::IpAddr::V4(std::net::Ipv4Addr::new(93, 184, 216, *lookups))])
+    }
+
+    fn pins(&self) -> bool {
+        true
+    }
+}
+
+#[test]
+fn pins_addresses_while_sending() {
+    let resolver = Rebinding(Mutex::new(0));
+    let (first, again) = dns::pinning(|| {
+        (dns::addresses(&resolver, "example.com").unwrap(),
+         dns::addresses(&resolver, "example.com").unwrap())
+    });
+    assert_eq!(first, again);
+    assert_eq!(*resolver.0.lock().unwrap(), 1);
+    assert!(dns::addresses(&resolver, "example.com").unwrap() != first);
+    assert!(dns::addresses(&resolver, "example.com").unwrap() != first);
+}
+
+#[test]
+fn limits_request_bodies() {
+    let mut client = Client::new();
+    client.max_request_bytes(10).stream_bodies_above(4);
+    match client.post("http://127.0.0.1:1/big").override_body("x".repeat(20)).send() {
+        Err(CursError::InvalidInput(why)) => assert!(why.contains("20 bytes")),
+        other => panic!("Expected the body to be refused, got {:?}", other),
+    }
+
+    let upload = |bytes: &[u8]| {
+        BytesUpload {
+            name: "file".to_string(),
+            filename: "file.bin".to_string(),
+            mime: None,
+            bytes: bytes.to_vec(),
+        }
+    };
+    let mut small = client.post("http://127.0.0.1:1/upload");
+    small.bytes_uploads(vec![upload(b"abc")]);
+    assert!(small.prepare().unwrap().body_source.is_none());
+    let mut big = client.post("http://127.0.0.1:1/upload");
+    big.bytes_uploads(vec![upload(b"abcdef")]);
+    assert!(big.prepare().unwrap().body_source.is_some());
+}
+
+#[test]
+fn resends_rewound_bodies_after_a_reset() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/documents/1", listener.local_addr().unwrap());
+    let body = "x".repeat(20000);
+    let expected = body.clone();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        // The first connection goes away halfway through the body.
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut partial = [0; 1024];
+        stream.read(&mut partial).unwrap();
+        drop(stream);
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut received = vec![];
+        let mut chunk = [0; 4096];
+        while !String::from_utf8_lossy(&received).ends_with(&expected) {
+            let read = stream.read(&mut chunk).unwrap();
+            if read == 0 {
+                break;
+            }
+            received.extend_from_slice(&chunk[..read]);
+        }
+        sender.send(String::from_utf8_lossy(&received).into_owned()).unwrap();
+        stream.write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n").unwrap();
+    });
+
+    let mut client = Client::new();
+    client.resend_after_reset();
+    let mut document = std::io::Cursor::new(format!("skip{}", body).into_bytes());
+    document.seek(std::io::SeekFrom::Start(4)).unwrap();
+    let response = client.put(&url).seekable_body(document).unwrap().send().unwrap();
+    assert_eq!(response.status, curs::StatusCode::NoContent);
+    let received = receiver.recv().unwrap();
+    assert!(received.contains("Content-Length: 20000\r\n"));
+    assert!(received.ends_with(&format!("\r\n\r\n{}", body)));
+
+    let reset = CursError::Network(curs::hyper::error::Error::Io(std::io::Error::new(
+        std::io::ErrorKind::ConnectionReset, "reset")));
+    assert!(retry::is_connection_reset(&reset));
+}
+
+#[test]
+fn sniffs_the_charset_of_html_pages() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/menu", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0; 1024];
+        stream.read(&mut request).unwrap();
+        let page = b"<html><head><meta charset=\"ISO-8859-1\"></head><p>Caf\xe9 \x80 3</p></html>";
+        stream.write_all(format!("HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\
+                                  Content-Length: {}\r\nConnection: close\r\n\r\n",
+                                 page.len())
+                             .as_bytes())
+              .unwrap();
+        stream.write_all(page).unwrap();
+    });
+
+    let mut response = Request::new(Method::Get, &url).send_buffered().unwrap();
+    assert!(response.decoded_text().unwrap().contains("<p>Café € 3</p>"));
+    assert!(response.text().unwrap().contains("Caf\u{FFFD}"));
+
+    let mut headers = curs::hyper::header::Headers::new();
+    assert_eq!(charset::text(&headers, b"\xff\xfeo\x00k\x00"), "ok");
+    headers.set_raw("Content-Type", vec![b"text/plain; charset=windows-1252".to_vec()]);
+    assert_eq!(charset::sniff(&headers, b"<meta charset=utf-8>"),
+               Some("windows-1252".to_string()));
+    assert_eq!(charset::text(&headers, b"\x93hi\x94"), "\u{201C}hi\u{201D}");
+}
+
+#[test]
+fn sends_requests_from_frozen_templates() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}/api/", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for _ in 0..2 {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            let mut chunk = [0; 1024];
+            // The body can come apart from the head, its length says when it's over.
+            while !request.contains("\r\n\r\n") ||
+                  request.split("\r\n\r\n").nth(1).unwrap().len() <
+                  request.split("Content-Length: ")
+                         .nth(1)
+                         .and_then(|rest| rest.split("\r\n").next())
+                         .map(|length| length.parse::<usize>().unwrap())
+                         .unwrap_or(0) {
+                let read = stream.read(&mut chunk).unwrap();
+                if read == 0 {
+                    break;
+                }
+                request.push_str(&String::from_utf8_lossy(&chunk[..read]));
+            }
+            sender.send(request).unwrap();
+            stream.write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n").unwrap();
+        }
+    });
+
+    let mut client = Client::new();
+    client.base_url(curs::hyper::Url::parse(&base).unwrap());
+    let mut request = client.put("orders/{id}/lines/{line}");
+    request.raw_header("X-Tenant", "acme").params(vec![("source", "sync")]);
+    let template = request.freeze().unwrap();
+    assert_eq!(template.path_params(), &["id".to_string(), "line".to_string()]);
+
+    let mut first = template.request();
+    first.path_param("id", "7").path_param("line", "a b").query("v", "2");
+    let prepared = first.prepare().unwrap();
+    assert_eq!(prepared.url(), &*format!("{}orders/7/lines/a%20b?v=2", base));
+    assert_eq!(prepared.body(), Some(&b"source=sync"[..]));
+    first.send().unwrap();
+    let sent = receiver.recv().unwrap();
+    assert!(sent.starts_with("PUT /api/orders/7/lines/a%20b?v=2 HTTP/1.1\r\n"));
+    assert!(sent.contains("X-Tenant: acme\r\n"));
+    assert!(sent.ends_with("\r\n\r\nsource=sync"));
+
+    let mut second = template.request();
+    second.path_param("id", "8").path_param("line", "1").json(DummyJson { foo: "bar".into() });
+    second.send().unwrap();
+    let sent = receiver.recv().unwrap();
+    assert!(sent.starts_with("PUT /api/orders/8/lines/1 HTTP/1.1\r\n"));
+    assert!(sent.contains("Content-Type: application/json\r\n"));
+    assert!(sent.ends_with("\r\n\r\n{\"foo\":\"bar\"}"));
+
+    match template.request().path_param("id", "9").prepare() {
+        Err(CursError::InvalidRequest(problems)) => assert!(problems[0].contains("{line}")),
+        other => panic!("Expected a missing path param, got {:?}", other),
+    }
+}
+
+#[test]
+fn signs_every_request_from_a_template() {
+    let server = StubServer::run(|stub| {
+        stub.got_path("/hooks");
+        stub.got_header("x-webhook-signature", ",v1=");
+    });
+    let url = server.url().to_string();
+    let ten_millis = time::Duration::from_millis(10);
+    thread::sleep(ten_millis);
+
+    let signer = WebhookSigner::new(b"whsec");
+    let client = Client::new();
+    let mut request = client.post(&format!("{}/hooks", url));
+    request.json(DummyJson { foo: "event".to_string() }).sign_webhook(&signer);
+    let template = request.freeze().unwrap();
+    template.request().send().unwrap();
+    // Timestamps are in seconds.
+    thread::sleep(time::Duration::from_millis(1100));
+    template.request().send().unwrap();
+
+    server.assert_satisfied();
+    let timestamps: Vec<String> = server.requests()
+                                        .iter()
+                                        .map(|r| {
+                                            let signature = r.header("x-webhook-signature");
+                                            signature.unwrap().split(',').next().unwrap().into()
+                                        })
+                                        .collect();
+    assert!(timestamps[0] != timestamps[1]);
+}